@@ -4,6 +4,8 @@ extern crate kernel;
 
 use test::Bencher;
 use kernel::streams::interpreter::*;
+use kernel::streams::vm;
+use kernel::commands::ast::{AST, Atom};
 use kernel::streams::stack::Stack;
 use kernel::handle;
 use kernel::reactors::task::Context;
@@ -21,8 +23,8 @@ fn parse1(b: &mut Bencher) {
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
     let eval = &"1*2+3".to_string();
     b.iter(|| {
-        h.borrow_mut().parse(eval);
-        h.borrow_mut().gc();
+        h.borrow_mut().parse(eval).unwrap();
+        h.borrow_mut().gc(&[]);
     })
 }
 
@@ -32,11 +34,28 @@ fn parse2(b: &mut Bencher) {
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
     let eval = &"+/{x*y}[(a;b;c;d;e);(2;6;2;1;3)]".to_string();
     b.iter(|| {
-        h.borrow_mut().parse(eval);
-        h.borrow_mut().gc();
+        h.borrow_mut().parse(eval).unwrap();
+        h.borrow_mut().gc(&[]);
     })
 }
 
+#[bench]
+fn compile1(b: &mut Bencher) {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    let f = h.borrow_mut().parse(&"{[x;y]$[0=x;1+y;$[0=y;f[x-1;1];f[x-1;f[x;y-1]]]]}".to_string()).unwrap();
+    let (names, body) = match f {
+        &AST::Vector(ref v) => {
+            match v[0] {
+                AST::Atom(Atom::Lambda(_, names, body)) => (names, body),
+                _ => panic!(),
+            }
+        }
+        _ => panic!(),
+    };
+    b.iter(|| vm::compile(names, body))
+}
+
 // #[bench]
 // #fn k_plus(b: &mut Bencher) {
 // #b.iter(|| ast::eval(AST::Verb(Verb::Plus, AST::Number(2).boxed(), AST::Number(3).boxed())));
@@ -49,8 +68,8 @@ fn parse4(b: &mut Bencher) {
     let eval = &"();[];{};(());[[]];{{}};()();1 2 3;(1 2 3);[1 2 3];[a[b[c[d]]]];(a(b(c(d))));{a{b{c{d}}}};"
         .to_string();
     b.iter(|| {
-        h.borrow_mut().parse(eval);
-        h.borrow_mut().gc();
+        h.borrow_mut().parse(eval).unwrap();
+        h.borrow_mut().gc(&[]);
     })
 }
 
@@ -77,12 +96,12 @@ fn fac_rec<'a>(b: &'a mut Bencher) {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
     let eval = &"fac:{$[x=1;1;x*fac[x-1]]}".to_string();
-    let code = h.borrow_mut().parse(eval);
+    let code = h.borrow_mut().parse(eval).unwrap();
     h.borrow_mut().run(code, Context::Nil, None).unwrap();
-    let f = h.borrow_mut().parse(&"fac[5]".to_string());
+    let f = h.borrow_mut().parse(&"fac[5]".to_string()).unwrap();
     b.iter(|| {
         let _ = h.borrow_mut().run(f, Context::Nil, None);
-        h.borrow_mut().gc();
+        h.borrow_mut().gc(&[f]);
     })
 }
 
@@ -91,12 +110,12 @@ fn fac_tail<'a>(b: &'a mut Bencher) {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
     let eval = &"fac:{[a;b]$[a=1;b;fac[a-1;a*b]]}".to_string();
-    let code = h.borrow_mut().parse(eval);
+    let code = h.borrow_mut().parse(eval).unwrap();
     h.borrow_mut().run(code, Context::Nil, None).unwrap();
-    let f = h.borrow_mut().parse(&"fac[4;5]".to_string());
+    let f = h.borrow_mut().parse(&"fac[4;5]".to_string()).unwrap();
     b.iter(|| {
         let _ = h.borrow_mut().run(f, Context::Nil, None);
-        h.borrow_mut().gc();
+        h.borrow_mut().gc(&[f]);
     })
 }
 
@@ -104,10 +123,10 @@ fn fac_tail<'a>(b: &'a mut Bencher) {
 fn fac_mul<'a>(b: &'a mut Bencher) {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
-    let f = h.borrow_mut().parse(&"2*3*4*5".to_string());
+    let f = h.borrow_mut().parse(&"2*3*4*5".to_string()).unwrap();
     b.iter(|| {
         let _ = h.borrow_mut().run(f, Context::Nil, None);
-        h.borrow_mut().gc();
+        h.borrow_mut().gc(&[f]);
     })
 }
 
@@ -116,12 +135,25 @@ fn akkerman_k<'a>(b: &'a mut Bencher) {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
     h.borrow_mut().define_primitives();
-    let akk = h.borrow_mut().parse(&"f:{[x;y]$[0=x;1+y;$[0=y;f[x-1;1];f[x-1;f[x;y-1]]]]}".to_string());
+    let akk = h.borrow_mut().parse(&"f:{[x;y]$[0=x;1+y;$[0=y;f[x-1;1];f[x-1;f[x;y-1]]]]}".to_string()).unwrap();
     h.borrow_mut().run(akk, Context::Nil, None).unwrap();
-    let call = h.borrow_mut().parse(&"f[3;4]".to_string());
+    let call = h.borrow_mut().parse(&"f[3;4]".to_string()).unwrap();
+    b.iter(|| {
+        let _ = h.borrow_mut().run(call, Context::Nil, None);
+        h.borrow_mut().gc(&[call]);
+    })
+}
+
+#[bench]
+fn sum_k<'a>(b: &'a mut Bencher) {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    let sum = h.borrow_mut().parse(&"sum:{[n;a]$[n=0;a;sum[n-1;a+n]]}".to_string()).unwrap();
+    h.borrow_mut().run(sum, Context::Nil, None).unwrap();
+    let call = h.borrow_mut().parse(&"sum[1000;0]".to_string()).unwrap();
     b.iter(|| {
         let _ = h.borrow_mut().run(call, Context::Nil, None);
-        h.borrow_mut().gc();
+        h.borrow_mut().gc(&[call]);
     })
 }
 
@@ -55,5 +55,11 @@ fn main() {
             None
         }
     };
-    park(star(4)).run0(input);
+    let mut bsp = park(star(4));
+    if let Ok(Some(port)) = args::Parser::new().get("-p", true) {
+        // q clients: hopen `:host:port
+        let addr = format!("0.0.0.0:{}", port).parse().expect(&format!("Bad port {:?}.", port));
+        println!("IPC {:?}", bsp.listen(&addr));
+    }
+    bsp.run0(input);
 }
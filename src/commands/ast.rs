@@ -5,21 +5,24 @@ use std::fmt;
 use std::iter;
 use std::result::Result;
 use std::collections::HashMap;
-use commands::command;
+use commands::{command, symbol};
+use commands::temporal::{self, Temporal};
 use streams::otree;
+use tables::{Table, Dict};
 use reactors::task::Context;
 use streams::interpreter::*;
 use std::cell::UnsafeCell;
-use std::isize;
+use std::{i64, f64, isize};
 use std::intrinsics::size_of;
 use std::rc::Rc;
 use core::ops::Deref;
 use core::slice::Iter;
 use std::mem;
+use lalrpop_util::ParseError;
 
 #[derive(Debug)]
 pub enum Error {
-    ParseError,
+    ParseError { desc: String, pos: usize },
     EvalError { desc: String, ast: String },
     InternalError,
 }
@@ -27,13 +30,24 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::ParseError => write!(f, "Parse error!\n"),
+            Error::ParseError { ref desc, pos } => write!(f, "Parse error: {} at {}.\n", desc, pos),
             Error::EvalError { ref desc, ref ast } => write!(f, "Eval error: {}.\nCaused here: {}\n", desc, ast),
             Error::InternalError => write!(f, "Internal error!\n"),
         }
     }
 }
 
+impl Error {
+    pub fn message(&self) -> String {
+        // the text a handler or the prompt sees, as with q's 'type
+        match *self {
+            Error::ParseError { ref desc, .. } => desc.clone(),
+            Error::EvalError { ref desc, .. } => desc.clone(),
+            Error::InternalError => "internal".to_string(),
+        }
+    }
+}
+
 // OK LANG
 
 //        a          l           a-a         l-a         a-l         l-l         triad    tetrad
@@ -90,7 +104,7 @@ pub enum Verb {
     New = 25,
 }
 
-#[derive(Debug)]
+#[derive(PartialEq,Debug,Clone, Copy)]
 pub enum Monadic {
     Flip = 0,
     Negate = 1,
@@ -139,7 +153,40 @@ impl Verb {
             "@" => Ok(Verb::At),
             "." => Ok(Verb::Dot),
             ";" => Ok(Verb::New),
-            _ => Err(Error::ParseError),
+            _ => {
+                Err(Error::ParseError {
+                    desc: format!("unknown {}", s),
+                    pos: 0,
+                })
+            }
+        }
+    }
+}
+
+impl Monadic {
+    pub fn from_verb(v: Verb) -> Option<Self> {
+        // see the "a"/"l" columns of the verb table above
+        match v {
+            Verb::Plus => Some(Monadic::Flip),
+            Verb::Minus => Some(Monadic::Negate),
+            Verb::Times => Some(Monadic::First),
+            Verb::Divide => Some(Monadic::Sqrt),
+            Verb::Mod => Some(Monadic::Iota),
+            Verb::Min => Some(Monadic::Where),
+            Verb::Max => Some(Monadic::Rev),
+            Verb::Lt => Some(Monadic::Asc),
+            Verb::Gt => Some(Monadic::Desc),
+            Verb::Eq => Some(Monadic::Group),
+            Verb::Match => Some(Monadic::Not),
+            Verb::Concat => Some(Monadic::List),
+            Verb::Except => Some(Monadic::Nil),
+            Verb::Take => Some(Monadic::Count),
+            Verb::Drop => Some(Monadic::Floor),
+            Verb::Cast => Some(Monadic::Fmt),
+            Verb::Find => Some(Monadic::Unique),
+            Verb::At => Some(Monadic::Type),
+            Verb::Dot => Some(Monadic::Eval),
+            _ => None,
         }
     }
 }
@@ -159,6 +206,16 @@ impl fmt::Display for Verb {
             Verb::Cast => write!(f, "$"),
             Verb::Times => write!(f, "*"),
             Verb::Divide => write!(f, "%"),
+            Verb::Mod => write!(f, "!"),
+            Verb::Min => write!(f, "&"),
+            Verb::Max => write!(f, "|"),
+            Verb::Match => write!(f, "~"),
+            Verb::Concat => write!(f, ","),
+            Verb::Except => write!(f, "^"),
+            Verb::Take => write!(f, "#"),
+            Verb::Drop => write!(f, "_"),
+            Verb::Find => write!(f, "?"),
+            Verb::At => write!(f, "@"),
             _ => write!(f, "{:?}", self),
         }
     }
@@ -192,7 +249,12 @@ impl Adverb {
             "::" => Ok(Adverb::View),
             "\\:" => Ok(Adverb::EachLeft),
             "/:" => Ok(Adverb::EachRight),
-            _ => Err(Error::ParseError),
+            _ => {
+                Err(Error::ParseError {
+                    desc: format!("unknown {}", s),
+                    pos: 0,
+                })
+            }
         }
     }
 }
@@ -201,11 +263,35 @@ impl fmt::Display for Adverb {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Adverb::Over => write!(f, "/"),
+            Adverb::Scan => write!(f, "\\"),
+            Adverb::Each => write!(f, "'"),
+            Adverb::EachPrio => write!(f, "':"),
+            Adverb::EachLeft => write!(f, "\\:"),
+            Adverb::EachRight => write!(f, "/:"),
             _ => write!(f, "{:?}", self),
         }
     }
 }
 
+#[derive(PartialEq,Debug,Clone, Copy)]
+pub enum Sql {
+    Select,
+    Exec,
+    Update,
+    Delete,
+}
+
+impl fmt::Display for Sql {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Sql::Select => write!(f, "select"),
+            Sql::Exec => write!(f, "exec"),
+            Sql::Update => write!(f, "update"),
+            Sql::Delete => write!(f, "delete"),
+        }
+    }
+}
+
 #[derive(PartialEq,Debug,Clone)]
 pub enum Atom<'a> {
     Nil,
@@ -219,6 +305,7 @@ pub enum Atom<'a> {
     Verb(Verb, &'a AST<'a>, &'a AST<'a>),
     Adverb(Adverb, &'a AST<'a>, &'a AST<'a>),
     Table(&'a AST<'a>, &'a AST<'a>),
+    Sql(Sql, &'a AST<'a>, &'a AST<'a>, &'a AST<'a>, &'a AST<'a>),
     Ioverb(String),
     Yield(Context<'a>),
     Value(Value),
@@ -229,14 +316,27 @@ pub enum Atom<'a> {
 pub enum Value {
     Nil,
     SymbolInt(u16),
-    SequenceInt(u16),
+    Sequence(String),
+    Char(char),
     Number(i64),
     Float(f64),
     VecInt(Vec<i64>),
     VecFloat(Vec<f64>),
+    VecSym(Vec<u16>),
+    Temporal(Temporal, i64),
+    VecTemporal(Temporal, Vec<i64>),
+    List(Vec<Value>),
+    Table(Table),
+    Dict(Box<Dict>),
     Ioverb(String),
 }
 
+impl Default for Value {
+    fn default() -> Value {
+        Value::Nil
+    }
+}
+
 #[derive(PartialEq,Debug,Clone)]
 pub enum AST<'a> {
     Atom(Atom<'a>),
@@ -248,11 +348,11 @@ pub type ASTIter<'a> = Iter<'a, AST<'a>>;
 #[derive(Debug)]
 pub struct Arena<'a> {
     pub names: UnsafeCell<HashMap<String, u16>>,
-    pub symbols: UnsafeCell<HashMap<String, u16>>,
-    pub sequences: UnsafeCell<HashMap<String, u16>>,
     pub builtins: u16,
     pub asts: UnsafeCell<Vec<AST<'a>>>,
     pub conts: UnsafeCell<Vec<Cont<'a>>>,
+    pub spans: UnsafeCell<HashMap<(usize, usize), usize>>,
+    pub free: UnsafeCell<Vec<usize>>,
 }
 
 fn delta<'a>(this: &Cont<'a>, next: &Cont<'a>) -> usize {
@@ -261,6 +361,17 @@ fn delta<'a>(this: &Cont<'a>, next: &Cont<'a>) -> usize {
     unsafe { (this_ptr - next_ptr) / size_of::<Cont<'a>>() }
 }
 
+fn operands<'a>(n: &AST<'a>) -> Option<(usize, usize)> {
+    let p = |x: &AST<'a>| x as *const AST<'a> as usize;
+    match *n {
+        AST::Atom(Atom::Verb(_, l, r)) |
+        AST::Atom(Atom::Adverb(_, l, r)) |
+        AST::Atom(Atom::Assign(l, r)) |
+        AST::Atom(Atom::Call(l, r)) => Some((p(l), p(r))),
+        _ => None,
+    }
+}
+
 fn is_int(x: &AST) -> bool {
     match x {
         &AST::Atom(Atom::Value(Value::Number(_))) => true,
@@ -333,7 +444,7 @@ pub fn postprocess<'a, 'b>(n: &'b AST<'a>, skip_depth: i64, arena: &'a Arena<'a>
 
     match n {
         &AST::Atom(ref x) => {
-            AST::Atom(match x {
+            let r = AST::Atom(match x {
                 &Atom::List(l) => Atom::List(postprocess_ast(l, skip_depth, arena)),
                 &Atom::Dict(d) => Atom::Dict(postprocess_ast(d, skip_depth, arena)), 
                 &Atom::Assign(a, b) => {
@@ -360,16 +471,29 @@ pub fn postprocess<'a, 'b>(n: &'b AST<'a>, skip_depth: i64, arena: &'a Arena<'a>
                               postprocess_ast(b, skip_depth, arena))
                 }
                 &Atom::Adverb(adv, a, b) => {
+                    let depth = match b {
+                        &AST::Atom(Atom::Dict(_)) => 3, // f/[x;y] args, as in Call
+                        _ => skip_depth,
+                    };
                     Atom::Adverb(adv,
                                 postprocess_ast(a, skip_depth, arena),
-                                postprocess_ast(b, skip_depth, arena))
+                                postprocess_ast(b, depth, arena))
                 }
                 &Atom::Table(a, b) => {
                     Atom::Table(postprocess_ast(a, skip_depth, arena),
                                postprocess_ast(b, skip_depth, arena))
                 }
+                &Atom::Sql(q, c, b, t, w) => {
+                    Atom::Sql(q,
+                             postprocess_ast(c, 2 /* Sql->VecAST nodes */, arena),
+                             postprocess_ast(b, 2, arena),
+                             postprocess_ast(t, skip_depth, arena),
+                             postprocess_ast(w, 2, arena))
+                }
                 x => x.clone(),  // nothing to postprocess
-            })
+            });
+            arena.respan(n, &r);
+            r
         }
         &AST::Vector(ref x) => {
             if (skip_depth <= 0) && is_monovec(x) {
@@ -382,12 +506,77 @@ pub fn postprocess<'a, 'b>(n: &'b AST<'a>, skip_depth: i64, arena: &'a Arena<'a>
     }
 }
 
-pub fn parse<'a>(arena: &'a Arena<'a>, s: &String) -> &'a AST<'a> {
-    let ast = command::parse_Mex(arena, s).unwrap();
+fn parse_error<T>(s: &str, e: ParseError<usize, T, (usize, &'static str)>) -> Error {
+    // regex terminals are too long to be worth listing, name only the punctuation
+    let expect = |x: Vec<String>| {
+        let x = x.into_iter().filter(|t| !t.starts_with("r#")).collect::<Vec<String>>();
+        if x.len() == 0 {
+            String::new()
+        } else {
+            format!(", expected {}", x.join(" "))
+        }
+    };
+    let (desc, pos) = match e {
+        ParseError::InvalidToken { location } => {
+            (format!("invalid token {:?}", &s[location..].chars().next().unwrap_or(' ')), location)
+        }
+        ParseError::UnrecognizedToken { token: None, expected } => (format!("unexpected end{}", expect(expected)), s.len()),
+        ParseError::UnrecognizedToken { token: Some((lo, _, hi)), expected } => {
+            (format!("unexpected {:?}{}", &s[lo..hi], expect(expected)), lo)
+        }
+        ParseError::ExtraToken { token: (lo, _, hi) } => (format!("extra {:?}", &s[lo..hi]), lo),
+        ParseError::User { error: (pos, desc) } => (desc.to_string(), pos),
+    };
+    Error::ParseError {
+        desc: desc,
+        pos: pos,
+    }
+}
+
+// The token regexes can't see what came before, so a minus that is the sign
+// of a literal is swapped for this byte first: one right before a digit that
+// doesn't follow a noun. -2+3 and x:-1 are literals, x-1 and -(1 2) are minus.
+pub const SIGN: char = '\x01';
+
+fn signs(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    let (mut quoted, mut escaped) = (false, false);
+    // the last thing outside blanks, and the word it ends if any
+    let (mut prev, mut word) = (' ', String::new());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            quoted = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+            r.push(c);
+            prev = c;
+            continue;
+        }
+        let keyword = ["select", "exec", "update", "delete", "by", "from", "where"].contains(&&word[..]);
+        let noun = (prev.is_alphanumeric() && !keyword) || ")]}\"`".contains(prev);
+        let digit = chars.peek().map_or(false, |d| d.is_digit(10));
+        r.push(if c == '-' && digit && !noun { SIGN } else { c });
+        quoted = c == '"';
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else if !c.is_whitespace() {
+            word.clear();
+        }
+        if !c.is_whitespace() {
+            prev = c;
+        } else {
+            word.clear();
+        }
+    }
+    r
+}
+
+pub fn parse<'a>(arena: &'a Arena<'a>, s: &String) -> Result<&'a AST<'a>, Error> {
+    let ast = try!(command::parse_Mex(arena, &signs(s)).map_err(|e| parse_error(s, e)));
     // println!("parse {:?}", ast);
-    let p_ast = postprocess(&ast, 0, arena);
+    let p_ast = postprocess(&ast, 1 /* top level is a sequence of expressions */, arena);
     // println!("post parse {:?}", p_ast);
-    arena.ast(p_ast)
+    Ok(arena.ast(p_ast))
 }
 
 struct viter<'a>(&'a ASTIter<'a>);
@@ -400,7 +589,8 @@ impl<'a> fmt::Display for viter<'a> {
 impl<'a> fmt::Display for Cont<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &Cont::Call(callee, cont) => write!(f, "call: {} next: {}", callee, delta(self, cont)),
+            &Cont::Call(callee, _, cont) => write!(f, "call: {} next: {}", callee, delta(self, cont)),
+            &Cont::Callee(args, _, cont) => write!(f, "callee: {} next: {}", args, delta(self, cont)),
             &Cont::Func(names, args, body, cont) => write!(f, "func: {} {} next: {}", names, args, delta(self, cont)),
             &Cont::Cond(if_expr, else_expr, cont) => {
                 write!(f,
@@ -426,7 +616,15 @@ impl<'a> fmt::Display for Cont<'a> {
                        delta(self, cont))
             }
             &Cont::Adverb(adv, _, cont) => write!(f, "adverb: {} next: {}", adv, delta(self, cont)),
-            &Cont::Verb(ref verb, right, swap, cont) => {
+            &Cont::Table(ref names, _, cont) => write!(f, "table: {:?} next: {}", names, delta(self, cont)),
+            &Cont::Sql(q, _, cont) => write!(f, "sql: {} next: {}", q, delta(self, cont)),
+            &Cont::Iter(adv, _, fun, _, _, ref acc, cont) => {
+                write!(f, "iter: {}{} {} next: {}", fun, adv, acc, delta(self, cont))
+            }
+            &Cont::Step(adv, _, fun, _, _, ref acc, cont) => {
+                write!(f, "step: {}{} {} next: {}", fun, adv, acc, delta(self, cont))
+            }
+            &Cont::Verb(ref verb, right, swap, _, cont) => {
                 write!(f, "verb: {} {} next: {}", verb, right, delta(self, cont))
             }
             &Cont::Expressions(ast, Some(ref rest), cont) => {
@@ -440,6 +638,11 @@ impl<'a> fmt::Display for Cont<'a> {
             &Cont::Return => write!(f, "return"),
             &Cont::Intercore(ref msg, cont) => write!(f, "intercore: {:?} {}", msg.clone(), delta(self, cont)),
             &Cont::Yield(cont) => write!(f, "yield: {}", delta(self, cont)),
+            &Cont::Trap(verb, cont) => write!(f, "trap: {} next: {}", verb, delta(self, cont)),
+            &Cont::Protect(handler, _, cont) => write!(f, "protect: {} next: {}", handler, delta(self, cont)),
+            &Cont::Signal(cont) => write!(f, "signal next: {}", delta(self, cont)),
+            &Cont::Compiled(fun, _, cont) => write!(f, "compiled: {} next: {}", fun, delta(self, cont)),
+            &Cont::Leave(node, cont) => write!(f, "leave: {:?} next: {}", node, delta(self, cont)),
         }
     }
 }
@@ -459,9 +662,9 @@ impl<'a> Arena<'a> {
         Arena {
             asts: asts,
             names: UnsafeCell::new(HashMap::new()),
-            symbols: UnsafeCell::new(HashMap::new()),
-            sequences: UnsafeCell::new(HashMap::new()),
             conts: UnsafeCell::new(Vec::with_capacity(2048 * 2048)),
+            spans: UnsafeCell::new(HashMap::new()),
+            free: UnsafeCell::new(Vec::new()),
             builtins: builtins,
         }
     }
@@ -495,15 +698,49 @@ impl<'a> Arena<'a> {
 
     #[inline(always)]
     pub fn ast(&self, n: AST<'a>) -> &'a AST<'a> {
+        // slots freed by the collector first, see streams::gc
         let ast = unsafe { &mut *self.asts.get() };
-        ast.push(n);
-        ast.last().unwrap()
+        match unsafe { (*self.free.get()).pop() } {
+            Some(i) => {
+                ast[i] = n;
+                &ast[i]
+            }
+            None => {
+                ast.push(n);
+                ast.last().unwrap()
+            }
+        }
     }
 
     pub fn vec(&self, v: Vec<AST<'a>>) -> &'a AST<'a> {
         self.ast(AST::Vector(v))
     }
 
+    pub fn at(&self, pos: usize, n: AST<'a>) -> AST<'a> {
+        // input offset of an application; its operands are allocated nodes,
+        // so their addresses identify it wherever the node itself is copied
+        if let Some(k) = operands(&n) {
+            let spans = unsafe { &mut *self.spans.get() };
+            spans.insert(k, pos);
+        }
+        n
+    }
+
+    pub fn span(&self, n: &AST<'a>) -> Option<usize> {
+        let spans = unsafe { &*self.spans.get() };
+        operands(n).and_then(|k| spans.get(&k).cloned())
+    }
+
+    fn respan(&self, from: &AST<'a>, to: &AST<'a>) {
+        // postprocess rebuilds every node, carry the position over
+        let spans = unsafe { &mut *self.spans.get() };
+        if let (Some(a), Some(b)) = (operands(from), operands(to)) {
+            if let Some(pos) = spans.remove(&a) {
+                spans.insert(b, pos);
+            }
+        }
+    }
+
     #[inline(always)]
     pub fn cont(&self, n: Cont<'a>) -> &'a Cont<'a> {
         let conts = unsafe { &mut *self.conts.get() };
@@ -527,32 +764,30 @@ impl<'a> Arena<'a> {
         self.ast(self.intern(s))
     }
 
-    pub fn intern_symbol(&self, s: String) -> AST<'a> {
-        let symbols = unsafe { &mut *self.symbols.get() };
-
-        AST::Atom(if symbols.contains_key(&s) {
-            Atom::Value(Value::SymbolInt(symbols[&s]))
-        } else {
-            let id = symbols.len() as u16;
-            symbols.insert(s, id);
-            Atom::Value(Value::SymbolInt(id))
-        })
+    pub fn intern_symbol(&self, s: String) -> Result<AST<'a>, Error> {
+        match symbol::intern(&s) {
+            Ok(x) => Ok(AST::Atom(Atom::Value(Value::SymbolInt(x)))),
+            Err(e) => {
+                Err(Error::EvalError {
+                    desc: e.to_string(),
+                    ast: s,
+                })
+            }
+        }
     }
 
-    pub fn intern_symbol_ast(&self, s: String) -> &'a AST<'a> {
-        self.ast(self.intern_symbol(s))
+    pub fn name(&self, id: u16) -> String {
+        // reverse lookup of an interned name
+        let names = unsafe { &*self.names.get() };
+        names.iter().find(|&(_, v)| *v == id).map(|(k, _)| k.clone()).unwrap_or(String::new())
     }
 
-    pub fn intern_sequence(&self, s: String) -> AST<'a> {
-        let sequences = unsafe { &mut *self.sequences.get() };
+    pub fn symbol(&self, id: u16) -> String {
+        symbol::name(id)
+    }
 
-        AST::Atom(if sequences.contains_key(&s) {
-            Atom::Value(Value::SequenceInt(sequences[&s]))
-        } else {
-            let id = sequences.len() as u16;
-            sequences.insert(s, id);
-            Atom::Value(Value::SequenceInt(id))
-        })
+    pub fn intern_symbol_ast(&self, s: String) -> Result<&'a AST<'a>, Error> {
+        self.intern_symbol(s).map(|x| self.ast(x))
     }
 
     pub fn to_string(&self) {
@@ -577,6 +812,8 @@ impl<'a> Arena<'a> {
         unsafe {
             asts.set_len(self.builtins as usize);
             conts.set_len(0);
+            (*self.spans.get()).clear();
+            (*self.free.get()).clear();
         };
         l
     }
@@ -698,18 +935,40 @@ impl<'a> fmt::Display for AST<'a> {
             AST::Atom(Atom::Adverb(ref v, ref a, ref b)) => write!(f, "{}{}{}", a, v, b),
             AST::Atom(Atom::Assign(ref a, ref b)) => write!(f, "{}:{}", a, b),
             AST::Atom(Atom::Cond(ref c, ref a, ref b)) => write!(f, "$[{};{};{}]", c, a, b),
+            AST::Atom(Atom::Sql(q, c, b, t, w)) => write!(f, "{} {} by {} from {} where {}", q, c, b, t, w),
             AST::Atom(Atom::Yield(ref c)) => write!(f, "Yield {:?}", c),
             AST::Atom(Atom::NameInt(ref n)) => write!(f, "^{}", n),
             AST::Atom(Atom::Value(ref v)) => {
                 match v {
                     &Value::Nil => write!(f, "Nil"),
                     &Value::Number(n) => write!(f, "{}", n),
-                    &Value::SymbolInt(ref s) => write!(f, "{}", s),
-                    &Value::SequenceInt(ref s) => write!(f, "{:?}s", s),
+                    &Value::Float(n) => write!(f, "{}", n),
+                    &Value::SymbolInt(s) => write!(f, "`{}", symbol::name(s)),
+                    &Value::Sequence(ref s) => write!(f, "{:?}", s),
+                    &Value::Char(c) => write!(f, "{:?}", c.to_string()),
                     &Value::VecInt(ref v) => write!(f, "#i[{}]", vi64(v)),
                     &Value::VecFloat(ref v) => write!(f, "#f[{}]", vf64(v)),
+                    &Value::VecSym(ref v) => {
+                        if v.len() == 0 {
+                            write!(f, "0#`")
+                        } else {
+                            write!(f, "{}", v.iter().map(|x| format!("`{}", symbol::name(*x))).collect::<String>())
+                        }
+                    }
+                    &Value::Temporal(k, x) => write!(f, "{}", temporal::format(k, x)),
+                    &Value::VecTemporal(k, ref v) => {
+                        if v.len() == 0 {
+                            write!(f, "0#{}", k.null())
+                        } else {
+                            write!(f, "{}", v.iter().map(|x| temporal::format(k, *x)).collect::<Vec<String>>().join(" "))
+                        }
+                    }
+                    &Value::List(ref v) => {
+                        write!(f, "#a[{}]", v.iter().map(|x| format!("{}", AST::Atom(Atom::Value(x.clone())))).collect::<Vec<String>>().join(";"))
+                    }
+                    &Value::Table(ref t) => write!(f, "{}", t),
+                    &Value::Dict(ref d) => write!(f, "{}", d),
                     &Value::Ioverb(ref v) => write!(f, "{}", v),
-                    _ => write!(f, "Not implemented yet."),
                 }
             }
             AST::Vector(ref v) => write!(f, "#a[{}]", vast(v)),
@@ -729,13 +988,67 @@ pub fn call<'a>(l: &'a AST<'a>, r: &'a AST<'a>, arena: &'a Arena<'a>) -> AST<'a>
     AST::Atom(Atom::Call(l, r))
 }
 
+pub fn infix<'a>(f: &str, l: AST<'a>, r: AST<'a>, arena: &'a Arena<'a>) -> AST<'a> {
+    // x f y is f[x;y]
+    let f = arena.intern(f.to_string());
+    AST::Atom(Atom::Call(arena.ast(f), arena.ast(AST::Atom(Atom::Dict(arena.ast(AST::Vector(vec![l, r])))))))
+}
+
+pub fn mentions<'a>(a: &AST<'a>, name: u16) -> bool {
+    match *a {
+        AST::Vector(ref v) => v.iter().any(|x| mentions(x, name)),
+        AST::Atom(Atom::NameInt(n)) => n == name,
+        AST::Atom(Atom::List(x)) |
+        AST::Atom(Atom::Dict(x)) => mentions(x, name),
+        AST::Atom(Atom::Call(x, y)) |
+        AST::Atom(Atom::Assign(x, y)) |
+        AST::Atom(Atom::Verb(_, x, y)) |
+        AST::Atom(Atom::Adverb(_, x, y)) |
+        AST::Atom(Atom::Table(x, y)) => mentions(x, name) || mentions(y, name),
+        AST::Atom(Atom::Cond(x, y, z)) => mentions(x, name) || mentions(y, name) || mentions(z, name),
+        AST::Atom(Atom::Sql(_, c, b, t, w)) => [c, b, t, w].iter().any(|x| mentions(x, name)),
+        _ => false, // nested lambdas bind their own x y z
+    }
+}
+
+fn implicit_args<'a>(body: &'a AST<'a>, arena: &'a Arena<'a>) -> &'a AST<'a> {
+    // {x+y} takes [x;y] and {z} takes [x;y;z]
+    let arity = {
+        let names = unsafe { &*arena.names.get() };
+        let uses = |s: &str| names.get(s).map_or(false, |&n| mentions(body, n));
+        if uses("z") {
+            3
+        } else if uses("y") {
+            2
+        } else {
+            1
+        }
+    };
+    match arity {
+        1 => arena.intern_ast("x".to_string()),
+        n => arena.vec(["x", "y", "z"][..n].iter().map(|s| arena.intern(s.to_string())).collect()),
+    }
+}
+
 pub fn fun<'a>(l: &'a AST<'a>, r: &'a AST<'a>, arena: &'a Arena<'a>) -> AST<'a> {
     AST::Atom(match *l {
-        AST::Atom(Atom::Value(Value::Nil)) => Atom::Lambda(None, arena.intern_ast("x".to_string()), r),
+        AST::Atom(Atom::Value(Value::Nil)) => Atom::Lambda(None, implicit_args(r, arena), r),
         _ => Atom::Lambda(None, l, r),
     })
 }
 
+pub fn query<'a>(q: Sql,
+                 c: Option<AST<'a>>,
+                 b: Option<AST<'a>>,
+                 t: AST<'a>,
+                 w: Option<AST<'a>>,
+                 arena: &'a Arena<'a>)
+                 -> AST<'a> {
+    // missing clauses are empty column lists
+    let clause = |x: Option<AST<'a>>| arena.ast(x.unwrap_or(AST::Vector(vec![])));
+    AST::Atom(Atom::Sql(q, clause(c), clause(b), arena.ast(t), clause(w)))
+}
+
 pub fn table<'a>(l: &'a AST<'a>, r: &'a AST<'a>, arena: &'a Arena<'a>) -> AST<'a> {
     AST::Atom(Atom::Table(l, r))
 }
@@ -745,31 +1058,166 @@ pub fn dict<'a>(l: &'a AST<'a>, arena: &'a Arena<'a>) -> AST<'a> {
 }
 
 pub fn list<'a>(l: &'a AST<'a>, arena: &'a Arena<'a>) -> AST<'a> {
-    AST::Atom(Atom::List(l))
+    match *l {
+        // (e) is just grouping, lists need at least one ;
+        AST::Vector(ref v) if v.len() == 1 => v[0].clone(),
+        _ => AST::Atom(Atom::List(l)),
+    }
+}
+
+fn literal(s: &str) -> Result<Value, &'static str> {
+    let neg = s.starts_with('-') || s.starts_with(SIGN);
+    let x = if neg { &s[1..] } else { s };
+    let v = match x {
+        "0N" => Value::Number(i64::MIN),
+        "0W" => Value::Number(i64::MAX),
+        "0n" => Value::Float(f64::NAN),
+        "0w" => Value::Float(f64::INFINITY),
+        _ if x.ends_with('f') => Value::Float(try!(x[..x.len() - 1].parse::<f64>().map_err(|_| "invalid float"))),
+        _ if x.contains(|c: char| c == '.' || c == 'e' || c == 'E') => {
+            Value::Float(try!(x.parse::<f64>().map_err(|_| "invalid float")))
+        }
+        _ => Value::Number(try!(x.parse::<i64>().map_err(|_| "integer overflow"))),
+    };
+    Ok(if neg { negate(&v).unwrap() } else { v })
+}
+
+pub fn integer<'a>(s: &str, radix: u32) -> Result<AST<'a>, &'static str> {
+    // decimal, 0x and b literals; the token regexes let through what doesn't fit
+    match i64::from_str_radix(s, radix) {
+        Ok(x) => Ok(AST::Atom(Atom::Value(Value::Number(x)))),
+        Err(_) if s.chars().all(|c| c.is_digit(radix)) => Err("integer overflow"),
+        Err(_) => Err("invalid digit"),
+    }
+}
+
+fn negate(v: &Value) -> Option<Value> {
+    // 0N stays null
+    match *v {
+        Value::Number(x) => Some(Value::Number(if x == i64::MIN { x } else { -x })),
+        Value::Float(x) => Some(Value::Float(-x)),
+        _ => None,
+    }
+}
+
+pub fn number<'a>(s: &str) -> Result<AST<'a>, &'static str> {
+    literal(s).map(|x| AST::Atom(Atom::Value(x)))
+}
+
+pub fn symbol<'a>(s: &str) -> Result<AST<'a>, &'static str> {
+    symbol::intern(s).map(|x| AST::Atom(Atom::Value(Value::SymbolInt(x))))
+}
+
+pub fn symvec<'a>(s: &str) -> Result<AST<'a>, &'static str> {
+    // `a`b`c => VecSym
+    s[1..].split('`').map(symbol::intern).collect::<Result<Vec<u16>, &'static str>>().map(|x| AST::Atom(Atom::Value(Value::VecSym(x))))
+}
+
+pub fn sequence<'a>(s: &str) -> AST<'a> {
+    // "abc" is a char vector, "a" a char atom
+    let mut r = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        r.push(if c != '\\' {
+            c
+        } else {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some(x) => x,
+                None => c,
+            }
+        });
+    }
+    AST::Atom(Atom::Value(match r.chars().count() {
+        1 => Value::Char(r.chars().next().unwrap()),
+        _ => Value::Sequence(r),
+    }))
+}
+
+pub fn time<'a>(s: &str) -> Result<AST<'a>, &'static str> {
+    // 2016.10.17D09:30 2016.10.18D09:30 => VecTemporal, mixed kinds stay a general list
+    let neg = s.starts_with(SIGN);
+    let mut items = try!(s.trim_left_matches(SIGN)
+        .split_whitespace()
+        .map(|x| temporal::parse(x).ok_or("invalid temporal"))
+        .collect::<Result<Vec<(Temporal, i64)>, &'static str>>());
+    if neg && items[0].1 != i64::MIN {
+        items[0].1 = -items[0].1;
+    }
+    if items.len() == 1 {
+        return Ok(AST::Atom(Atom::Value(Value::Temporal(items[0].0, items[0].1))));
+    }
+    let k = items[0].0;
+    Ok(if items.iter().all(|&(x, _)| x == k) {
+        AST::Atom(Atom::Value(Value::VecTemporal(k, items.iter().map(|&(_, x)| x).collect())))
+    } else {
+        AST::Vector(items.iter().map(|&(k, x)| AST::Atom(Atom::Value(Value::Temporal(k, x)))).collect())
+    })
+}
+
+pub fn field<'a>(l: AST<'a>, f: &str, arena: &'a Arena<'a>) -> Result<AST<'a>, &'static str> {
+    // t.minute is the dyad t . `minute
+    let r = try!(symbol(&f[1..]));
+    Ok(AST::Atom(Atom::Verb(Verb::Dot, arena.ast(l), arena.ast(r))))
+}
+
+pub fn numvec<'a>(s: &str) -> Result<AST<'a>, &'static str> {
+    // 1 2 3 => VecInt, any float item makes the whole literal VecFloat
+    let items = try!(s.split_whitespace().map(literal).collect::<Result<Vec<Value>, &'static str>>());
+    let floats = items.iter().any(|x| match *x {
+        Value::Float(_) => true,
+        _ => false,
+    });
+    Ok(AST::Atom(Atom::Value(if floats {
+        Value::VecFloat(items.iter()
+            .map(|x| match *x {
+                Value::Number(i64::MIN) => f64::NAN,
+                Value::Number(n) => n as f64,
+                Value::Float(f) => f,
+                _ => 0.0,
+            })
+            .collect())
+    } else {
+        Value::VecInt(items.iter()
+            .map(|x| match *x {
+                Value::Number(n) => n,
+                _ => 0,
+            })
+            .collect())
+    })))
+}
+
+fn cond<'a>(x: &'a [AST<'a>], arena: &'a Arena<'a>) -> Atom<'a> {
+    // $[c1;t1;c2;t2;f] is $[c1;t1;$[c2;t2;f]]
+    match x.len() {
+        3 => Atom::Cond(&x[0], &x[1], &x[2]),
+        _ => Atom::Cond(&x[0], &x[1], arena.ast(AST::Atom(cond(&x[2..], arena)))),
+    }
+}
+
+pub fn branches<'a>(v: Verb, r: &AST<'a>) -> Result<(), &'static str> {
+    // past two arguments $[..] is a cond, and a cond ends with its else
+    match (v, r) {
+        (Verb::Cast, &AST::Atom(Atom::Dict(&AST::Vector(ref x)))) if x.len() > 3 && x.len() % 2 == 0 => Err("rank"),
+        _ => Ok(()),
+    }
 }
 
 pub fn verb<'a>(v: Verb, l: &'a AST<'a>, r: &'a AST<'a>, arena: &'a Arena<'a>) -> AST<'a> {
     match v {
-        Verb::Dot => {
+        Verb::Cast => {
             AST::Atom(match (l, r) {
-                (&AST::Atom(Atom::Value(Value::Number(x))), &AST::Atom(Atom::Value(Value::Number(y)))) => {
-                    Atom::Value(Value::Float((x as f64) +
-                                            (format!("0.{}", y)).parse::<f64>().expect("Invalid fraction")))
+                (&AST::Atom(Atom::Value(Value::Nil)), &AST::Atom(Atom::Dict(&AST::Vector(ref x)))) if x.len() % 2 == 1 && x.len() > 1 => {
+                    cond(x, arena)
+                }
+                (_, &AST::Atom(Atom::Dict(&AST::Vector(ref x)))) if x.len() % 2 == 1 && x.len() > 1 => {
+                    Atom::Call(l, arena.ast(AST::Atom(cond(x, arena))))
                 }
                 _ => Atom::Verb(v, l, r),
             })
         }
-        Verb::Cast => {
-            let rexpr = match r {
-                &AST::Atom(Atom::Dict(&AST::Vector(ref x))) if x.len() == 3 => Atom::Cond(&x[0], &x[1], &x[2]),
-                _ => Atom::Verb(v, l, r),
-            };
-            AST::Atom(match *l {
-                AST::Atom(Atom::Value(Value::Nil)) => rexpr,
-                _ => Atom::Call(l, arena.ast(AST::Atom(rexpr))), 
-            })
-
-        }
         _ => {
             AST::Atom(match r { // optional AST transformations could be done during parsing
                 &AST::Atom(Atom::Adverb(ref a, al, ar)) => {
@@ -813,28 +1261,28 @@ impl<'a> ASTAcc<'a> {
         ASTAcc { inner: Rc::new(UnsafeCell::new(a)) }
     }
 
-    pub fn push(&'a self, n: &'a AST<'a>) -> usize {
+    pub fn push(&self, n: &AST<'a>) -> usize {
         unsafe {
             (*self.inner.deref().get()).push(n.clone());
             self.len() - 1
         }
     }
 
-    pub fn set(&'a self, idx: usize, val: AST<'a>) {
+    pub fn set(&self, idx: usize, val: AST<'a>) {
         unsafe {
             (&mut *self.inner.deref().get())[idx] = val;
         }
     }
 
-    pub fn get(&'a self) -> &'a Vec<AST<'a>> {
+    pub fn get(&self) -> &Vec<AST<'a>> {
         unsafe { &*self.inner.deref().get() }
     }
 
-    pub fn len(&'a self) -> usize {
+    pub fn len(&self) -> usize {
         unsafe { (*self.inner.deref().get()).len() }
     }
 
-    pub fn disown(&'a self) -> Vec<AST<'a>> {
+    pub fn disown(&self) -> Vec<AST<'a>> {
         // transfer vector ownership
         unsafe { mem::replace(&mut *self.inner.deref().get(), vec![]) }
     }
@@ -0,0 +1,592 @@
+// kdb+ IPC wire format: what -8! writes and -9! reads.
+// A message is an 8 byte header (endianness, message type, compression
+// flag, reserved, total length) followed by one serialized object.
+// We always write little-endian and read either order.
+
+use std::{i16, i32, i64, mem, str};
+use commands::ast::{Value, Error};
+use commands::symbol;
+use commands::temporal::{Temporal, DAY};
+use tables::{Table, Dict};
+use tables::dict;
+
+pub const ASYNC: u8 = 0;
+pub const SYNC: u8 = 1;
+pub const RESPONSE: u8 = 2;
+
+// q only compresses messages bigger than this
+const COMPRESS_MIN: usize = 2000;
+
+fn error(desc: &str) -> Error {
+    Error::EvalError {
+        desc: format!("ipc: {}", desc),
+        ast: String::new(),
+    }
+}
+
+fn temporal_type(k: Temporal) -> i8 {
+    match k {
+        Temporal::Timestamp => 12,
+        Temporal::Date => 14,
+        Temporal::Timespan => 16,
+        Temporal::Time => 19,
+    }
+}
+
+fn narrow(x: i64) -> i32 {
+    // q keeps nulls and infinities at the ends of each width
+    match x {
+        i64::MIN => i32::MIN,
+        i64::MAX => i32::MAX,
+        x if x == -i64::MAX => -i32::MAX,
+        x if x > i32::MAX as i64 || x < -i32::MAX as i64 => i32::MIN,
+        x => x as i32,
+    }
+}
+
+fn widen32(x: i32) -> i64 {
+    match x {
+        i32::MIN => i64::MIN,
+        i32::MAX => i64::MAX,
+        x if x == -i32::MAX => -i64::MAX,
+        x => x as i64,
+    }
+}
+
+fn widen16(x: i16) -> i64 {
+    match x {
+        i16::MIN => i64::MIN,
+        i16::MAX => i64::MAX,
+        x if x == -i16::MAX => -i64::MAX,
+        x => x as i64,
+    }
+}
+
+struct Writer {
+    b: Vec<u8>,
+}
+
+impl Writer {
+    fn byte(&mut self, x: u8) {
+        self.b.push(x);
+    }
+
+    fn int(&mut self, x: i32) {
+        let v: [u8; 4] = unsafe { mem::transmute(x.to_le()) };
+        self.b.extend_from_slice(&v);
+    }
+
+    fn long(&mut self, x: i64) {
+        let v: [u8; 8] = unsafe { mem::transmute(x.to_le()) };
+        self.b.extend_from_slice(&v);
+    }
+
+    fn float(&mut self, x: f64) {
+        self.long(unsafe { mem::transmute(x) });
+    }
+
+    fn sym(&mut self, x: u16) {
+        self.b.extend_from_slice(symbol::name(x).as_bytes());
+        self.b.push(0);
+    }
+
+    fn head(&mut self, t: i8, n: usize) {
+        // vectors: type, attribute, count
+        self.byte(t as u8);
+        self.byte(0);
+        self.int(n as i32);
+    }
+
+    fn value(&mut self, v: &Value) -> Result<(), Error> {
+        match *v {
+            Value::Nil => {
+                self.byte(101);
+                self.byte(0);
+            }
+            Value::Number(x) => {
+                self.byte(-7i8 as u8);
+                self.long(x);
+            }
+            Value::Float(x) => {
+                self.byte(-9i8 as u8);
+                self.float(x);
+            }
+            Value::Char(c) if (c as u32) < 256 => {
+                self.byte(-10i8 as u8);
+                self.byte(c as u8);
+            }
+            Value::Char(_) => return Err(error("char out of range")),
+            Value::SymbolInt(x) => {
+                self.byte(-11i8 as u8);
+                self.sym(x);
+            }
+            Value::Sequence(ref s) => {
+                self.head(10, s.len());
+                self.b.extend_from_slice(s.as_bytes());
+            }
+            Value::VecInt(ref x) => {
+                self.head(7, x.len());
+                for i in x.iter() {
+                    self.long(*i);
+                }
+            }
+            Value::VecFloat(ref x) => {
+                self.head(9, x.len());
+                for i in x.iter() {
+                    self.float(*i);
+                }
+            }
+            Value::VecSym(ref x) => {
+                self.head(11, x.len());
+                for i in x.iter() {
+                    self.sym(*i);
+                }
+            }
+            Value::Temporal(k, x) => {
+                self.byte(-temporal_type(k) as u8);
+                match k {
+                    Temporal::Date | Temporal::Time => self.int(narrow(x)),
+                    _ => self.long(x),
+                }
+            }
+            Value::VecTemporal(k, ref x) => {
+                self.head(temporal_type(k), x.len());
+                for i in x.iter() {
+                    match k {
+                        Temporal::Date | Temporal::Time => self.int(narrow(*i)),
+                        _ => self.long(*i),
+                    }
+                }
+            }
+            Value::List(ref x) => {
+                self.head(0, x.len());
+                for i in x.iter() {
+                    try!(self.value(i));
+                }
+            }
+            Value::Dict(ref d) => {
+                self.byte(99);
+                try!(self.value(&d.keys));
+                try!(self.value(&d.values));
+            }
+            Value::Table(ref t) => {
+                self.byte(98);
+                self.byte(0);
+                self.byte(99);
+                self.head(11, t.names.len());
+                for n in t.names.iter() {
+                    self.b.extend_from_slice(n.as_bytes());
+                    self.b.push(0);
+                }
+                self.head(0, t.columns.len());
+                for c in t.columns.iter() {
+                    try!(self.value(c));
+                }
+            }
+            Value::Ioverb(_) => return Err(error("type")),
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'b> {
+    b: &'b [u8],
+    at: usize,
+    little: bool,
+}
+
+macro_rules! read_num {
+    ($name:ident, $t:ty, $n:expr) => {
+        fn $name(&mut self) -> Result<$t, Error> {
+            let b = try!(self.take($n));
+            let mut a = [0u8; $n];
+            a.copy_from_slice(b);
+            let x: $t = unsafe { mem::transmute(a) };
+            Ok(if self.little { <$t>::from_le(x) } else { <$t>::from_be(x) })
+        }
+    }
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, n: usize) -> Result<&'b [u8], Error> {
+        if self.b.len() - self.at < n {
+            return Err(error("truncated"));
+        }
+        self.at += n;
+        Ok(&self.b[self.at - n..self.at])
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        self.take(1).map(|b| b[0])
+    }
+
+    read_num!(short, i16, 2);
+    read_num!(int, i32, 4);
+    read_num!(long, i64, 8);
+
+    fn real(&mut self) -> Result<f64, Error> {
+        let x = try!(self.int());
+        Ok(unsafe { mem::transmute::<i32, f32>(x) } as f64)
+    }
+
+    fn float(&mut self) -> Result<f64, Error> {
+        let x = try!(self.long());
+        Ok(unsafe { mem::transmute(x) })
+    }
+
+    fn text(&mut self) -> Result<&'b str, Error> {
+        let n = match self.b[self.at..].iter().position(|c| *c == 0) {
+            Some(n) => n,
+            None => return Err(error("truncated")),
+        };
+        let s = try!(self.take(n + 1));
+        str::from_utf8(&s[..n]).map_err(|_| error("symbol is not utf-8"))
+    }
+
+    fn sym(&mut self) -> Result<u16, Error> {
+        self.text().and_then(|s| symbol::intern(s).map_err(error))
+    }
+
+    fn count(&mut self, width: usize) -> Result<usize, Error> {
+        // attribute byte, then the length; the items have to be in the
+        // bytes left before anything is allocated for them
+        try!(self.byte());
+        let n = try!(self.int());
+        if n < 0 {
+            return Err(error("length"));
+        }
+        if n as usize > (self.b.len() - self.at) / width {
+            return Err(error("truncated"));
+        }
+        Ok(n as usize)
+    }
+
+    fn atom(&mut self, t: i8) -> Result<Value, Error> {
+        Ok(match t {
+            -1 | -4 => Value::Number(try!(self.byte()) as i64),
+            -5 => Value::Number(widen16(try!(self.short()))),
+            -6 => Value::Number(widen32(try!(self.int()))),
+            -7 => Value::Number(try!(self.long())),
+            -8 => Value::Float(try!(self.real())),
+            -9 => Value::Float(try!(self.float())),
+            -10 => Value::Char(try!(self.byte()) as char),
+            -11 => Value::SymbolInt(try!(self.sym())),
+            -12 => Value::Temporal(Temporal::Timestamp, try!(self.long())),
+            -14 => Value::Temporal(Temporal::Date, widen32(try!(self.int()))),
+            -15 => Value::Temporal(Temporal::Timestamp, datetime(try!(self.float()))),
+            -16 => Value::Temporal(Temporal::Timespan, try!(self.long())),
+            -17 => Value::Temporal(Temporal::Time, scale(widen32(try!(self.int())), 60_000)),
+            -18 => Value::Temporal(Temporal::Time, scale(widen32(try!(self.int())), 1_000)),
+            -19 => Value::Temporal(Temporal::Time, widen32(try!(self.int()))),
+            _ => return Err(error(&format!("type {}", t))),
+        })
+    }
+
+    fn vector(&mut self, t: i8) -> Result<Value, Error> {
+        let n = try!(self.count(width(t)));
+        if t == 10 {
+            let s = try!(self.take(n));
+            // utf-8 when it is, otherwise one char per byte
+            return Ok(Value::Sequence(match str::from_utf8(s) {
+                Ok(t) => t.to_string(),
+                Err(_) => s.iter().map(|c| *c as char).collect(),
+            }));
+        }
+        if t == 11 {
+            let mut v = Vec::with_capacity(n);
+            for _ in 0..n {
+                v.push(try!(self.sym()));
+            }
+            return Ok(Value::VecSym(v));
+        }
+        if t == 0 {
+            let mut v = Vec::with_capacity(n);
+            for _ in 0..n {
+                v.push(try!(self.value()));
+            }
+            return Ok(Value::List(v));
+        }
+        // every other vector is its atoms laid end to end
+        let mut v = Vec::with_capacity(n);
+        for _ in 0..n {
+            v.push(try!(self.atom(-t)));
+        }
+        Ok(match (t, v.first().cloned()) {
+            (8, _) | (9, _) => Value::VecFloat(v.into_iter().map(|x| if let Value::Float(f) = x { f } else { 0.0 }).collect()),
+            (_, Some(Value::Temporal(k, _))) => {
+                Value::VecTemporal(k,
+                                   v.into_iter().map(|x| if let Value::Temporal(_, i) = x { i } else { 0 }).collect())
+            }
+            (12, None) | (15, None) => Value::VecTemporal(Temporal::Timestamp, vec![]),
+            (14, None) => Value::VecTemporal(Temporal::Date, vec![]),
+            (16, None) => Value::VecTemporal(Temporal::Timespan, vec![]),
+            (17, None) | (18, None) | (19, None) => Value::VecTemporal(Temporal::Time, vec![]),
+            _ => Value::VecInt(v.into_iter().map(|x| if let Value::Number(i) = x { i } else { 0 }).collect()),
+        })
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        let t = try!(self.byte()) as i8;
+        match t {
+            -128 => Err(error(try!(self.text()))),
+            t if t < 0 => self.atom(t),
+            t if t < 20 && t != 2 && t != 3 && t != 13 => self.vector(t),
+            98 => {
+                try!(self.byte());
+                match try!(self.value()) {
+                    Value::Dict(d) => table(*d),
+                    _ => Err(error("table")),
+                }
+            }
+            99 | 127 => {
+                let k = try!(self.value());
+                let v = try!(self.value());
+                Dict::new(k, v).map(|d| Value::Dict(Box::new(d)))
+            }
+            101 => {
+                try!(self.byte());
+                Ok(Value::Nil)
+            }
+            t => Err(error(&format!("type {}", t))),
+        }
+    }
+}
+
+fn width(t: i8) -> usize {
+    // the fewest bytes one item of a t vector takes on the wire
+    match t {
+        5 => 2,
+        6 | 8 | 13 | 14 | 17 | 18 | 19 => 4,
+        7 | 9 | 12 | 15 | 16 => 8,
+        _ => 1,
+    }
+}
+
+fn scale(x: i64, by: i64) -> i64 {
+    match x {
+        i64::MIN | i64::MAX => x,
+        x if x == -i64::MAX => x,
+        x => x * by,
+    }
+}
+
+fn datetime(x: f64) -> i64 {
+    // datetime is float days from the epoch, nan for null
+    if x.is_nan() {
+        i64::MIN
+    } else if x.is_infinite() {
+        if x > 0.0 { i64::MAX } else { -i64::MAX }
+    } else {
+        (x * DAY as f64).round() as i64
+    }
+}
+
+fn table(d: Dict) -> Result<Value, Error> {
+    let names = match d.keys {
+        Value::VecSym(ref n) => n.iter().map(|s| symbol::name(*s)).collect::<Vec<String>>(),
+        _ => return Err(error("table")),
+    };
+    let columns = match d.values {
+        Value::List(c) => c,
+        _ => return Err(error("table")),
+    };
+    if columns.len() != names.len() ||
+       columns.iter().any(|c| dict::count(c) != columns.first().map_or(0, dict::count)) {
+        return Err(error("length"));
+    }
+    Ok(Value::Table(Table {
+        names: names,
+        columns: columns,
+    }))
+}
+
+fn seal(w: &mut Writer) {
+    let n = w.b.len();
+    let size: [u8; 4] = unsafe { mem::transmute((n as u32).to_le()) };
+    w.b[4..8].copy_from_slice(&size);
+}
+
+pub fn encode(v: &Value, msg: u8, compress: bool) -> Result<Vec<u8>, Error> {
+    let mut w = Writer { b: vec![1, msg, 0, 0, 0, 0, 0, 0] };
+    try!(w.value(v));
+    seal(&mut w);
+    let n = w.b.len();
+    if compress && n > COMPRESS_MIN {
+        if let Some(c) = deflate(&w.b) {
+            return Ok(c);
+        }
+    }
+    Ok(w.b)
+}
+
+pub fn fault(desc: &str, msg: u8) -> Vec<u8> {
+    // what q sends back for 'desc: type -128 and the text
+    let mut w = Writer { b: vec![1, msg, 0, 0, 0, 0, 0, 0] };
+    w.byte(-128i8 as u8);
+    w.b.extend_from_slice(desc.as_bytes());
+    w.b.push(0);
+    seal(&mut w);
+    w.b
+}
+
+pub fn header(b: &[u8]) -> Result<(u8, bool, usize), Error> {
+    // message type, compressed, total length
+    if b.len() < 8 {
+        return Err(error("truncated"));
+    }
+    let mut r = Reader {
+        b: b,
+        at: 4,
+        little: b[0] == 1,
+    };
+    let n = try!(r.int()) as u32 as usize;
+    Ok((b[1], b[2] == 1, n))
+}
+
+pub fn decode(b: &[u8]) -> Result<(u8, Value), Error> {
+    let (msg, compressed, n) = try!(header(b));
+    if n > b.len() || n < 8 {
+        return Err(error("truncated"));
+    }
+    let plain;
+    let b = if compressed {
+        plain = try!(inflate(&b[..n]));
+        &plain[..]
+    } else {
+        &b[..n]
+    };
+    let mut r = Reader {
+        b: b,
+        at: 8,
+        little: b[0] == 1,
+    };
+    let v = try!(r.value());
+    if r.at != b.len() {
+        return Err(error("length"));
+    }
+    Ok((msg, v))
+}
+
+// q's own compression: a flag byte governs the next eight items, each either
+// a literal byte or a back reference found by hashing the next two bytes.
+
+fn deflate(y: &[u8]) -> Option<Vec<u8>> {
+    let t = y.len();
+    let e = t / 2;
+    let mut b = vec![0u8; e];
+    b[..4].copy_from_slice(&y[..4]);
+    b[2] = 1;
+    let len: [u8; 4] = unsafe { mem::transmute((t as u32).to_le()) };
+    b[8..12].copy_from_slice(&len);
+    let mut a = [0usize; 256];
+    let (mut c, mut d) = (12, 12);
+    let (mut f, mut i) = (0u8, 0u8);
+    let (mut h, mut h0, mut s0) = (0usize, 0usize, 0usize);
+    let mut s = 8;
+    while s < t {
+        if i == 0 {
+            if d + 17 > e {
+                return None;
+            }
+            i = 1;
+            b[c] = f;
+            c = d;
+            d += 1;
+            f = 0;
+        }
+        let mut p = 0;
+        let mut g = s > t - 3;
+        if !g {
+            h = (y[s] ^ y[s + 1]) as usize;
+            p = a[h];
+            g = p == 0 || y[s] != y[p];
+        }
+        if s0 > 0 {
+            a[h0] = s0;
+            s0 = 0;
+        }
+        if g {
+            h0 = h;
+            s0 = s;
+            b[d] = y[s];
+            d += 1;
+            s += 1;
+        } else {
+            a[h] = s;
+            f |= i;
+            p += 2;
+            s += 2;
+            let r = s;
+            let q = if s + 255 < t { s + 255 } else { t };
+            while s < q && y[p] == y[s] {
+                p += 1;
+                s += 1;
+            }
+            b[d] = h as u8;
+            b[d + 1] = (s - r) as u8;
+            d += 2;
+        }
+        i = i.wrapping_mul(2);
+    }
+    b[c] = f;
+    let size: [u8; 4] = unsafe { mem::transmute((d as u32).to_le()) };
+    b[4..8].copy_from_slice(&size);
+    b.truncate(d);
+    Some(b)
+}
+
+fn inflate(b: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut r = Reader {
+        b: b,
+        at: 8,
+        little: b[0] == 1,
+    };
+    let n = try!(r.int()) as u32 as usize;
+    // eight items per flag byte, at most 257 bytes from a two byte reference
+    if n < 8 || n > 8 + (b.len() - 12) / 17 * 2056 + 2056 {
+        return Err(error("length"));
+    }
+    let mut dst = vec![0u8; n];
+    dst[..4].copy_from_slice(&b[..4]);
+    dst[2] = 0;
+    let size: [u8; 4] = unsafe { mem::transmute(if r.little { (n as u32).to_le() } else { (n as u32).to_be() }) };
+    dst[4..8].copy_from_slice(&size);
+    let mut a = [0usize; 256];
+    let (mut s, mut p, mut d) = (8, 8, 12);
+    let (mut f, mut i) = (0u8, 0u8);
+    let byte = |d: usize| if d < b.len() { Ok(b[d]) } else { Err(error("truncated")) };
+    while s < n {
+        if i == 0 {
+            f = try!(byte(d));
+            d += 1;
+            i = 1;
+        }
+        let mut m = 0;
+        if f & i != 0 {
+            let mut q = a[try!(byte(d)) as usize];
+            m = try!(byte(d + 1)) as usize;
+            d += 2;
+            if s + 2 + m > n || q + 2 + m > s + 2 + m {
+                return Err(error("corrupt"));
+            }
+            for _ in 0..m + 2 {
+                dst[s] = dst[q];
+                s += 1;
+                q += 1;
+            }
+        } else {
+            dst[s] = try!(byte(d));
+            d += 1;
+            s += 1;
+        }
+        while p + 1 < s - m {
+            a[(dst[p] ^ dst[p + 1]) as usize] = p;
+            p += 1;
+        }
+        if f & i != 0 {
+            p = s;
+        }
+        i = i.wrapping_mul(2);
+    }
+    Ok(dst)
+}
@@ -0,0 +1,277 @@
+// k syntax output: what the console echoes and monadic $ returns.
+// Every value prints as source that parses back to an equal value,
+// except dicts and tables, which the console lays out as q does.
+
+use std::{i64, f64};
+use commands::ast::{AST, Atom, Value, Arena};
+use commands::{symbol, temporal};
+use tables::{Table, Dict};
+
+fn int(x: i64) -> String {
+    match x {
+        i64::MIN => "0N".to_string(),
+        i64::MAX => "0W".to_string(),
+        x if x == -i64::MAX => "-0W".to_string(),
+        x => format!("{}", x),
+    }
+}
+
+fn float(x: f64) -> String {
+    if x.is_nan() {
+        "0n".to_string()
+    } else if x.is_infinite() {
+        if x > 0.0 { "0w" } else { "-0w" }.to_string()
+    } else if x != 0.0 && (x.abs() >= 1e16 || x.abs() < 1e-4) {
+        format!("{:e}", x)
+    } else {
+        format!("{}", x)
+    }
+}
+
+fn floats(v: &[f64]) -> String {
+    // whole floats need the f suffix to stay floats
+    let s = v.iter().map(|x| float(*x)).collect::<Vec<String>>().join(" ");
+    if s.contains(|c: char| c == '.' || c == 'e' || c == 'n' || c == 'w') {
+        s
+    } else {
+        s + "f"
+    }
+}
+
+fn plain(s: &str) -> bool {
+    s.chars().enumerate().all(|(i, c)| {
+        (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '.' ||
+        (i > 0 && ((c >= '0' && c <= '9') || c == '_'))
+    })
+}
+
+fn sym(x: u16) -> String {
+    let s = symbol::name(x);
+    if plain(&s) {
+        format!("`{}", s)
+    } else {
+        format!("`$\"{}\"", escape(&s))
+    }
+}
+
+fn syms(x: &[u16]) -> String {
+    // `a`b runs together only while every name is plain, otherwise `$ of the strings
+    let names = x.iter().map(|x| symbol::name(*x)).collect::<Vec<String>>();
+    if names.iter().all(|s| plain(s)) {
+        vector(x.len(), "0#`", x.iter().map(|x| sym(*x)).collect::<String>())
+    } else {
+        format!("`${}", list(names.iter().map(|s| format!("\"{}\"", escape(s))).collect()))
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            '\t' => r.push_str("\\t"),
+            '\r' => r.push_str("\\r"),
+            c => r.push(c),
+        }
+    }
+    r
+}
+
+fn vector(n: usize, empty: &str, items: String) -> String {
+    match n {
+        0 => empty.to_string(),
+        1 => format!(",{}", items),
+        _ => items,
+    }
+}
+
+fn list(v: Vec<String>) -> String {
+    match v.len() {
+        0 => "()".to_string(),
+        1 => format!(",{}", v[0]),
+        _ => format!("({})", v.join(";")),
+    }
+}
+
+fn key(v: &Value) -> String {
+    // the left of ! has to be a single noun
+    let s = value(v);
+    if s.starts_with(',') || s.starts_with("0#") || s.starts_with('!') || s.starts_with("`$") {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+fn columns(t: &Table) -> String {
+    t.names
+        .iter()
+        .zip(t.columns.iter())
+        .map(|(n, c)| format!("{}:{}", n, value(c)))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+fn dict(d: &Dict) -> String {
+    match (&d.keys, &d.values) {
+        (&Value::Table(ref k), &Value::Table(ref v)) => format!("([{}]{})", columns(k), columns(v)),
+        (k, v) => format!("{}!{}", key(k), value(v)),
+    }
+}
+
+pub fn value(v: &Value) -> String {
+    match *v {
+        Value::Nil => "::".to_string(),
+        Value::Number(x) => int(x),
+        Value::Float(x) => floats(&[x]),
+        Value::SymbolInt(x) => sym(x),
+        Value::Char(c) => format!("\"{}\"", escape(&c.to_string())),
+        Value::Sequence(ref s) => {
+            match s.chars().count() {
+                1 => format!(",\"{}\"", escape(s)),
+                _ => format!("\"{}\"", escape(s)),
+            }
+        }
+        Value::VecInt(ref x) => vector(x.len(), "!0", x.iter().map(|x| int(*x)).collect::<Vec<String>>().join(" ")),
+        Value::VecFloat(ref x) => vector(x.len(), "0#0.0", floats(x)),
+        Value::VecSym(ref x) => syms(x),
+        Value::Temporal(k, x) => temporal::format(k, x),
+        Value::VecTemporal(k, ref x) => {
+            vector(x.len(),
+                   &format!("0#{}", k.null()),
+                   x.iter().map(|x| temporal::format(k, *x)).collect::<Vec<String>>().join(" "))
+        }
+        Value::List(ref x) => list(x.iter().map(value).collect()),
+        Value::Dict(ref d) => dict(d),
+        Value::Table(ref t) => format!("([]{})", columns(t)),
+        Value::Ioverb(ref s) => s.clone(),
+    }
+}
+
+pub fn fmt<'a>(x: &AST<'a>) -> String {
+    match *x {
+        AST::Atom(Atom::Value(ref v)) => value(v),
+        AST::Vector(ref v) => list(v.iter().map(fmt).collect()),
+        ref x => format!("{}", x),
+    }
+}
+
+pub fn show<'a>(x: &AST<'a>) -> String {
+    // console echo: :: prints nothing, dicts and tables get the q layout
+    match *x {
+        AST::Atom(Atom::Value(Value::Nil)) => String::new(),
+        AST::Atom(Atom::Value(Value::Dict(ref d))) => format!("{}", d),
+        AST::Atom(Atom::Value(Value::Table(ref t))) => format!("{}", t),
+        ref x => fmt(x),
+    }
+}
+
+fn noun<'a>(x: &AST<'a>) -> bool {
+    // safe on the left of a verb without parentheses
+    match *x {
+        AST::Atom(Atom::NameInt(_)) |
+        AST::Atom(Atom::Value(Value::SymbolInt(_))) |
+        AST::Atom(Atom::Value(Value::Sequence(_))) |
+        AST::Atom(Atom::Lambda(..)) => true,
+        AST::Atom(Atom::Value(Value::Number(n))) => n >= 0,
+        AST::Atom(Atom::Value(Value::Float(n))) => n >= 0.0,
+        AST::Atom(Atom::Verb(_, l, r)) => {
+            let nil = AST::Atom(Atom::Value(Value::Nil));
+            *l == nil && *r == nil
+        }
+        _ => false,
+    }
+}
+
+fn left<'a>(x: &AST<'a>, arena: &Arena<'a>) -> Option<String> {
+    code(x, arena).map(|s| if noun(x) { s } else { format!("({})", s) })
+}
+
+fn args<'a>(x: &AST<'a>, arena: &Arena<'a>) -> Option<String> {
+    // a;b;c as inside [] and lambda bodies
+    match *x {
+        AST::Vector(ref v) => {
+            v.iter()
+                .map(|a| code(a, arena))
+                .collect::<Option<Vec<String>>>()
+                .map(|v| v.join(";"))
+        }
+        ref x => code(x, arena),
+    }
+}
+
+pub fn code<'a>(x: &AST<'a>, arena: &Arena<'a>) -> Option<String> {
+    // parsed code back to source, names resolved through the arena;
+    // None for what has no source form (tables, queries, suspended values)
+    match *x {
+        AST::Atom(Atom::Value(ref v)) => Some(value(v)),
+        AST::Atom(Atom::NameInt(n)) => Some(arena.name(n)),
+        AST::Atom(Atom::Any) => Some(String::new()),
+        AST::Atom(Atom::Lambda(_, a, b)) => {
+            match (args(a, arena), args(b, arena)) {
+                (Some(a), Some(b)) => Some(format!("{{[{}] {}}}", a, b)),
+                _ => None,
+            }
+        }
+        AST::Atom(Atom::Verb(v, l, r)) => {
+            let nil = AST::Atom(Atom::Value(Value::Nil));
+            match (*l == nil, *r == nil) {
+                (true, true) => Some(format!("{}", v)),
+                (true, false) => code(r, arena).map(|r| format!("{}{}", v, r)),
+                _ => {
+                    match (left(l, arena), code(r, arena)) {
+                        (Some(l), Some(r)) => Some(format!("{}{}{}", l, v, r)),
+                        _ => None,
+                    }
+                }
+            }
+        }
+        AST::Atom(Atom::Adverb(a, f, x)) => {
+            let x = match *x {
+                AST::Atom(Atom::Dict(d)) => args(d, arena).map(|d| format!("[{}]", d)),
+                ref x => code(x, arena),
+            };
+            let f = match *f {
+                // x,'y keeps the left operand with its verb
+                AST::Atom(Atom::Verb(v, l, r)) if *r == AST::Atom(Atom::Value(Value::Nil)) => {
+                    match *l {
+                        AST::Atom(Atom::Value(Value::Nil)) => Some(format!("{}", v)),
+                        ref l => left(l, arena).map(|l| format!("{}{}", l, v)),
+                    }
+                }
+                ref f => left(f, arena),
+            };
+            match (f, x) {
+                (Some(f), Some(x)) => Some(format!("{}{}{}", f, a, x)),
+                _ => None,
+            }
+        }
+        AST::Atom(Atom::Call(f, x)) => {
+            let x = match *x {
+                AST::Atom(Atom::Dict(d)) => args(d, arena).map(|d| format!("[{}]", d)),
+                ref x => code(x, arena).map(|x| format!(" {}", x)),
+            };
+            match (left(f, arena), x) {
+                (Some(f), Some(x)) => Some(format!("{}{}", f, x)),
+                _ => None,
+            }
+        }
+        AST::Atom(Atom::Assign(n, v)) => {
+            match (code(n, arena), code(v, arena)) {
+                (Some(n), Some(v)) => Some(format!("{}:{}", n, v)),
+                _ => None,
+            }
+        }
+        AST::Atom(Atom::Cond(c, a, b)) => {
+            match (code(c, arena), code(a, arena), code(b, arena)) {
+                (Some(c), Some(a), Some(b)) => Some(format!("$[{};{};{}]", c, a, b)),
+                _ => None,
+            }
+        }
+        AST::Atom(Atom::List(x)) => args(x, arena).map(|x| format!("({})", x)),
+        AST::Atom(Atom::Dict(x)) => args(x, arena).map(|x| format!("[{}]", x)),
+        _ => None,
+    }
+}
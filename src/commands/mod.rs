@@ -1,3 +1,6 @@
-
 pub mod ast;
+pub mod symbol;
+pub mod temporal;
+pub mod kfmt;
+pub mod ipc;
 pub mod command;
@@ -0,0 +1,62 @@
+// Process wide symbol table. Every core interns into the same table,
+// so a symbol id means the same text on whichever core receives it.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Mutex, Once, ONCE_INIT};
+
+struct Symbols {
+    ids: HashMap<String, u16>,
+    names: Vec<String>,
+}
+
+static INIT: Once = ONCE_INIT;
+static mut SYMBOLS: *const Mutex<Symbols> = 0 as *const Mutex<Symbols>;
+
+fn symbols() -> &'static Mutex<Symbols> {
+    unsafe {
+        INIT.call_once(|| {
+            let mut ids = HashMap::new();
+            ids.insert(String::new(), NULL);
+            SYMBOLS = Box::into_raw(Box::new(Mutex::new(Symbols {
+                ids: ids,
+                names: vec![String::new()],
+            })));
+        });
+        &*SYMBOLS
+    }
+}
+
+// the empty symbol ` is interned first, so nulls never need a new id
+pub const NULL: u16 = 0;
+
+pub fn intern(s: &str) -> Result<u16, &'static str> {
+    // ids are u16 wide, the table stops at 65536 names rather than wrap
+    let mut t = symbols().lock().unwrap();
+    if let Some(&id) = t.ids.get(s) {
+        return Ok(id);
+    }
+    if t.names.len() > u16::max_value() as usize {
+        return Err("symbol table full");
+    }
+    let id = t.names.len() as u16;
+    t.names.push(s.to_string());
+    t.ids.insert(s.to_string(), id);
+    Ok(id)
+}
+
+pub fn name(id: u16) -> String {
+    symbols().lock().unwrap().names.get(id as usize).cloned().unwrap_or(String::new())
+}
+
+pub fn names(ids: &[u16]) -> Vec<String> {
+    // a whole list resolves under one lock, sorts compare the texts after
+    let t = symbols().lock().unwrap();
+    ids.iter().map(|&id| t.names.get(id as usize).cloned().unwrap_or(String::new())).collect()
+}
+
+pub fn cmp(a: u16, b: u16) -> Ordering {
+    // symbols sort by text, not by the order they were interned
+    let t = symbols().lock().unwrap();
+    t.names[a as usize].cmp(&t.names[b as usize])
+}
@@ -0,0 +1,192 @@
+// q temporal types. All four are i64 counts from the q epoch 2000.01.01:
+// timestamp and timespan in nanoseconds, date in days, time in milliseconds.
+
+use std::fmt;
+use std::i64;
+use time;
+
+#[derive(PartialEq,Debug,Clone, Copy)]
+pub enum Temporal {
+    Timestamp,
+    Date,
+    Time,
+    Timespan,
+}
+
+pub const NS: i64 = 1_000_000_000;
+pub const DAY: i64 = 86_400 * NS;
+pub const MS: i64 = 1_000_000;
+
+// days from 1970.01.01 to 2000.01.01
+const EPOCH: i64 = 10_957;
+
+impl Temporal {
+    pub fn code(&self) -> i64 {
+        match *self {
+            Temporal::Timestamp => 12,
+            Temporal::Date => 14,
+            Temporal::Timespan => 16,
+            Temporal::Time => 19,
+        }
+    }
+
+    pub fn null(&self) -> &'static str {
+        match *self {
+            Temporal::Timestamp => "0Np",
+            Temporal::Date => "0Nd",
+            Temporal::Timespan => "0Nn",
+            Temporal::Time => "0Nt",
+        }
+    }
+
+    pub fn to_ns(&self, x: i64) -> i64 {
+        // common scale for comparing across types
+        match *self {
+            Temporal::Date => x.saturating_mul(DAY),
+            Temporal::Time => x.saturating_mul(MS),
+            _ => x,
+        }
+    }
+
+    pub fn from_ns(&self, x: i64) -> i64 {
+        match *self {
+            Temporal::Date => floor(x, DAY),
+            Temporal::Time => floor(x, MS),
+            _ => x,
+        }
+    }
+}
+
+impl fmt::Display for Temporal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Temporal::Timestamp => write!(f, "timestamp"),
+            Temporal::Date => write!(f, "date"),
+            Temporal::Time => write!(f, "time"),
+            Temporal::Timespan => write!(f, "timespan"),
+        }
+    }
+}
+
+pub fn floor(x: i64, d: i64) -> i64 {
+    let q = x / d;
+    if (x % d != 0) && ((x < 0) != (d < 0)) { q - 1 } else { q }
+}
+
+pub fn civil(days: i64) -> (i64, i64, i64) {
+    // days since 2000.01.01 => (year, month, day), proleptic Gregorian
+    let z = days + EPOCH + 719_468;
+    let era = floor(z, 146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + if m <= 2 { 1 } else { 0 }, m, d)
+}
+
+pub fn days(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = floor(y, 400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468 - EPOCH
+}
+
+fn clock(s: &str) -> Option<i64> {
+    // HH:MM[:SS[.fff]] => nanoseconds
+    let (hms, frac) = match s.find('.') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, ""),
+    };
+    let mut ns = 0;
+    let units = [3600 * NS, 60 * NS, NS];
+    for (i, p) in hms.split(':').enumerate() {
+        if i > 2 {
+            return None;
+        }
+        ns += match p.parse::<i64>() {
+            Ok(x) => x * units[i],
+            Err(_) => return None,
+        };
+    }
+    if frac.len() > 0 {
+        let digits = frac.chars().chain("000000000".chars()).take(9).collect::<String>();
+        ns += match digits.parse::<i64>() {
+            Ok(x) => x,
+            Err(_) => return None,
+        };
+    }
+    Some(ns)
+}
+
+fn date(s: &str) -> Option<i64> {
+    let p = s.split('.').map(|x| x.parse::<i64>()).collect::<Vec<_>>();
+    match (p.get(0), p.get(1), p.get(2)) {
+        (Some(&Ok(y)), Some(&Ok(m)), Some(&Ok(d))) if p.len() == 3 && m >= 1 && m <= 12 && d >= 1 && d <= 31 => {
+            Some(days(y, m, d))
+        }
+        _ => None,
+    }
+}
+
+pub fn parse(s: &str) -> Option<(Temporal, i64)> {
+    // 2016.10.17D09:30:00.000000000, 2016.10.17, 09:30:00.000, 0D00:05:00
+    match s {
+        "0Np" => return Some((Temporal::Timestamp, i64::MIN)),
+        "0Nd" => return Some((Temporal::Date, i64::MIN)),
+        "0Nt" => return Some((Temporal::Time, i64::MIN)),
+        "0Nn" => return Some((Temporal::Timespan, i64::MIN)),
+        _ => {}
+    }
+    match s.find('D') {
+        Some(i) => {
+            let t = if i + 1 < s.len() { clock(&s[i + 1..]) } else { Some(0) };
+            match (date(&s[..i]), s[..i].parse::<i64>(), t) {
+                (Some(d), _, Some(t)) => Some((Temporal::Timestamp, d * DAY + t)),
+                (None, Ok(d), Some(t)) => Some((Temporal::Timespan, d * DAY + t)),
+                _ => None,
+            }
+        }
+        None if s.contains(':') => clock(s).map(|t| (Temporal::Time, t / MS)),
+        None => date(s).map(|d| (Temporal::Date, d)),
+    }
+}
+
+fn hms(ns: i64, digits: usize) -> String {
+    let s = ns / NS;
+    let f = format!("{:09}", ns % NS);
+    format!("{:02}:{:02}:{:02}.{}", s / 3600, s / 60 % 60, s % 60, &f[..digits])
+}
+
+pub fn format(k: Temporal, x: i64) -> String {
+    if x == i64::MIN {
+        return k.null().to_string();
+    }
+    match k {
+        Temporal::Date => {
+            let (y, m, d) = civil(x);
+            format!("{:04}.{:02}.{:02}", y, m, d)
+        }
+        Temporal::Timestamp => {
+            let d = floor(x, DAY);
+            format!("{}D{}", format(Temporal::Date, d), hms(x - d * DAY, 9))
+        }
+        Temporal::Time => {
+            let sign = if x < 0 { "-" } else { "" };
+            format!("{}{}", sign, hms(x.abs() * MS, 3))
+        }
+        Temporal::Timespan => {
+            let sign = if x < 0 { "-" } else { "" };
+            format!("{}{}D{}", sign, x.abs() / DAY, hms(x.abs() % DAY, 9))
+        }
+    }
+}
+
+pub fn now() -> i64 {
+    // current UTC timestamp
+    let t = time::get_time();
+    (t.sec - EPOCH * 86_400) * NS + t.nsec as i64
+}
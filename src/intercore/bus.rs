@@ -2,6 +2,7 @@ use queues::publisher::Publisher;
 use queues::publisher::Subscriber;
 use core::cell::UnsafeCell;
 use intercore::message::Message;
+use commands::ast::Value;
 
 pub fn send<'a>(bus: &'a Channel, m: Message) {
     if let Some(v) = bus.publisher.next() {
@@ -10,15 +11,93 @@ pub fn send<'a>(bus: &'a Channel, m: Message) {
     };
 }
 
+// What a user queue carries, spelled with q type chars: "j" ints, "F" float
+// vectors, "jfs" records of an int, a float and a symbol, "*" anything.
+
+#[derive(PartialEq,Debug,Clone)]
 pub enum TypeId {
     Byte,
     Int,
     Float,
+    Char,
+    Symbol,
+    Vector(Box<TypeId>),
+    Record(Vec<TypeId>),
+    Any,
+}
+
+impl TypeId {
+    fn atom(c: char) -> Option<TypeId> {
+        match c {
+            'x' => Some(TypeId::Byte),
+            'h' | 'i' | 'j' => Some(TypeId::Int),
+            'e' | 'f' => Some(TypeId::Float),
+            'c' => Some(TypeId::Char),
+            's' => Some(TypeId::Symbol),
+            _ => None,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<TypeId> {
+        let c = s.chars().collect::<Vec<char>>();
+        match c.len() {
+            0 => None,
+            1 if c[0] == '*' => Some(TypeId::Any),
+            1 if c[0].is_uppercase() => {
+                c[0].to_lowercase().next().and_then(TypeId::atom).map(|t| TypeId::Vector(Box::new(t)))
+            }
+            1 => TypeId::atom(c[0]),
+            _ => c.iter().map(|x| TypeId::atom(*x)).collect::<Option<Vec<TypeId>>>().map(TypeId::Record),
+        }
+    }
+
+    pub fn empty(&self) -> Value {
+        // what draining an empty queue of this type gives
+        match *self {
+            TypeId::Byte | TypeId::Int => Value::VecInt(vec![]),
+            TypeId::Float => Value::VecFloat(vec![]),
+            TypeId::Symbol => Value::VecSym(vec![]),
+            TypeId::Char => Value::Sequence(String::new()),
+            _ => Value::List(vec![]),
+        }
+    }
+
+    pub fn fits(&self, v: &Value) -> bool {
+        match (self, v) {
+            (&TypeId::Any, _) => true,
+            (&TypeId::Byte, &Value::Number(x)) => x >= 0 && x < 256,
+            (&TypeId::Int, &Value::Number(_)) |
+            (&TypeId::Float, &Value::Float(_)) |
+            (&TypeId::Char, &Value::Char(_)) |
+            (&TypeId::Symbol, &Value::SymbolInt(_)) => true,
+            (&TypeId::Vector(ref t), &Value::VecInt(ref x)) => {
+                **t == TypeId::Int || (**t == TypeId::Byte && x.iter().all(|b| *b >= 0 && *b < 256))
+            }
+            (&TypeId::Vector(ref t), &Value::VecFloat(_)) => **t == TypeId::Float,
+            (&TypeId::Vector(ref t), &Value::VecSym(_)) => **t == TypeId::Symbol,
+            (&TypeId::Vector(ref t), &Value::Sequence(_)) => **t == TypeId::Char,
+            (&TypeId::Record(ref ts), &Value::List(ref x)) => {
+                ts.len() == x.len() && ts.iter().zip(x.iter()).all(|(t, v)| t.fits(v))
+            }
+            // a record of like fields arrives as a typed vector
+            (&TypeId::Record(ref ts), v) => {
+                match *v {
+                    Value::VecInt(ref x) => ts.len() == x.len() && x.iter().all(|i| ts.iter().all(|t| t.fits(&Value::Number(*i)))),
+                    Value::VecFloat(ref x) => ts.len() == x.len() && ts.iter().all(|t| *t == TypeId::Float),
+                    Value::VecSym(ref x) => ts.len() == x.len() && ts.iter().all(|t| *t == TypeId::Symbol),
+                    Value::Sequence(ref x) => ts.len() == x.chars().count() && ts.iter().all(|t| *t == TypeId::Char),
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
 }
 
 pub struct Memory {
-    publishers: UnsafeCell<Vec<Publisher<i64>>>,
-    subscribers: UnsafeCell<Vec<Subscriber<i64>>>,
+    publishers: UnsafeCell<Vec<Publisher<Value>>>,
+    subscribers: UnsafeCell<Vec<Subscriber<Value>>>,
+    types: UnsafeCell<Vec<TypeId>>,
 }
 
 pub struct Channel {
@@ -42,15 +121,28 @@ impl Memory {
         Memory {
             publishers: UnsafeCell::new(vec![]),
             subscribers: UnsafeCell::new(vec![]),
+            types: UnsafeCell::new(vec![]),
         }
     }
+
+    pub fn publish(&self, cap: usize, t: TypeId) -> usize {
+        self.publishers().push(Publisher::with_capacity(cap));
+        self.types().push(t);
+        self.publishers().len() - 1
+    }
+
     #[inline]
-    pub fn publishers(&self) -> &mut Vec<Publisher<i64>> {
+    pub fn publishers(&self) -> &mut Vec<Publisher<Value>> {
         unsafe { &mut *self.publishers.get() }
     }
 
     #[inline]
-    pub fn subscribers(&self) -> &mut Vec<Subscriber<i64>> {
+    pub fn subscribers(&self) -> &mut Vec<Subscriber<Value>> {
         unsafe { &mut *self.subscribers.get() }
     }
+
+    #[inline]
+    pub fn types(&self) -> &mut Vec<TypeId> {
+        unsafe { &mut *self.types.get() }
+    }
 }
\ No newline at end of file
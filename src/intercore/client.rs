@@ -1,14 +1,28 @@
-
-use commands::ast::{Error, AST, Atom, Arena, Value};
+use commands::ast::{self, Error, AST, Atom, Arena, Value, Verb};
+use commands::temporal::{self, Temporal};
+use commands::{kfmt, symbol};
+use streams::verb::{self, list, dict};
 use streams::otree;
 use streams::interpreter::{Interpreter, Lazy, Cont};
-use intercore::message::{Pub, Sub, Message, Spawn};
+use intercore::message::{Pub, Sub, Message, Spawn, Peach, Await, Eval, Park};
+use intercore::bus::TypeId;
 use reactors::task::Context;
 use handle::{into_raw, from_raw};
 
 // The InterCore messages + Buildins are being handled in Interpreter
 
-pub fn internals<'a>(i: &'a mut Interpreter<'a>, f_id: u16, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Context<'a> {
+fn error<'a>(desc: &str, x: &AST<'a>) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{:?}", x),
+    }
+}
+
+pub fn internals<'a>(i: &'a mut Interpreter<'a>,
+                     f_id: u16,
+                     args: &'a AST<'a>,
+                     arena: &'a Arena<'a>)
+                     -> Result<Context<'a>, Error> {
     match f_id {
         0 => print(i, args, arena),
         1 => publisher(i, args, arena),
@@ -16,8 +30,19 @@ pub fn internals<'a>(i: &'a mut Interpreter<'a>, f_id: u16, args: &'a AST<'a>, a
         3 => send(i, args, arena),
         4 => receive(i, args, arena),
         5 => spawn(i, args, arena),
-        6 => Context::Nil,
-        _ => panic!("unknown internal func"),
+        6 => cols(i, args, arena),
+        7 => count(i, args, arena),
+        8 => upsert(i, args, arena),
+        9 => key(i, args, arena),
+        10 => value(i, args, arena),
+        11 => xbar(i, args, arena),
+        12 => now(i, args, arena),
+        13 => peach(i, args, arena),
+        14 => wait(i, args, arena),
+        15 => status(i, args, arena),
+        16 => at(i, args, arena),
+        17 => receive_all(i, args, arena),
+        _ => Err(error("Unknown internal function", args)),
     }
 }
 
@@ -41,79 +66,145 @@ pub fn eval_context<'a>(f: otree::NodeId,
         }
         Context::Node(ref ast) => from_raw(h).run_cont(f, ast, cont),
 
-        _ => panic!("TODO"),
+        x => {
+            Err(Error::EvalError {
+                desc: "Unexpected builtin context".to_string(),
+                ast: format!("{:?}", x),
+            })
+        }
     }
 }
 
+fn ints<'a>(name: &str, args: &'a AST<'a>) -> Result<(i64, i64), Error> {
+    // f[x;y] with two int arguments
+    match args {
+        &AST::Vector(ref v) if v.len() == 2 => {
+            match (&v[0], &v[1]) {
+                (&AST::Atom(Atom::Value(Value::Number(x))), &AST::Atom(Atom::Value(Value::Number(y)))) => Ok((x, y)),
+                _ => Err(error(&format!("{}: type", name), args)),
+            }
+        }
+        _ => Err(error(&format!("{}: rank", name), args)),
+    }
+}
 
-pub fn print<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Context<'a> {
+pub fn print<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
     println!("Print Args: {}", args);
-    Context::Node(args)
+    Ok(Context::Node(args))
 }
 
-pub fn spawn<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Context<'a> {
-    println!("Spawn Args: {:?}", args);
-    let (core, txt) = (0, "a:1".to_string());
-
-    let (core, txt) = match args {
+fn source<'a>(name: &str, args: &'a AST<'a>) -> Result<(i64, String), Error> {
+    // f[core;"expr"]
+    match args {
         &AST::Vector(ref v) if v.len() == 2 => {
             match (&v[0], &v[1]) {
-                (&AST::Atom(Atom::Value(Value::Number(c))), &AST::Atom(Atom::Value(Value::SequenceInt(n)))) => {
-                    (c, "test".to_string())
+                (&AST::Atom(Atom::Value(Value::Number(c))), &AST::Atom(Atom::Value(Value::Sequence(ref s)))) if c >= 0 => {
+                    Ok((c, s.clone()))
+                }
+                (&AST::Atom(Atom::Value(Value::Number(c))), &AST::Atom(Atom::Value(Value::Char(s)))) if c >= 0 => {
+                    Ok((c, s.to_string()))
                 }
-                _ => (0, "".to_string()),
+                _ => Err(error(&format!("{}: type", name), args)),
             }
         }
-        _ => (0, "".to_string()),
-    };
+        _ => Err(error(&format!("{}: rank", name), args)),
+    }
+}
+
+pub fn spawn<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    println!("Spawn Args: {:?}", args);
+    let (core, txt) = try!(source("spawn", args));
 
     i.edge = Message::Spawn(Spawn {
-        from: 0,
+        from: i.core,
+        to: core as usize,
+        task_id: i.task_id,
+        txt: txt,
+    });
+    Ok(Context::Intercore(&i.edge))
+}
+
+fn watch<'a>(name: &str, i: &'a mut Interpreter<'a>, args: &'a AST<'a>, block: bool) -> Result<Context<'a>, Error> {
+    // a task handle is core index, as spawn gives it back
+    let (core, index) = match unary(args) {
+        &AST::Atom(Atom::Value(Value::VecInt(ref v))) if v.len() == 2 && v[0] >= 0 && v[1] >= 0 => (v[0], v[1]),
+        _ => return Err(error(&format!("{}: not a task", name), args)),
+    };
+    i.edge = Message::Await(Await {
+        from: i.core,
         to: core as usize,
+        task_id: i.task_id,
+        index: index as usize,
+        block: block,
+    });
+    Ok(Context::Intercore(&i.edge))
+}
+
+pub fn wait<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    watch("wait", i, args, true)
+}
+
+pub fn status<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    watch("status", i, args, false)
+}
+
+pub fn at<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    // evaluated over there, this task waits for the value the way pub does
+    let (core, txt) = try!(source("at", args));
+    i.calls += 1;
+    i.edge = Message::Eval(Eval {
+        from: i.core,
+        to: core as usize,
+        task_id: i.task_id,
+        id: i.calls,
         txt: txt,
     });
-    Context::Intercore(&i.edge)
+    Ok(Context::Intercore(&i.edge))
 }
 
-pub fn publisher<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Context<'a> {
+pub fn publisher<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
     println!("Pub Args: {:?}", args);
 
-    let (core, cap) = match args {
-        &AST::Vector(ref v) if v.len() == 2 => {
-            match (&v[0], &v[1]) {
-                (&AST::Atom(Atom::Value(Value::Number(core))), &AST::Atom(Atom::Value(Value::Number(cap)))) => {
-                    (core as usize, cap as usize)
-                }
-                _ => panic!("oops!"),
+    // pub[core;cap] is an int queue, pub[core;cap;"F"] says what goes in
+    let (kind, args) = match args {
+        &AST::Vector(ref v) if v.len() == 3 => {
+            let t = match v[2] {
+                AST::Atom(Atom::Value(Value::Char(c))) => TypeId::parse(&c.to_string()),
+                AST::Atom(Atom::Value(Value::Sequence(ref s))) => TypeId::parse(s),
+                _ => return Err(error("pub: type", args)),
+            };
+            match t {
+                Some(t) => (t, arena.ast(AST::Vector(v[..2].to_vec()))),
+                None => return Err(error("pub: unknown queue type", args)),
             }
         }
-        _ => panic!("oops!"),
+        x => (TypeId::Int, x),
     };
+    let (core, cap) = try!(ints("pub", args));
+    if core < 0 || cap <= 0 {
+        return Err(error("pub: domain", args));
+    }
+    let (core, cap) = (core as usize, cap as usize);
 
     i.edge = Message::Pub(Pub {
-        from: 0,
-        task_id: 0,
+        from: i.core,
+        task_id: i.task_id,
         to: core,
         name: "".to_string(),
         cap: cap,
+        kind: kind,
     });
-    Context::Intercore(&i.edge)
+    Ok(Context::Intercore(&i.edge))
 }
 
-pub fn subscriber<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Context<'a> {
+pub fn subscriber<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
     println!("Sub Args: {:?}", args);
 
-    let (core, pub_id) = match args {
-        &AST::Vector(ref v) if v.len() == 2 => {
-            match (&v[0], &v[1]) {
-                (&AST::Atom(Atom::Value(Value::Number(core))), &AST::Atom(Atom::Value(Value::Number(pub_id)))) => {
-                    (core as usize, pub_id as usize)
-                }
-                _ => panic!("oops!"),
-            }
-        }
-        _ => panic!("oops!"),
-    };
+    let (core, pub_id) = try!(ints("sub", args));
+    if core < 0 || pub_id < 0 {
+        return Err(error("sub: domain", args));
+    }
+    let (core, pub_id) = (core as usize, pub_id as usize);
 
     i.edge = Message::Sub(Sub {
         from: i.task_id,
@@ -121,45 +212,233 @@ pub fn subscriber<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a
         to: core,
         pub_id: pub_id,
     });
-    Context::Intercore(&i.edge)
+    Ok(Context::Intercore(&i.edge))
 }
 
-pub fn send<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Context<'a> {
+pub fn send<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
     println!("Send Args: {:?}", args);
 
-    let (val, pub_id) = match args {
+    let (pub_id, x, val) = match args {
+        &AST::Vector(ref v) if v.len() == 2 => {
+            match (&v[0], verb::to_value(&v[1])) {
+                (&AST::Atom(Atom::Value(Value::Number(p))), Some(x)) => (p, &v[1], x),
+                _ => return Err(error("snd: type", args)),
+            }
+        }
+        _ => return Err(error("snd: rank", args)),
+    };
+    let p = match i.queues.publishers().get(pub_id as usize) {
+        Some(p) if pub_id >= 0 => p,
+        _ => return Err(error(&format!("snd: wrong publisher id {}", pub_id), args)),
+    };
+    let t = &i.queues.types()[pub_id as usize];
+    let batch = if t.fits(&val) {
+        vec![val]
+    } else {
+        // a list of items the queue takes goes in as one batch, snd[p;1 2 3]
+        match verb::items(x).iter().map(verb::to_value).collect::<Option<Vec<Value>>>() {
+            Some(ref b) if verb::count(x) != 1 && b.iter().all(|v| t.fits(v)) => b.clone(),
+            _ => return Err(error(&format!("snd: {} doesn't fit publisher {}", kfmt::value(&val), pub_id), args)),
+        }
+    };
+    // the whole batch goes in or none of it
+    match p.next_n_split(batch.len()) {
+        Some((a, b)) => {
+            for (slot, v) in a.iter_mut().chain(b.iter_mut()).zip(batch.into_iter()) {
+                *slot = v;
+            }
+            p.commit();
+        }
+        None => return Err(error("snd: queue full", args)),
+    }
+    Ok(Context::Node(arena.nil()))
+}
+
+pub fn receive<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    println!("Receive Args: {:?}", args);
+    // rcv s parks the task until an item comes, rcv[s;ms] gives up with nil
+    let (sub_id, timeout) = match args {
         &AST::Vector(ref v) if v.len() == 2 => {
             match (&v[0], &v[1]) {
-                (&AST::Atom(Atom::Value(Value::Number(pub_id))), &AST::Atom(Atom::Value(Value::Number(val)))) => {
-                    (val, pub_id)
+                (&AST::Atom(Atom::Value(Value::Number(sub_id))),
+                 &AST::Atom(Atom::Value(Value::Number(ms)))) if ms >= 0 => (sub_id, Some(ms as u64)),
+                _ => return Err(error("rcv: type", args)),
+            }
+        }
+        x => {
+            match unary(x) {
+                &AST::Atom(Atom::Value(Value::Number(sub_id))) => (sub_id, None),
+                _ => return Err(error("rcv: type", args)),
+            }
+        }
+    };
+    let s = match i.queues.subscribers().get(sub_id as usize) {
+        Some(s) if sub_id >= 0 => s,
+        _ => return Err(error(&format!("rcv: wrong subscriber id {}", sub_id), args)),
+    };
+    if let Some(slot) = s.recv() {
+        let res = slot.clone();
+        s.commit();
+        return Ok(Context::Node(arena.ast(verb::from_value(res))));
+    }
+    if timeout == Some(0) {
+        return Ok(Context::Node(arena.nil()));
+    }
+    i.edge = Message::Park(Park {
+        from: i.core,
+        to: i.core,
+        task_id: i.task_id,
+        sub_id: sub_id as usize,
+        timeout: timeout,
+    });
+    Ok(Context::Intercore(&i.edge))
+}
+
+pub fn receive_all<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    // drains the subscriber without waiting, an empty one gives an empty list of the queue's type
+    let sub_id = match unary(args) {
+        &AST::Atom(Atom::Value(Value::Number(sub_id))) => sub_id,
+        _ => return Err(error("rcvall: type", args)),
+    };
+    let s = match i.queues.subscribers().get(sub_id as usize) {
+        Some(s) if sub_id >= 0 => s,
+        _ => return Err(error(&format!("rcvall: wrong subscriber id {}", sub_id), args)),
+    };
+    let items = match s.recv_all_split() {
+        Some((a, b)) => {
+            let items = a.iter().chain(b.iter()).cloned().map(verb::from_value).collect::<Vec<AST>>();
+            s.commit();
+            items
+        }
+        None => {
+            // a queue published on another core has no type here
+            let empty = i.queues
+                .publishers()
+                .iter()
+                .position(|p| s.follows(p))
+                .and_then(|k| i.queues.types().get(k))
+                .map_or(Value::List(vec![]), |t| t.empty());
+            return Ok(Context::Node(arena.ast(AST::Atom(Atom::Value(empty)))));
+        }
+    };
+    Ok(Context::Node(arena.ast(verb::collapse(items))))
+}
+
+fn unary<'a>(args: &'a AST<'a>) -> &'a AST<'a> {
+    // f x and f[x] both reach here, the latter as a one item vector
+    match args {
+        &AST::Vector(ref v) if v.len() == 1 => &v[0],
+        x => x,
+    }
+}
+
+pub fn cols<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    let names = match unary(args) {
+        &AST::Atom(Atom::Value(Value::Table(ref t))) => t.names.clone(),
+        &AST::Atom(Atom::Value(Value::Dict(ref d))) => {
+            match (&d.keys, &d.values) {
+                (&Value::Table(ref k), &Value::Table(ref v)) => k.names.iter().chain(v.names.iter()).cloned().collect(),
+                _ => return Err(error("cols: not a table", args)),
+            }
+        }
+        _ => return Err(error("cols: not a table", args)),
+    };
+    let names = try!(names.iter()
+        .map(|n| symbol::intern(n).map_err(|e| error(e, args)))
+        .collect::<Result<Vec<u16>, Error>>());
+    Ok(Context::Node(arena.ast(AST::Atom(Atom::Value(Value::VecSym(names))))))
+}
+
+pub fn count<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    Ok(Context::Node(arena.ast(AST::Atom(Atom::Value(Value::Number(verb::count(unary(args)) as i64))))))
+}
+
+pub fn upsert<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    // rows or a table onto a table, a dictionary onto a dictionary
+    let r = match args {
+        &AST::Vector(ref v) if v.len() == 2 => {
+            match (&v[0], &v[1]) {
+                (&AST::Atom(Atom::Value(Value::Table(_))), _) => try!(list::concat(&v[0], &v[1])),
+                (&AST::Atom(Atom::Value(Value::Dict(_))), &AST::Atom(Atom::Value(Value::Dict(_)))) => {
+                    try!(dict::eval(Verb::Concat, &v[0], &v[1]))
                 }
-                _ => panic!("oops!"),
+                _ => return Err(error("upsert: type", args)),
             }
         }
-        _ => panic!("oops!"),
+        _ => return Err(error("upsert: rank", args)),
     };
+    Ok(Context::Node(arena.ast(r)))
+}
 
-    let mut p = i.queues.publishers().get(pub_id as usize).expect(&format!("Wrong publisher id: {}", pub_id));
-    if let Some(slot) = p.next() {
-        *slot = val;
-        p.commit();
+pub fn key<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    match dict::key(unary(args)) {
+        Some(k) => Ok(Context::Node(arena.ast(k))),
+        None => Err(error("key: not a dictionary", args)),
     }
-    Context::Node(arena.nil())
 }
 
-pub fn receive<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Context<'a> {
-    println!("Receive Args: {:?}", args);
+pub fn value<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    match dict::value(unary(args)) {
+        Some(v) => Ok(Context::Node(arena.ast(v))),
+        None => Err(error("value: not a dictionary", args)),
+    }
+}
+
+pub fn xbar<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
     match args {
-        &AST::Atom(Atom::Value(Value::Number(sub_id))) => {
-            let mut s = i.queues.subscribers().get(sub_id as usize).expect(&format!("Wrong subscriber id: {}", sub_id));
-            if let Some(slot) = s.recv() {
-                let res = *slot;
-                s.commit();
-                return Context::Node(arena.ast(AST::Atom(Atom::Value(Value::Number(res as i64)))));
+        &AST::Vector(ref v) if v.len() == 2 => Ok(Context::Node(arena.ast(try!(verb::temporal::xbar(&v[0], &v[1]))))),
+        _ => Err(error("xbar: rank", args)),
+    }
+}
+
+pub fn now<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    Ok(Context::Node(arena.ast(AST::Atom(Atom::Value(Value::Temporal(Temporal::Timestamp, temporal::now()))))))
+}
+
+pub fn peach<'a>(i: &'a mut Interpreter<'a>, args: &'a AST<'a>, arena: &'a Arena<'a>) -> Result<Context<'a>, Error> {
+    // f each x with x cut into one chunk per other core, f goes as source
+    // so it sees only its arguments there, not this workspace
+    let (f, x) = match args {
+        &AST::Vector(ref v) if v.len() == 2 => (&v[0], &v[1]),
+        _ => return Err(error("peach: rank", args)),
+    };
+    let f = match f {
+        &AST::Atom(Atom::Lambda(closure, names, body)) => {
+            // only source travels, a closure over this task's locals can't
+            let own = match names {
+                &AST::Vector(ref v) => v.iter().map(|x| x.clone()).collect::<Vec<AST>>(),
+                x => vec![x.clone()],
+            };
+            let outer = closure.map_or(vec![], |n| i.env.locals(n));
+            if let Some(n) = outer.iter().find(|&&n| !own.contains(&AST::Atom(Atom::NameInt(n))) && ast::mentions(body, n)) {
+                return Err(error(&format!("peach: lambda captures local {}", arena.name(*n)), args));
+            }
+            match kfmt::code(f, arena) {
+                Some(s) => s,
+                None => return Err(error("peach: lambda has no source form", args)),
             }
         }
-        _ => panic!("oops!"),
+        _ => return Err(error("peach: type", args)),
+    };
+    if !verb::is_list(x) {
+        return Err(error("peach: type", args));
     }
+    let xs = verb::items(x);
+    if xs.len() == 0 {
+        return Ok(Context::Node(x));
+    }
+    let workers = if i.cores > 1 { i.cores - 1 } else { 1 };
+    let size = (xs.len() + workers - 1) / workers;
+    let chunks = xs.chunks(size).map(|c| kfmt::fmt(&verb::collapse(c.to_vec()))).collect::<Vec<String>>();
 
-    Context::Node(arena.nil())
+    i.gather = vec![None; chunks.len()];
+    i.edge = Message::Peach(Peach {
+        from: i.core,
+        to: i.core,
+        task_id: i.task_id,
+        seq: 0,
+        f: f,
+        xs: chunks,
+    });
+    Ok(Context::Intercore(&i.edge))
 }
@@ -1,5 +1,8 @@
 
 use queues::publisher::Subscriber;
+use intercore::bus::TypeId;
+use commands::ast::Value;
+use commands::ipc;
 
 #[derive(PartialEq, Debug,Clone)]
 pub struct Pub {
@@ -8,6 +11,7 @@ pub struct Pub {
     pub task_id: usize,
     pub name: String,
     pub cap: usize,
+    pub kind: TypeId,
 }
 
 #[derive(PartialEq, Debug,Clone)]
@@ -22,6 +26,7 @@ pub struct Sub {
 pub struct Spawn {
     pub from: usize,
     pub to: usize,
+    pub task_id: usize,
     pub txt: String,
 }
 
@@ -31,7 +36,7 @@ pub struct AckSub {
     pub to: usize,
     pub task_id: usize,
     pub result_id: usize,
-    pub s: Subscriber<i64>,
+    pub s: Subscriber<Value>,
 }
 
 #[derive(PartialEq, Debug,Clone)]
@@ -47,6 +52,93 @@ pub struct AckSpawn {
     pub from: usize,
     pub to: usize,
     pub task_id: usize,
+    pub index: usize,
+}
+
+// wait and status on a spawned task, index is its place on the `to` core
+
+#[derive(PartialEq, Debug,Clone)]
+pub struct Await {
+    pub from: usize,
+    pub to: usize,
+    pub task_id: usize,
+    pub index: usize,
+    pub block: bool,
+}
+
+#[derive(PartialEq, Debug,Clone)]
+pub struct AckAwait {
+    pub from: usize,
+    pub to: usize,
+    pub task_id: usize,
+    pub index: usize,
+    pub block: bool,
+    pub result: Option<Result<String, String>>,
+}
+
+#[derive(PartialEq, Debug,Clone)]
+pub struct Peach {
+    pub from: usize,
+    pub to: usize,
+    pub task_id: usize,
+    pub seq: usize,
+    pub f: String,
+    pub xs: Vec<String>,
+}
+
+#[derive(PartialEq, Debug,Clone)]
+pub struct AckPeach {
+    pub from: usize,
+    pub to: usize,
+    pub task_id: usize,
+    pub seq: usize,
+    pub result: Result<String, String>,
+}
+
+// at[core;"expr"]: id pairs the value with the call still waiting for it
+
+#[derive(PartialEq, Debug,Clone)]
+pub struct Eval {
+    pub from: usize,
+    pub to: usize,
+    pub task_id: usize,
+    pub id: usize,
+    pub txt: String,
+}
+
+#[derive(PartialEq, Debug,Clone)]
+pub struct AckEval {
+    pub from: usize,
+    pub to: usize,
+    pub task_id: usize,
+    pub id: usize,
+    pub result: Result<String, String>,
+}
+
+#[derive(PartialEq, Debug,Clone)]
+pub struct Park {
+    pub from: usize,
+    pub to: usize,
+    pub task_id: usize,
+    pub sub_id: usize,
+    pub timeout: Option<u64>,
+}
+
+#[derive(PartialEq, Debug,Clone)]
+pub struct AckPark {
+    pub from: usize,
+    pub to: usize,
+    pub task_id: usize,
+    pub value: Value,
+}
+
+#[derive(PartialEq, Debug,Clone)]
+pub struct Query {
+    pub to: usize,
+    pub slot: usize,
+    pub conn: usize,
+    pub msg: u8,
+    pub txt: String,
 }
 
 #[derive(PartialEq, Debug,Clone)]
@@ -58,13 +150,32 @@ pub enum Message {
     AckSub(AckSub),
     AckPub(AckPub),
     AckSpawn(AckSpawn),
+    Await(Await),
+    AckAwait(AckAwait),
+    Peach(Peach),
+    AckPeach(AckPeach),
+    Eval(Eval),
+    AckEval(AckEval),
+    Park(Park),
+    AckPark(AckPark),
     Exec(usize, String),
+    Query(Query),
     Select(String, u16),
     QoS(u8, u8, u8),
     Halt,
     Nop,
 }
 
+impl AckAwait {
+    pub fn status(&self) -> &'static str {
+        match self.result {
+            None => "running",
+            Some(Ok(_)) => "done",
+            Some(Err(_)) => "failed",
+        }
+    }
+}
+
 impl Default for Message {
     fn default() -> Message {
         Message::Nop
@@ -73,7 +184,11 @@ impl Default for Message {
 
 impl Message {
     pub fn from_u8(b: &[u8]) -> Self {
-        //
-        Message::Nop
+        // a kdb+ IPC frame from a q client, a string is a line for the shell
+        match ipc::decode(b) {
+            Ok((_, Value::Sequence(s))) => Message::Exec(0, s),
+            Ok((_, Value::Char(c))) => Message::Exec(0, c.to_string()),
+            _ => Message::Nop,
+        }
     }
 }
@@ -1,19 +1,131 @@
 
-use queues::publisher::Publisher;
 use intercore::bus::{Channel, send};
-use intercore::message::{Message, AckPub, AckSub};
+use intercore::message::{Message, AckPub, AckSub, Peach, AckPeach, AckSpawn, Await, AckAwait, AckEval};
 use reactors::cps::CpsTask;
 use reactors::job::Job;
 use reactors::task::{Task, Context, Termination, Poll, Error};
-use reactors::scheduler::Scheduler;
+use commands::kfmt;
+use commands::ast::Value;
+use streams::verb::to_value;
+use reactors::selector::Slot;
+use reactors::scheduler::{Scheduler, Parked};
 use handle::{from_raw, into_raw, use_};
+use std::time::{Instant, Duration};
+
+fn caret(line: &str, pos: usize) -> String {
+    // pad up to the failing column, keeping tabs so the ^ lines up
+    line[..pos.min(line.len())].chars().map(|c| if c == '\t' { c } else { ' ' }).collect()
+}
+
+fn awaited(bus: &Channel, w: &Await, result: Option<Result<String, String>>) {
+    send(bus,
+         Message::AckAwait(AckAwait {
+             from: bus.id,
+             to: w.from,
+             task_id: w.task_id,
+             index: w.index,
+             block: w.block,
+             result: result,
+         }));
+}
+
+fn chunks<'a>(sched: &'a mut Scheduler<'a>, p: &Peach) {
+    // f each chunk, every chunk a task of its own so it is preempted like any other
+    let x = into_raw(sched);
+    for (k, xs) in p.xs.iter().enumerate() {
+        let src = format!("{}'({})", p.f, xs);
+        let t = from_raw(x).spawn(Job::Cps(CpsTask::new(from_raw(x).mem())),
+                                  Termination::Recursive,
+                                  Some(&src));
+        from_raw(x).chunks.insert(t.0,
+                                  Peach {
+                                      from: p.from,
+                                      to: p.to,
+                                      task_id: p.task_id,
+                                      seq: p.seq + k,
+                                      f: p.f.clone(),
+                                      xs: vec![],
+                                  });
+    }
+}
+
+pub fn answer<'a>(sched: &mut Scheduler<'a>, task: usize, p: &Poll<Context<'a>, Error>) {
+    // a finished remote task replies on its connection,
+    // a spawned one keeps its result and wakes whoever waits on it
+    let r = match *p {
+        Poll::End(Context::Node(n)) => Ok(Some(n)),
+        Poll::End(_) => Ok(None),
+        Poll::Err(Error::RuntimeError(ref e, _)) => Err(e.clone()),
+        Poll::Err(ref e) => Err(format!("{:?}", e)),
+        _ => return,
+    };
+    let mut to = None;
+    if let Some(x) = sched.remotes.iter_mut().find(|x| x.task == task && x.pending.is_some()) {
+        to = Some((x.slot, x.conn.expect("no connection"), x.pending.take().unwrap()));
+    }
+    if let Some((slot, conn, msg)) = to {
+        let v = r.and_then(|n| n.map_or(Some(Value::Nil), to_value).ok_or("type".to_string()));
+        sched.reply(slot, conn, msg, v);
+        return;
+    }
+    if let Some(c) = sched.chunks.remove(&task) {
+        // a peach chunk goes back to the task gathering them
+        send(&sched.bus,
+             Message::AckPeach(AckPeach {
+                 from: sched.bus.id,
+                 to: c.from,
+                 task_id: c.task_id,
+                 seq: c.seq,
+                 result: r.map(|n| n.map_or("::".to_string(), kfmt::fmt)),
+             }));
+        sched.release(task);
+        return;
+    }
+    if let Some(e) = sched.calls.remove(&task) {
+        // an at[] task answers its caller and is gone
+        send(&sched.bus,
+             Message::AckEval(AckEval {
+                 from: sched.bus.id,
+                 to: e.from,
+                 task_id: e.task_id,
+                 id: e.id,
+                 result: r.map(|n| n.map_or("::".to_string(), kfmt::fmt)),
+             }));
+        sched.release(task);
+        return;
+    }
+    match sched.tasks.get(task) {
+        Some(t) if t.1 == Termination::Recursive && !sched.results.contains_key(&task) => (),
+        _ => return,
+    }
+    let r = r.map(|n| n.map_or("::".to_string(), kfmt::fmt));
+    let t = into_raw(sched.tasks.get_mut(task).expect("no task"));
+    from_raw(t).0.exec(None);
+    for w in sched.awaits.iter().filter(|w| w.index == task) {
+        awaited(&sched.bus, w, Some(r.clone()));
+    }
+    // a result someone waited for is collected, otherwise it stays for wait or status
+    if sched.awaits.iter().any(|w| w.index == task) {
+        sched.awaits.retain(|w| w.index != task);
+        sched.release(task);
+    } else {
+        sched.results.insert(task, r);
+    }
+}
 
 // The InterCore Delivery by Adressee
 
 pub fn delivery<'a>(context: Context<'a>, sched: &'a mut Scheduler<'a>) -> Poll<Context<'a>, Error> {
     match context {
         Context::NodeAck(task, res) => {
-            use_(sched).tasks.get_mut(task).expect("no shell").0.poll(context, sched)
+            let p = use_(sched).tasks.get_mut(task).expect("no shell").0.poll(context, use_(sched));
+            answer(use_(sched), task, &p);
+            p
+        }
+        Context::Cont(task, _) => {
+            let p = use_(sched).tasks.get_mut(task).expect("no task").0.poll(context, use_(sched));
+            answer(use_(sched), task, &p);
+            p
         }
         Context::Node(..) => Poll::End(context),
         _ => Poll::Yield(Context::Nil)
@@ -29,30 +141,120 @@ pub fn intercore<'a>(sched: &'a mut Scheduler<'a>, message: Option<&'a Message>,
         Some(&Message::Spawn(ref v)) if v.to == bus.id => {
             println!("InterCore Spawn {:?} {:?}", bus.id, v);
             let x = into_raw(sched);
-            from_raw(x).spawn(Job::Cps(CpsTask::new(sched.mem())),
-                              Termination::Recursive,
-                              Some(&v.txt));
+            let t = from_raw(x).spawn(Job::Cps(CpsTask::new(sched.mem())),
+                                      Termination::Recursive,
+                                      Some(&v.txt));
+            send(bus,
+                 Message::AckSpawn(AckSpawn {
+                     from: bus.id,
+                     to: v.from,
+                     task_id: v.task_id,
+                     index: t.0,
+                 }));
+            Context::Nil
+        }
+
+        Some(&Message::AckSpawn(ref a)) if a.to == bus.id => {
+            println!("InterCore AckSpawn {:?} {:?}", bus.id, a);
+            Context::Cont(a.task_id, message.unwrap())
+        }
+
+        Some(&Message::Await(ref w)) if w.to == bus.id => {
+            println!("InterCore Await {:?} {:?}", bus.id, w);
+            let spawned = match sched.tasks.get(w.index) {
+                Some(t) => t.1 == Termination::Recursive && !sched.free.contains(&w.index) &&
+                           !sched.calls.contains_key(&w.index) && !sched.chunks.contains_key(&w.index),
+                None => false,
+            };
+            match sched.results.get(&w.index).cloned() {
+                Some(r) => {
+                    awaited(bus, w, Some(r));
+                    if w.block {
+                        sched.release(w.index);
+                    }
+                }
+                None if !spawned => {
+                    awaited(bus, w, Some(Err(format!("wait: no task {}", w.index))))
+                }
+                None if w.block => sched.awaits.push(w.clone()),
+                None => awaited(bus, w, None),
+            }
             Context::Nil
         }
 
+        Some(&Message::AckAwait(ref a)) if a.to == bus.id => {
+            println!("InterCore AckAwait {:?} {:?}", bus.id, a.index);
+            Context::Cont(a.task_id, message.unwrap())
+        }
+
         Some(&Message::QoS(task, bus, io)) => {
             println!("InterCore QoS {:?} {:?} {:?}", task, bus, io);
             Context::Nil
         }
 
         Some(&Message::Exec(ref task, ref cmd)) if 0 == bus.id => {
+            // a new line drops whatever rcv the shell was parked on
+            sched.parked.retain(|p| p.task != *task);
             let mut t = into_raw(sched.tasks.get_mut(task.clone()).expect("no shell"));
             from_raw(t).0.exec(Some(cmd));
             let x = from_raw(t).0.poll(Context::Nil, use_(sched));
-            println!("InterCore Exec {:?} {:?} {:?}", task, cmd, x);
+            match x {
+                Poll::Err(Error::RuntimeError(ref e, pos)) => {
+                    println!("'{}", e);
+                    if let Some(p) = pos {
+                        println!("  {}\n  {}^", cmd, caret(cmd, p));
+                    }
+                }
+                Poll::End(Context::Node(n)) => println!("{}", kfmt::show(n)),
+                ref x => println!("InterCore Exec {:?} {:?} {:?}", task, cmd, x),
+            }
+            Context::Nil
+        }
+
+        Some(&Message::Query(ref q)) if q.to == bus.id => {
+            println!("InterCore Query {:?} {:?}", q.conn, q.txt);
+            let x = into_raw(sched);
+            let task = from_raw(x).remote(Slot(q.slot), q.conn);
+            if let Some(r) = from_raw(x).remotes.iter_mut().find(|r| r.task == task) {
+                r.pending = Some(q.msg);
+            }
+            from_raw(x).parked.retain(|p| p.task != task);
+            let t = into_raw(from_raw(x).tasks.get_mut(task).expect("no task"));
+            from_raw(t).0.exec(Some(&q.txt));
+            let p = from_raw(t).0.poll(Context::Nil, use_(sched));
+            answer(use_(sched), task, &p);
+            Context::Nil
+        }
+
+        Some(&Message::Eval(ref e)) if e.to == bus.id => {
+            println!("InterCore Eval {:?} {:?}", bus.id, e.id);
+            // a task of its own, preempted and free to call other cores like a spawned one
+            let x = into_raw(sched);
+            let t = from_raw(x).spawn(Job::Cps(CpsTask::new(sched.mem())),
+                                      Termination::Recursive,
+                                      Some(&e.txt));
+            from_raw(x).calls.insert(t.0, e.clone());
+            Context::Nil
+        }
+
+        Some(&Message::AckEval(ref a)) if a.to == bus.id => {
+            println!("InterCore AckEval {:?} {:?}", bus.id, a.id);
+            Context::Cont(a.task_id, message.unwrap())
+        }
+
+        Some(&Message::Park(ref p)) if p.to == bus.id => {
+            println!("InterCore Park {:?} {:?}", bus.id, p);
+            sched.parked.push(Parked {
+                task: p.task_id,
+                sub_id: p.sub_id,
+                deadline: p.timeout.map(|ms| Instant::now() + Duration::from_millis(ms)),
+            });
             Context::Nil
         }
 
         Some(&Message::Pub(ref p)) if p.to == p.from && p.to == bus.id => {
             println!("Local Pub {:?} {:?}", bus.id, p);
-            sched.queues.publishers().push(Publisher::with_capacity(p.cap));
-            let mut t = use_(sched).tasks.get_mut(p.task_id).expect("no task");
-            let id = use_(sched).queues.publishers().len() - 1;
+            let id = sched.queues.publish(p.cap, p.kind.clone());
             Context::NodeAck(p.task_id, id)
         }
 
@@ -76,15 +278,13 @@ pub fn intercore<'a>(sched: &'a mut Scheduler<'a>, message: Option<&'a Message>,
         }
 
         Some(&Message::Pub(ref p)) if p.to == bus.id => {
-            let mut pb = Publisher::with_capacity(p.cap);
-            sched.queues.publishers().push(pb);
             println!("InterCore Pub {:?} {:?}", bus.id, p);
             send(bus,
                  Message::AckPub(AckPub {
                      from: bus.id,
                      to: p.from,
                      task_id: p.task_id,
-                     result_id: sched.queues.publishers().len() - 1,
+                     result_id: sched.queues.publish(p.cap, p.kind.clone()),
                  }));
             Context::Nil
         }
@@ -128,6 +328,39 @@ pub fn intercore<'a>(sched: &'a mut Scheduler<'a>, message: Option<&'a Message>,
             Context::NodeAck(a.task_id, sub_index)
         }
 
+        Some(&Message::Peach(ref p)) if p.to == p.from && p.to == bus.id => {
+            println!("Local Peach {:?} {:?}", bus.id, p.xs.len());
+            let cores = bus.subscribers.len();
+            for (k, x) in p.xs.iter().enumerate() {
+                // chunks go round the other cores, a lone core runs them itself
+                let chunk = Peach {
+                    from: bus.id,
+                    to: if cores > 1 { (bus.id + 1 + k % (cores - 1)) % cores } else { bus.id },
+                    task_id: p.task_id,
+                    seq: k,
+                    f: p.f.clone(),
+                    xs: vec![x.clone()],
+                };
+                if chunk.to == bus.id {
+                    chunks(use_(sched), &chunk);
+                } else {
+                    send(bus, Message::Peach(chunk));
+                }
+            }
+            Context::Nil
+        }
+
+        Some(&Message::Peach(ref p)) if p.to == bus.id => {
+            println!("InterCore Peach {:?} {:?}", bus.id, p.seq);
+            chunks(sched, p);
+            Context::Nil
+        }
+
+        Some(&Message::AckPeach(ref a)) if a.to == bus.id => {
+            println!("InterCore AckPeach {:?} {:?}", bus.id, a.seq);
+            Context::Cont(a.task_id, message.unwrap())
+        }
+
         Some(x) => {
             // println!("Test {:?}", x);
             Context::Nil
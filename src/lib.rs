@@ -40,6 +40,7 @@ extern crate net2;
 extern crate nix;
 extern crate alloc;
 extern crate time;
+extern crate lalrpop_util;
 
 #[macro_use]
 pub mod session_types;
@@ -53,6 +54,7 @@ pub mod streams;
 pub mod queues;
 pub mod handle;
 pub mod sys;
+pub mod tables;
 
 #[macro_use]
 extern crate libc;
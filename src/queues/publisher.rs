@@ -139,6 +139,15 @@ impl<T: Default> Publisher<T> {
     }
 
     pub fn next_n(&self, n: usize) -> Option<&mut [T]> {
+        self.claim(n).map(|seq| unsafe { self.ring.get_slice_mut(seq, n) })
+    }
+
+    pub fn next_n_split(&self, n: usize) -> Option<(&mut [T], &mut [T])> {
+        // n slots in ring order, the second part is what wrapped to the start
+        self.claim(n).map(|seq| unsafe { self.ring.get_slices_mut(seq, n) })
+    }
+
+    fn claim(&self, n: usize) -> Option<Sequence> {
         let head = self.head();
         let cursors = self.cursors();
         let delta = n as Sequence;
@@ -146,6 +155,9 @@ impl<T: Default> Publisher<T> {
         let next_seq = curr_seq + delta;
         let cap = self.ring.cap();
 
+        if n > cap {
+            return None;
+        }
         if head.get_cache() + cap < next_seq {
             let mut min_tail = MAX;
             for tail in cursors.iter().skip(1) {
@@ -157,8 +169,7 @@ impl<T: Default> Publisher<T> {
             }
         }
         self.next_seq_cache.set(next_seq);
-        let slice = unsafe { self.ring.get_slice_mut(curr_seq, n) };
-        Some(slice)
+        Some(curr_seq)
     }
 
     pub fn commit(&self) {
@@ -235,6 +246,10 @@ impl<T: Default> Subscriber<T> {
         }
     }
 
+    pub fn follows(&self, p: &Publisher<T>) -> bool {
+        &*self.ring as *const RingBuffer<T> == &*p.ring as *const RingBuffer<T>
+    }
+
     pub fn recv(&self) -> Option<&T> {
         self.recv_n(1).map(|vs| &vs[0])
     }
@@ -263,7 +278,14 @@ impl<T: Default> Subscriber<T> {
     }
 
     pub fn recv_all(&self) -> Option<&[T]> {
+        self.pending().map(|(seq, n)| unsafe { self.ring.get_slice(seq, n) })
+    }
+
+    pub fn recv_all_split(&self) -> Option<(&[T], &[T])> {
+        self.pending().map(|(seq, n)| unsafe { self.ring.get_slices(seq, n) })
+    }
 
+    fn pending(&self) -> Option<(Sequence, usize)> {
         let tail = self.tail(self.token);
         let head = self.tail(0);
 
@@ -276,8 +298,7 @@ impl<T: Default> Subscriber<T> {
             return None;
         } else {
             self.next_seq_cache.set(head_seq);
-            let slice = unsafe { self.ring.get_slice(tail_seq, head_seq - tail_seq) };
-            Some(slice)
+            Some((tail_seq, head_seq - tail_seq))
         }
     }
 
@@ -391,6 +412,46 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_publisher_split_wraps() {
+        let publisher: Publisher<u64> = Publisher::with_capacity(8);
+        let subscriber = publisher.subscribe();
+
+        match publisher.next_n(6) {
+            Some(vs) => {
+                for v in vs.iter_mut() {
+                    *v = 0;
+                }
+                publisher.commit();
+            }
+            None => assert!(false, "Queue was empty but refused six slots!"),
+        }
+        assert_eq!(subscriber.recv_all().map(|vs| vs.len()), Some(6));
+        subscriber.commit();
+
+        match publisher.next_n_split(5) {
+            Some((a, b)) => {
+                assert_eq!((a.len(), b.len()), (2, 3));
+                for (i, v) in a.iter_mut().chain(b.iter_mut()).enumerate() {
+                    *v = i as u64 + 1;
+                }
+                publisher.commit();
+            }
+            None => assert!(false, "Queue had room for five but refused them!"),
+        }
+        assert!(publisher.next_n_split(9).is_none());
+
+        match subscriber.recv_all_split() {
+            Some((a, b)) => {
+                assert_eq!(a, &[1, 2u64]);
+                assert_eq!(b, &[3, 4, 5u64]);
+                subscriber.commit();
+            }
+            None => assert!(false, "Queue was not empty but recv_all_split() returned nothing!"),
+        }
+        assert!(subscriber.recv_all_split().is_none());
+    }
+
     #[derive(PartialEq)]
     enum Proto {
         A,
@@ -127,6 +127,23 @@ impl<T: Default> RingBuffer<T> {
         transmute(from_raw_parts_mut(self.buffer.ptr().offset((pos & self.mask) as isize), len))
     }
 
+    #[inline]
+    pub unsafe fn get_slices(&self, pos: usize, len: usize) -> (&[T], &[T]) {
+        // a plain ring isn't mirrored: a run past the end goes on from the start
+        let at = pos & self.mask;
+        let head = std::cmp::min(len, self.mask + 1 - at);
+        (transmute(from_raw_parts(self.buffer.ptr().offset(at as isize), head)),
+         transmute(from_raw_parts(self.buffer.ptr(), len - head)))
+    }
+
+    #[inline]
+    pub unsafe fn get_slices_mut(&self, pos: usize, len: usize) -> (&mut [T], &mut [T]) {
+        let at = pos & self.mask;
+        let head = std::cmp::min(len, self.mask + 1 - at);
+        (transmute(from_raw_parts_mut(self.buffer.ptr().offset(at as isize), head)),
+         transmute(from_raw_parts_mut(self.buffer.ptr(), len - head)))
+    }
+
     #[inline]
     pub unsafe fn take(&self, pos: usize) -> T {
         ptr::read(self.buffer.ptr().offset((pos & self.mask) as isize))
@@ -1,14 +1,17 @@
 use reactors::task::{Task, Context, Poll, Error};
 use streams::interpreter::*;
-use commands::ast::{Atom, AST};
+use commands::ast::{self, Atom, AST};
 use handle::*;
 use intercore::bus::{send, Memory};
+use intercore::message::Message;
 use reactors::scheduler::Scheduler;
 
 pub struct CpsTask<'a> {
     pub interpreter: Interpreter<'a>,
     pub ast: Option<&'a AST<'a>>,
+    fault: Option<ast::Error>,
     task_id: usize,
+    waiting: bool,
 }
 
 impl<'a> CpsTask<'a> {
@@ -16,7 +19,9 @@ impl<'a> CpsTask<'a> {
         CpsTask {
             interpreter: Interpreter::new(mem_ptr).unwrap(),
             ast: None,
+            fault: None,
             task_id: 0,
+            waiting: false,
         }
     }
 
@@ -34,6 +39,7 @@ impl<'a> CpsTask<'a> {
                     match sched {
                         Some(ref s) => {
                             send(&s.bus, msg.clone());
+                            from_raw(x).waiting = true;
                             return Poll::Yield(ic.clone());
                         }
                         None => Poll::Yield(Context::Nil),
@@ -43,13 +49,13 @@ impl<'a> CpsTask<'a> {
                 }
             }
             Ok(r) => return Poll::End(Context::Node(r)),
-            Err(e) => return Poll::Err(Error::RuntimeError),
+            Err(e) => return Poll::Err(Error::RuntimeError(e.message(), from_raw(x).interpreter.fault)),
         }
     }
 }
 
 impl<'a> Task<'a> for CpsTask<'a> {
-    fn init(&'a mut self, input: Option<&'a str>, task_id: usize) {
+    fn init(&'a mut self, input: Option<&str>, task_id: usize) {
         let (s1, s2) = split(self);
         s1.interpreter.define_primitives();
         s2.interpreter.task_id = task_id;
@@ -57,29 +63,54 @@ impl<'a> Task<'a> for CpsTask<'a> {
         match input {
             Some(i) => {
                 let s = i.to_string();
-                s2.ast = Some(s2.interpreter.parse(&s));
+                match s2.interpreter.parse(&s) {
+                    Ok(a) => s2.ast = Some(a),
+                    Err(e) => s2.fault = Some(e),
+                }
             }
             None => s2.ast = None,
         }
     }
 
     fn exec(&'a mut self, input: Option<&'a str>) {
+        self.waiting = false;
         match input {
             Some(i) => {
                 let s = i.to_string();
-                let parse = self.interpreter.parse(&s);
-                self.ast = Some(parse);
+                let (s1, s2) = split(self);
+                // the previous line is done with, keep only what the workspace references
+                s1.interpreter.gc(&[]);
+                match s1.interpreter.parse(&s) {
+                    Ok(a) => s2.ast = Some(a),
+                    Err(e) => {
+                        s2.ast = None;
+                        s2.fault = Some(e);
+                    }
+                }
             }
             None => self.ast = None,
         }
     }
 
     fn poll(&'a mut self, c: Context<'a>, sched: &'a Scheduler<'a>) -> Poll<Context<'a>, Error> {
+        if let Some(ast::Error::ParseError { desc, pos }) = self.fault.take() {
+            return Poll::Err(Error::RuntimeError(desc, Some(pos)));
+        }
         match self.ast {
             Some(a) => {
                 match c.clone() {
                     Context::Node(n) => self.run(n, c, Some(sched)),
-                    Context::NodeAck(_, n) => self.run(a, c, Some(sched)),
+                    // a late answer to a call given up on leaves the task waiting
+                    Context::Cont(_, &Message::AckEval(ref e)) if e.id != self.interpreter.calls => {
+                        Poll::Yield(Context::Nil)
+                    }
+                    Context::NodeAck(..) |
+                    Context::Cont(..) => {
+                        self.waiting = false;
+                        self.run(a, c, Some(sched))
+                    }
+                    // only the reply moves on a task that asked another core
+                    Context::Nil if self.waiting => Poll::Yield(Context::Nil),
                     Context::Nil => self.run(a, c, Some(sched)),
                     _ => Poll::Err(Error::WrongContext),
                 }
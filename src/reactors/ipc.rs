@@ -0,0 +1,264 @@
+// kdb+ IPC listener: q processes connect with hopen and talk to the kernel.
+// After the capability handshake every frame is decoded and queued;
+// the scheduler turns frames into Query messages and answers sync ones
+// on the same connection when the connection's task is done.
+
+use std::net::SocketAddr;
+use io::tcp::{TcpListener, TcpStream};
+use io::token::Token;
+use std::io::{self, Read, Write, ErrorKind};
+use std::collections::HashMap;
+use std::fmt::Arguments;
+use std::thread;
+use reactors::selector::{Select, Slot};
+use reactors::system::IO;
+use commands::ast::{Value, Error};
+use commands::{ipc, kfmt, symbol};
+
+const BUF_SIZE: usize = 2048;
+
+// the highest capability we speak: compression and timestamps
+const CAPABILITY: u8 = 3;
+
+// a peer past either of these is dropped, so a client buffer never holds
+// more than a handshake or one partial frame
+const HANDSHAKE_MAX: usize = 1024;
+const FRAME_MAX: usize = 1 << 26;
+
+pub enum Event {
+    Frame(usize, u8, Result<Value, Error>),
+    Closed(usize),
+}
+
+#[derive(Debug,Clone,Copy)]
+pub struct Remote {
+    pub slot: Slot,
+    pub conn: Option<usize>,
+    pub task: usize,
+    pub pending: Option<u8>,
+}
+
+struct IpcClient {
+    sock: TcpStream,
+    addr: SocketAddr,
+    cap: Option<u8>,
+    buf: Vec<u8>,
+}
+
+pub struct IpcServer {
+    listen_token: Token,
+    slot: Slot,
+    tcp: TcpListener,
+    clients: HashMap<Token, IpcClient>,
+    events: Vec<Event>,
+    internal_buf: [u8; BUF_SIZE],
+}
+
+pub fn query(v: &Value) -> Result<String, String> {
+    // q sends either a string or (`f;x;y), the latter is f[x;y]
+    match *v {
+        Value::Sequence(ref s) => Ok(s.clone()),
+        Value::Char(c) => Ok(c.to_string()),
+        Value::List(ref x) if x.len() > 0 => {
+            let f = match x[0] {
+                Value::SymbolInt(s) => symbol::name(s),
+                Value::Sequence(ref s) => format!("({})", s),
+                _ => return Err("type".to_string()),
+            };
+            match x.len() {
+                1 => Ok(format!("{}[]", f)),
+                _ => Ok(format!("{}[{}]", f, x[1..].iter().map(kfmt::value).collect::<Vec<String>>().join(";"))),
+            }
+        }
+        _ => Err("type".to_string()),
+    }
+}
+
+impl IpcServer {
+    pub fn new(addr: &SocketAddr) -> Self {
+        let t = TcpListener::bind(&addr).unwrap();
+        IpcServer {
+            listen_token: Token(0),
+            slot: Slot(0),
+            tcp: t,
+            clients: HashMap::with_capacity(256),
+            events: Vec::new(),
+            internal_buf: [0u8; BUF_SIZE],
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.local_addr()
+    }
+
+    pub fn events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+
+    #[inline]
+    fn reg_incoming(&mut self, io: &mut IO) {
+        // edge triggered: take every pending connection
+        loop {
+            match self.tcp.accept() {
+                Ok((s, a)) => {
+                    let t = io.register(&s, self.slot);
+                    self.clients.insert(t,
+                                        IpcClient {
+                                            sock: s,
+                                            addr: a,
+                                            cap: None,
+                                            buf: Vec::new(),
+                                        });
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("IpcError: {:?}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn handshake(c: &mut IpcClient) -> bool {
+        // "user:password" then the capability byte and a zero
+        match c.buf.iter().position(|b| *b == 0) {
+            Some(n) => {
+                let cap = if n > 0 && c.buf[n - 1] < 32 {
+                    c.buf[n - 1].min(CAPABILITY)
+                } else {
+                    0
+                };
+                c.buf.drain(..n + 1);
+                c.cap = Some(cap);
+                send(&mut c.sock, &[cap]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[inline]
+    fn frames(t: Token, c: &mut IpcClient, events: &mut Vec<Event>) -> bool {
+        while c.buf.len() >= 8 {
+            let (msg, n) = match ipc::header(&c.buf) {
+                Ok((msg, _, n)) if c.buf[0] < 2 && n > 8 && n <= FRAME_MAX => (msg, n),
+                _ => return false,
+            };
+            if n > c.buf.len() {
+                return true;
+            }
+            let frame = c.buf.drain(..n).collect::<Vec<u8>>();
+            events.push(Event::Frame(t.0, msg, ipc::decode(&frame).map(|(_, v)| v)));
+        }
+        true
+    }
+
+    #[inline]
+    fn parse(t: Token, c: &mut IpcClient, events: &mut Vec<Event>) -> bool {
+        // false when the peer can't be talking q
+        if c.cap.is_none() && !Self::handshake(c) {
+            return c.buf.len() <= HANDSHAKE_MAX;
+        }
+        Self::frames(t, c, events)
+    }
+
+    #[inline]
+    fn read_incoming(&mut self, t: Token) {
+        let mut closed = false;
+        match self.clients.get_mut(&t) {
+            Some(c) => {
+                loop {
+                    match c.sock.read(&mut self.internal_buf) {
+                        Ok(0) => {
+                            closed = true;
+                            break;
+                        }
+                        Ok(n) => c.buf.extend_from_slice(&self.internal_buf[..n]),
+                        Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(_) => {
+                            closed = true;
+                            break;
+                        }
+                    }
+                    // take frames as they come in, not once the socket is drained
+                    if !Self::parse(t, c, &mut self.events) {
+                        println!("IpcError: bad handshake or frame from {:?}", c.addr);
+                        closed = true;
+                        break;
+                    }
+                }
+            }
+            None => return,
+        }
+        if closed {
+            self.clients.remove(&t);
+            self.events.push(Event::Closed(t.0));
+        }
+    }
+
+    pub fn reply(&mut self, conn: usize, r: Result<Value, String>) {
+        if let Some(c) = self.clients.get_mut(&Token(conn)) {
+            let compress = c.cap.unwrap_or(0) > 0;
+            let b = match r {
+                Ok(v) => {
+                    match ipc::encode(&v, ipc::RESPONSE, compress) {
+                        Ok(b) => b,
+                        Err(e) => ipc::fault(&e.message(), ipc::RESPONSE),
+                    }
+                }
+                Err(e) => ipc::fault(&e, ipc::RESPONSE),
+            };
+            send(&mut c.sock, &b);
+        }
+    }
+}
+
+fn send(sock: &mut TcpStream, b: &[u8]) {
+    // the socket is non-blocking, keep at it until the whole frame is out
+    let mut at = 0;
+    while at < b.len() {
+        match sock.write(&b[at..]) {
+            Ok(n) => at += n,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => thread::yield_now(),
+            Err(_) => return,
+        }
+    }
+}
+
+impl<'a> Select<'a> for IpcServer {
+    fn init(&mut self, io: &mut IO, s: Slot) {
+        let t = io.register(&self.tcp, s);
+        self.listen_token = t;
+        self.slot = s;
+    }
+
+    fn select(&'a mut self, io: &'a mut IO, t: Token, buf: &mut [u8]) -> usize {
+        if t == self.listen_token {
+            self.reg_incoming(io);
+        } else {
+            self.read_incoming(t);
+        }
+        0
+    }
+
+    fn finalize(&mut self) {
+        println!("Bye!");
+    }
+}
+
+impl Write for IpcServer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(1)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+    fn write_fmt(&mut self, fmt: Arguments) -> io::Result<()> {
+        Ok(())
+    }
+}
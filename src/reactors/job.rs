@@ -17,7 +17,7 @@ impl<'a> Job<'a> {
 }
 
 impl<'a> Task<'a> for Job<'a> {
-    fn init(&'a mut self, input: Option<&'a str>, task_id: usize) {
+    fn init(&'a mut self, input: Option<&str>, task_id: usize) {
         self.unwrap().init(input, task_id)
     }
     fn exec(&'a mut self, input: Option<&'a str>) {
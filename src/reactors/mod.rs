@@ -5,4 +5,5 @@ pub mod cps;
 pub mod scheduler;
 pub mod task;
 pub mod ws;
+pub mod ipc;
 pub mod job;
\ No newline at end of file
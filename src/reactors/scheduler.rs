@@ -4,21 +4,40 @@ use reactors::system::{IO, Async};
 use reactors::cps::CpsTask;
 use intercore::message::*;
 use intercore::bus::{Memory, Channel, send};
-use intercore::server::{intercore, delivery};
+use intercore::server::{intercore, delivery, answer};
 use queues::publisher::Publisher;
 use std::{thread, time};
-use handle::{from_raw, into_raw, UnsafeShared};
+use handle::{from_raw, into_raw, use_, UnsafeShared};
 use reactors::console::Console;
-use reactors::selector::Selector;
+use reactors::selector::{Selector, Slot};
+use reactors::ipc::{self, IpcServer, Event, Remote};
+use commands::ast::Value;
+use commands::ipc::SYNC;
+use std::net::SocketAddr;
+use std::collections::HashMap;
+use std::time::Instant;
 use std::str;
 
-const TASKS_MAX_CNT: usize = 256;
+#[derive(Debug,Clone,Copy)]
+pub struct Parked {
+    pub task: usize,
+    pub sub_id: usize,
+    pub deadline: Option<Instant>,
+}
 
 pub struct Scheduler<'a> {
     pub tasks: Vec<T3<Job<'a>>>,
     pub bus: Channel,
     pub queues: Memory,
     pub io: IO,
+    pub listeners: Vec<Slot>,
+    pub remotes: Vec<Remote>,
+    pub results: HashMap<usize, Result<String, String>>,
+    pub awaits: Vec<Await>,
+    pub parked: Vec<Parked>,
+    pub calls: HashMap<usize, Eval>,
+    pub chunks: HashMap<usize, Peach>,
+    pub free: Vec<usize>,
 }
 
 impl<'a> Scheduler<'a> {
@@ -31,29 +50,64 @@ impl<'a> Scheduler<'a> {
             subscribers: Vec::new(),
         };
         Scheduler {
-            tasks: Vec::with_capacity(TASKS_MAX_CNT),
+            tasks: Vec::new(),
             bus: chan,
             io: IO::new(),
             queues: Memory::new(),
+            listeners: Vec::new(),
+            remotes: Vec::new(),
+            results: HashMap::new(),
+            awaits: Vec::new(),
+            parked: Vec::new(),
+            calls: HashMap::new(),
+            chunks: HashMap::new(),
+            free: Vec::new(),
         }
     }
 
     pub fn with_channel2(channel: Channel) -> Self {
         Scheduler {
-            tasks: Vec::with_capacity(TASKS_MAX_CNT),
+            tasks: Vec::new(),
             bus: channel,
             io: IO::new(),
             queues: Memory::new(),
+            listeners: Vec::new(),
+            remotes: Vec::new(),
+            results: HashMap::new(),
+            awaits: Vec::new(),
+            parked: Vec::new(),
+            calls: HashMap::new(),
+            chunks: HashMap::new(),
+            free: Vec::new(),
         }
     }
 
-    pub fn spawn(&'a mut self, t: Job<'a>, l: Termination, input: Option<&'a str>) -> TaskId {
-        let last = self.tasks.len();
-        self.tasks.push(T3(t, l));
-        self.tasks.last_mut().expect("Scheduler: can't retrieve a task.").0.init(input, last);
+    pub fn spawn(&'a mut self, t: Job<'a>, l: Termination, input: Option<&str>) -> TaskId {
+        // a released slot is taken before the task list grows
+        let last = match self.free.pop() {
+            Some(k) => {
+                self.tasks[k] = T3(t, l);
+                k
+            }
+            None => {
+                self.tasks.push(T3(t, l));
+                self.tasks.len() - 1
+            }
+        };
+        self.tasks.get_mut(last).expect("Scheduler: can't retrieve a task.").0.init(input, last);
         TaskId(last, self.bus.id)
     }
 
+    pub fn release(&mut self, i: usize) {
+        // the task is done with, its slot goes to the next spawn
+        self.results.remove(&i);
+        self.calls.remove(&i);
+        self.chunks.remove(&i);
+        if !self.free.contains(&i) {
+            self.free.push(i);
+        }
+    }
+
     pub fn exec(&'a mut self, t: TaskId, input: Option<&'a str>) {
         self.tasks.get_mut(t.0).expect("Scheduler: can't retrieve a task.").0.exec(input);
     }
@@ -79,15 +133,139 @@ impl<'a> Scheduler<'a> {
         }
     }
 
+    pub fn listen(&mut self, addr: &SocketAddr) -> SocketAddr {
+        let slot = self.io.spawn(Selector::Ipc(IpcServer::new(addr)));
+        self.listeners.push(slot);
+        match self.io.selector(slot) {
+            Some(&mut Selector::Ipc(ref q)) => q.local_addr().expect("IPC: no local address."),
+            _ => *addr,
+        }
+    }
+
+    pub fn remote(&'a mut self, slot: Slot, conn: usize) -> usize {
+        // a connection keeps its task, closed connections hand theirs on
+        if let Some(r) = self.remotes.iter().find(|r| r.conn == Some(conn)) {
+            return r.task;
+        }
+        if let Some(r) = self.remotes.iter_mut().find(|r| r.conn.is_none()) {
+            r.slot = slot;
+            r.conn = Some(conn);
+            return r.task;
+        }
+        let x = into_raw(self);
+        let t = from_raw(x).spawn(Job::Cps(CpsTask::new(self.mem())), Termination::Corecursive, None);
+        from_raw(x).remotes.push(Remote {
+            slot: slot,
+            conn: Some(conn),
+            task: t.0,
+            pending: None,
+        });
+        t.0
+    }
+
+    pub fn reply(&mut self, slot: Slot, conn: usize, msg: u8, r: Result<Value, String>) {
+        // async messages are never answered, errors included
+        if msg != SYNC {
+            return;
+        }
+        if let Some(&mut Selector::Ipc(ref mut q)) = self.io.selector(slot) {
+            q.reply(conn, r);
+        }
+    }
+
+    pub fn poll_ipc(&mut self) {
+        let x = into_raw(self);
+        for slot in &from_raw(x).listeners {
+            let events = match from_raw(x).io.selector(*slot) {
+                Some(&mut Selector::Ipc(ref mut q)) => q.events(),
+                _ => continue,
+            };
+            for e in events {
+                match e {
+                    Event::Frame(conn, msg, Ok(v)) => {
+                        match ipc::query(&v) {
+                            Ok(txt) => {
+                                send(&self.bus,
+                                     Message::Query(Query {
+                                         to: self.bus.id,
+                                         slot: slot.0,
+                                         conn: conn,
+                                         msg: msg,
+                                         txt: txt,
+                                     }))
+                            }
+                            Err(e) => self.reply(*slot, conn, msg, Err(e)),
+                        }
+                    }
+                    Event::Frame(conn, msg, Err(e)) => self.reply(*slot, conn, msg, Err(e.message())),
+                    Event::Closed(conn) => {
+                        for r in from_raw(x).remotes.iter_mut().filter(|r| r.conn == Some(conn)) {
+                            r.conn = None;
+                            r.pending = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     pub fn hibernate(&mut self) {
         thread::sleep(time::Duration::from_millis(10)); // Green Peace
     }
 
-    fn poll_tasks(&mut self) {
+    fn wake(&mut self) {
+        // a parked rcv goes on once its subscriber has an item or its deadline is gone
         let a = into_raw(self);
+        let mut k = 0;
+        while k < from_raw(a).parked.len() {
+            let p = from_raw(a).parked[k];
+            let item = match from_raw(a).queues.subscribers().get(p.sub_id) {
+                Some(s) => {
+                    match s.recv() {
+                        Some(v) => {
+                            let v = v.clone();
+                            s.commit();
+                            Some(v)
+                        }
+                        None => None,
+                    }
+                }
+                None => Some(Value::Nil),
+            };
+            let value = match item {
+                Some(v) => v,
+                None if p.deadline.map_or(false, |d| Instant::now() >= d) => Value::Nil,
+                None => {
+                    k += 1;
+                    continue;
+                }
+            };
+            from_raw(a).parked.remove(k);
+            let mut m = Message::AckPark(AckPark {
+                from: from_raw(a).bus.id,
+                to: from_raw(a).bus.id,
+                task_id: p.task,
+                value: value,
+            });
+            let r = from_raw(a).tasks[p.task].0.poll(Context::Cont(p.task, use_(&mut m)), from_raw(a));
+            answer(from_raw(a), p.task, &r);
+        }
+    }
+
+    pub fn poll_tasks(&mut self) {
+        let a = into_raw(self);
+        from_raw(a).wake();
         let l = from_raw(a).tasks.len();
-        for i in 1..l {
-            from_raw(a).tasks[i].0.poll(Context::Nil, from_raw(a));
+        for i in 0..l {
+            // the shell runs when asked, a remote task while its query is unanswered,
+            // finished spawned ones are only kept for their result
+            let asked = from_raw(a).remotes.iter().any(|r| r.task == i && r.pending.is_some());
+            if (from_raw(a).tasks[i].1 == Termination::Corecursive && !asked) || from_raw(a).results.contains_key(&i) ||
+               from_raw(a).free.contains(&i) {
+                continue;
+            }
+            let p = from_raw(a).tasks[i].0.poll(Context::Nil, from_raw(a));
+            answer(from_raw(a), i, &p);
         }
     }
 
@@ -111,6 +289,7 @@ impl<'a> Scheduler<'a> {
                 Async::Ready((_, buf)) => self.handle_shell(from_raw(x).io.cmd(buf), shell),
                 _ => (),
             }
+            self.poll_ipc();
             self.hibernate();
             self.poll_tasks();
         }
@@ -62,6 +62,10 @@ impl<'a> IO {
         slot
     }
 
+    pub fn selector(&mut self, s: Slot) -> Option<&mut Selector> {
+        self.selectors.get_mut(s.0)
+    }
+
     pub fn write(&mut self, s: Slot, buf: &[u8]) -> io::Result<()> {
         self.selectors.get_mut(s.0).expect("Can't retrieve a selector.").unpack().write(buf);
         Ok(())
@@ -24,7 +24,7 @@ pub enum Poll<T, E> {
 
 #[derive(Debug,Clone)]
 pub enum Error {
-    RuntimeError,
+    RuntimeError(String, Option<usize>),
     WrongContext,
 }
 
@@ -39,7 +39,7 @@ pub enum Context<'a> {
 }
 
 pub trait Task<'a> {
-    fn init(&'a mut self, input: Option<&'a str>, task_id: usize);
+    fn init(&'a mut self, input: Option<&str>, task_id: usize);
     fn exec(&'a mut self, input: Option<&'a str>);
     fn poll(&'a mut self, c: Context<'a>, sched: &'a Scheduler<'a>) -> Poll<Context<'a>, Error>;
     fn finalize(&'a mut self);
@@ -1 +1,165 @@
 pub mod map;
+
+// Adverbs over primitive verbs are evaluated eagerly here,
+// lambdas are iterated by the interpreter (see Cont::Iter)
+
+use commands::ast::{AST, Atom, Value, Verb, Adverb, Error};
+use streams::verb::{self, simd, is_list, count, item, items, zip, collapse};
+
+fn error<'a, 'b>(desc: &str, adv: Adverb, v: Verb, x: &'b AST<'a>) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{}{} {:?}", v, adv, x),
+    }
+}
+
+macro_rules! fold_vec {
+    ($v:expr, $x:expr, $sink:ident, $scan:expr, $plus:expr, $times:expr) => {
+        // +/ */ &/ |/ over typed vectors without boxing every item
+        {
+            let f = |a, b| match $v {
+                Verb::Plus => $plus(a, b),
+                Verb::Times => $times(a, b),
+                Verb::Min => if a < b { a } else { b },
+                _ => if a > b { a } else { b },
+            };
+            let mut acc = $x[0];
+            if $scan {
+                let mut r = Vec::with_capacity($x.len());
+                r.push(acc);
+                for i in &$x[1..] {
+                    acc = f(acc, *i);
+                    r.push(acc);
+                }
+                AST::Atom(Atom::Value(Value::$sink(r)))
+            } else {
+                for i in &$x[1..] {
+                    acc = f(acc, *i);
+                }
+                AST::Atom(Atom::Value(Value::$sink(vec![acc])))
+            }
+        }
+    }
+}
+
+fn fast<'a, 'b>(v: Verb, x: &'b AST<'a>, scan: bool) -> Option<AST<'a>> {
+    match v {
+        Verb::Plus | Verb::Times | Verb::Min | Verb::Max => (),
+        _ => return None,
+    };
+    if !scan && v != Verb::Times {
+        if let Some(r) = simd::fold(v, x) {
+            return Some(r);
+        }
+    }
+    let r = match *x {
+        // ints wrap like the dyads do
+        AST::Atom(Atom::Value(Value::VecInt(ref a))) if a.len() > 0 => {
+            fold_vec!(v, a, VecInt, scan, i64::wrapping_add, i64::wrapping_mul)
+        }
+        AST::Atom(Atom::Value(Value::VecFloat(ref a))) if a.len() > 0 => {
+            fold_vec!(v, a, VecFloat, scan, |a: f64, b: f64| a + b, |a: f64, b: f64| a * b)
+        }
+        _ => return None,
+    };
+    Some(if scan { r } else { item(&r, 0) })
+}
+
+fn fold<'a, 'b>(v: Verb, l: Option<&'b AST<'a>>, x: &'b AST<'a>, scan: bool) -> Result<AST<'a>, Error> {
+    if l.is_none() {
+        if let Some(r) = fast(v, x, scan) {
+            return Ok(r);
+        }
+    }
+    let xs = items(x);
+    let (mut acc, from) = match l {
+        Some(seed) => (seed.clone(), 0),
+        None if xs.len() > 0 => (xs[0].clone(), 1),
+        None => {
+            return Ok(match (v, scan) {
+                (Verb::Plus, false) => AST::Atom(Atom::Value(Value::Number(0))),
+                (Verb::Times, false) => AST::Atom(Atom::Value(Value::Number(1))),
+                _ => x.clone(),
+            })
+        }
+    };
+    let mut r = Vec::with_capacity(xs.len());
+    if l.is_none() {
+        r.push(acc.clone());
+    }
+    for i in &xs[from..] {
+        acc = try!(verb::eval(v, &acc, i));
+        if scan {
+            r.push(acc.clone());
+        }
+    }
+    Ok(if scan { collapse(r) } else { acc })
+}
+
+fn each<'a, 'b>(v: Verb, l: Option<&'b AST<'a>>, x: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    match l {
+        None => {
+            if !is_list(x) {
+                return verb::monad::eval(v, x);
+            }
+            let mut r = Vec::with_capacity(count(x));
+            for i in items(x) {
+                r.push(try!(verb::monad::eval(v, &i)));
+            }
+            Ok(collapse(r))
+        }
+        Some(y) => {
+            if is_list(y) && is_list(x) && count(y) != count(x) {
+                return Err(error("length", Adverb::Each, v, x));
+            }
+            if !is_list(y) && !is_list(x) {
+                return verb::eval(v, y, x);
+            }
+            let mut r = Vec::with_capacity(count(y).max(count(x)));
+            for (a, b) in zip(y, x) {
+                r.push(try!(verb::eval(v, &a, &b)));
+            }
+            Ok(collapse(r))
+        }
+    }
+}
+
+fn each_side<'a, 'b>(v: Verb, l: &'b AST<'a>, x: &'b AST<'a>, left: bool) -> Result<AST<'a>, Error> {
+    let xs = items(if left { l } else { x });
+    let mut r = Vec::with_capacity(xs.len());
+    for i in xs.iter() {
+        r.push(try!(if left {
+            verb::eval(v, i, x)
+        } else {
+            verb::eval(v, l, i)
+        }));
+    }
+    Ok(collapse(r))
+}
+
+fn each_prior<'a, 'b>(v: Verb, l: Option<&'b AST<'a>>, x: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    let xs = items(x);
+    let mut r = Vec::with_capacity(xs.len());
+    for i in 0..xs.len() {
+        r.push(match (i, l) {
+            (0, Some(seed)) => try!(verb::eval(v, &xs[0], seed)),
+            (0, None) => xs[0].clone(),
+            _ => try!(verb::eval(v, &xs[i], &xs[i - 1])),
+        });
+    }
+    Ok(collapse(r))
+}
+
+pub fn eval<'a, 'b>(adv: Adverb, v: Verb, l: Option<&'b AST<'a>>, x: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    match (adv, l) {
+        (Adverb::Over, _) => fold(v, l, x, false),
+        (Adverb::Scan, _) => fold(v, l, x, true),
+        (Adverb::Each, _) => each(v, l, x),
+        (Adverb::EachPrio, _) => each_prior(v, l, x),
+        (Adverb::EachLeft, Some(y)) => each_side(v, y, x, true),
+        (Adverb::EachRight, Some(y)) => each_side(v, y, x, false),
+        (Adverb::EachLeft, None) |
+        (Adverb::EachRight, None) => Err(error("rank", adv, v, x)),
+        _ => Err(error("Adverb is not implemented", adv, v, x)),
+    }
+}
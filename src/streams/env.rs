@@ -4,7 +4,7 @@ use std::cell::UnsafeCell;
 use streams::otree::{Tree, NodeId};
 
 #[derive(Debug, Clone)]
-pub struct Entry<'a>(u16, &'a AST<'a>);
+pub struct Entry<'a>(pub u16, pub &'a AST<'a>);
 
 #[derive(Debug)]
 pub struct Environment<'a> {
@@ -17,6 +17,11 @@ impl<'a> Environment<'a> {
         Ok(Environment { tree: UnsafeCell::new(s) })
     }
 
+    pub fn root(&self) -> NodeId {
+        let tree = unsafe { &*self.tree.get() };
+        tree.root()
+    }
+
     pub fn last(&self) -> NodeId {
         let tree = unsafe { &*self.tree.get() };
         tree.last()
@@ -37,9 +42,9 @@ impl<'a> Environment<'a> {
         tree.append_node(n)
     }
 
-    pub fn define(&'a self, key: u16, value: &'a AST<'a>) -> Result<(), Error> {
+    pub fn define(&'a self, n: NodeId, key: u16, value: &'a AST<'a>) -> Result<(), Error> {
         let tree = unsafe { &mut *self.tree.get() };
-        tree.insert(Entry(key, value));
+        tree.insert(n, Entry(key, value));
         Ok(())
     }
 
@@ -51,6 +56,21 @@ impl<'a> Environment<'a> {
         }
     }
 
+    pub fn locals(&self, n: NodeId) -> Vec<u16> {
+        // names bound from n up to, not including, the workspace
+        let tree = unsafe { &*self.tree.get() };
+        let mut names = Vec::new();
+        let mut k = Some(n);
+        while let Some(c) = k {
+            if c == tree.root() {
+                break;
+            }
+            names.extend(tree.items(c).iter().map(|e| e.0));
+            k = tree.parent(c);
+        }
+        names
+    }
+
     pub fn clean(&self) -> usize {
         let tree = unsafe { &mut *self.tree.get() };
         tree.clean()
@@ -0,0 +1,203 @@
+// Mark and sweep for the arena and the environment tree.
+//
+// Runs between evaluations, when no continuation is alive. Roots are the
+// builtins, the global scope and whatever the caller still holds; closures
+// keep their scopes alive. Slots never move: dead ones
+// go on the arena free list. The tree is compacted, shadowed bindings are
+// dropped and live lambdas get their scope ids renumbered.
+
+use std::mem;
+use std::collections::HashSet;
+use commands::ast::{AST, Atom, Arena};
+use reactors::task::Context;
+use streams::env::{Environment, Entry};
+use streams::otree::NodeId;
+
+struct Heap {
+    base: usize,
+    len: usize,
+    buffers: Vec<(usize, usize, usize)>,
+}
+
+fn buffers<'a>(x: &AST<'a>, slot: usize, out: &mut Vec<(usize, usize, usize)>) {
+    if let AST::Vector(ref v) = *x {
+        if v.len() > 0 {
+            let p = v.as_ptr() as usize;
+            out.push((p, p + v.len() * mem::size_of::<AST<'a>>(), slot));
+        }
+        for i in v.iter() {
+            buffers(i, slot, out);
+        }
+    }
+}
+
+impl Heap {
+    fn new<'a>(arena: &Arena<'a>) -> Heap {
+        // vector items sit in their own allocations, a reference to one
+        // of them keeps the slot owning the vector alive
+        let asts = unsafe { &*arena.asts.get() };
+        let mut b = Vec::new();
+        for (i, x) in asts.iter().enumerate() {
+            buffers(x, i, &mut b);
+        }
+        b.sort();
+        Heap {
+            base: asts.as_ptr() as usize,
+            len: asts.len(),
+            buffers: b,
+        }
+    }
+
+    fn slot<'a>(&self, p: usize) -> Option<usize> {
+        let size = mem::size_of::<AST<'a>>();
+        if p >= self.base && p < self.base + self.len * size {
+            return Some((p - self.base) / size);
+        }
+        match self.buffers.binary_search_by(|&(lo, _, _)| lo.cmp(&p)) {
+            Ok(i) => Some(self.buffers[i].2),
+            Err(i) if i > 0 && p < self.buffers[i - 1].1 => Some(self.buffers[i - 1].2),
+            Err(_) => None,
+        }
+    }
+}
+
+fn trace<'a>(x: &AST<'a>, refs: &mut Vec<&'a AST<'a>>, scopes: &mut Vec<NodeId>) {
+    match *x {
+        AST::Vector(ref v) => {
+            for i in v.iter() {
+                trace(i, refs, scopes);
+            }
+        }
+        AST::Atom(Atom::List(a)) |
+        AST::Atom(Atom::Dict(a)) |
+        AST::Atom(Atom::Yield(Context::Node(a))) => refs.push(a),
+        AST::Atom(Atom::Call(a, b)) |
+        AST::Atom(Atom::Assign(a, b)) |
+        AST::Atom(Atom::Verb(_, a, b)) |
+        AST::Atom(Atom::Adverb(_, a, b)) |
+        AST::Atom(Atom::Table(a, b)) => {
+            refs.push(a);
+            refs.push(b);
+        }
+        AST::Atom(Atom::Cond(a, b, c)) => {
+            refs.push(a);
+            refs.push(b);
+            refs.push(c);
+        }
+        AST::Atom(Atom::Sql(_, a, b, c, d)) => {
+            refs.push(a);
+            refs.push(b);
+            refs.push(c);
+            refs.push(d);
+        }
+        AST::Atom(Atom::Lambda(n, a, b)) => {
+            if let Some(n) = n {
+                scopes.push(n);
+            }
+            refs.push(a);
+            refs.push(b);
+        }
+        _ => {}
+    }
+}
+
+fn renumber<'a>(x: &mut AST<'a>, map: &[Option<NodeId>]) {
+    match *x {
+        AST::Vector(ref mut v) => {
+            for i in v.iter_mut() {
+                renumber(i, map);
+            }
+        }
+        AST::Atom(Atom::Lambda(ref mut n, _, _)) => *n = n.and_then(|n| map[n.index()]),
+        _ => {}
+    }
+}
+
+pub fn collect<'a>(arena: &Arena<'a>, env: &Environment<'a>, roots: &[&'a AST<'a>]) -> usize {
+    let heap = Heap::new(arena);
+    let asts = unsafe { &mut *arena.asts.get() };
+    let tree = unsafe { &mut *env.tree.get() };
+    let builtins = arena.builtins as usize;
+
+    // mark
+    let mut slots = vec![false; heap.len];
+    let mut nodes = vec![false; tree.len().0];
+    let mut refs: Vec<&'a AST<'a>> = roots.to_vec();
+    let mut scopes = vec![env.root()];
+    for i in 0..builtins {
+        slots[i] = true;
+        trace(&asts[i], &mut refs, &mut scopes);
+    }
+    loop {
+        if let Some(n) = scopes.pop() {
+            let mut s = Some(n);
+            while let Some(n) = s {
+                if nodes[n.index()] {
+                    break;
+                }
+                nodes[n.index()] = true;
+                for &&Entry(_, v) in tree.items(n).iter() {
+                    refs.push(v);
+                }
+                s = tree.parent(n);
+            }
+        } else if let Some(x) = refs.pop() {
+            match heap.slot(x as *const AST<'a> as usize) {
+                Some(i) if slots[i] => {}
+                Some(i) => {
+                    slots[i] = true;
+                    trace(&asts[i], &mut refs, &mut scopes);
+                }
+                None => trace(x, &mut refs, &mut scopes),
+            }
+        } else {
+            break;
+        }
+    }
+
+    // compact the tree, a later binding of a name hides the earlier ones in its node
+    let mut seen = HashSet::new();
+    let mut at = None;
+    let (map, mut freed) = tree.compact(&nodes, |n, e: &Entry<'a>| {
+        if at != Some(n) {
+            seen.clear();
+            at = Some(n);
+        }
+        seen.insert(e.0)
+    });
+
+    // sweep, trailing dead slots are given back altogether
+    let free = unsafe { &mut *arena.free.get() };
+    let was_free = free.drain(..).collect::<HashSet<usize>>();
+    let mut len = asts.len();
+    while len > builtins && !slots[len - 1] {
+        len -= 1;
+    }
+    freed += (len..asts.len()).filter(|i| !was_free.contains(i)).count();
+    asts.truncate(len);
+    for i in (builtins..len).rev() {
+        if slots[i] {
+            renumber(&mut asts[i], &map);
+        } else {
+            if !was_free.contains(&i) {
+                freed += 1;
+            }
+            asts[i] = AST::Atom(Atom::Nil);
+            free.push(i);
+        }
+    }
+
+    let spans = unsafe { &mut *arena.spans.get() };
+    let dead = spans.keys()
+        .filter(|&&(l, r)| !(heap.slot(l).map_or(false, |i| slots[i]) && heap.slot(r).map_or(false, |i| slots[i])))
+        .cloned()
+        .collect::<Vec<(usize, usize)>>();
+    for k in dead {
+        spans.remove(&k);
+    }
+
+    let conts = unsafe { &mut *arena.conts.get() };
+    freed += conts.len();
+    conts.clear();
+    freed
+}
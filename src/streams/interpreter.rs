@@ -1,16 +1,19 @@
 
 // O-CPS INTERPRETER by 5HT et all
 
-use streams::{verb, env, otree};
+use streams::{verb, adverb, env, otree, gc, vm};
 use commands::ast::{self, Error, AST, Atom, Verb, Adverb, Arena, Value, ASTAcc, ASTIter};
+use commands::symbol;
+use tables::{Table, Dict};
 use intercore::bus::Memory;
 use intercore::client::{eval_context, internals};
 use reactors::task::Context;
-use intercore::message::Message;
+use intercore::message::{Message, AckPeach};
 use reactors::scheduler::Scheduler;
 use handle::{self, into_raw, from_raw, UnsafeShared};
+use std::mem;
 
-const PREEMPTION: u64 = 20000000; // Yield each two instructions
+pub const PREEMPTION: u64 = 20000000; // Yield each two instructions
 
 #[derive(Clone, Debug)]
 pub enum Cont<'a> {
@@ -21,14 +24,64 @@ pub enum Cont<'a> {
     List(&'a AST<'a>, ASTIter<'a>, &'a Cont<'a>),
     Dict(ASTAcc<'a>, ASTIter<'a>, &'a Cont<'a>),
     DictComplete(ASTAcc<'a>, ASTIter<'a>, usize, &'a Cont<'a>),
-    Call(&'a AST<'a>, &'a Cont<'a>),
-    Verb(Verb, &'a AST<'a>, u8, &'a Cont<'a>),
-    Adverb(Adverb, &'a AST<'a>, &'a Cont<'a>),
+    Call(&'a AST<'a>, &'a AST<'a>, &'a Cont<'a>),
+    Callee(&'a AST<'a>, &'a AST<'a>, &'a Cont<'a>),
+    Verb(Verb, &'a AST<'a>, u8, &'a AST<'a>, &'a Cont<'a>),
+    Adverb(Adverb, u8, &'a Cont<'a>),
+    Iter(Adverb, u8, &'a AST<'a>, Option<&'a AST<'a>>, &'a AST<'a>, ASTAcc<'a>, &'a Cont<'a>),
+    Step(Adverb, u8, &'a AST<'a>, Option<&'a AST<'a>>, &'a AST<'a>, ASTAcc<'a>, &'a Cont<'a>),
+    Table(Vec<String>, usize, &'a Cont<'a>),
+    Sql(&'a AST<'a>, otree::NodeId, &'a Cont<'a>),
+    Trap(Verb, &'a Cont<'a>),
+    Protect(&'a AST<'a>, otree::NodeId, &'a Cont<'a>),
+    Signal(&'a Cont<'a>),
+    Compiled(&'a AST<'a>, &'a AST<'a>, &'a Cont<'a>),
+    Leave(otree::NodeId, &'a Cont<'a>),
     Return,
     Intercore(Message, &'a Cont<'a>),
     Yield(&'a Cont<'a>),
 }
 
+impl<'a> Cont<'a> {
+    pub fn next(&self) -> Option<&'a Cont<'a>> {
+        match *self {
+            Cont::Expressions(_, _, c) |
+            Cont::Assign(_, c) |
+            Cont::Cond(_, _, c) |
+            Cont::Func(_, _, _, c) |
+            Cont::List(_, _, c) |
+            Cont::Dict(_, _, c) |
+            Cont::DictComplete(_, _, _, c) |
+            Cont::Call(_, _, c) |
+            Cont::Callee(_, _, c) |
+            Cont::Verb(_, _, _, _, c) |
+            Cont::Adverb(_, _, c) |
+            Cont::Iter(_, _, _, _, _, _, c) |
+            Cont::Step(_, _, _, _, _, _, c) |
+            Cont::Table(_, _, c) |
+            Cont::Sql(_, _, c) |
+            Cont::Trap(_, c) |
+            Cont::Protect(_, _, c) |
+            Cont::Signal(c) |
+            Cont::Compiled(_, _, c) |
+            Cont::Leave(_, c) |
+            Cont::Intercore(_, c) |
+            Cont::Yield(c) => Some(c),
+            Cont::Return => None,
+        }
+    }
+
+    pub fn source(&self) -> Option<&'a AST<'a>> {
+        // the application this continuation finishes, for error positions
+        match *self {
+            Cont::Call(_, s, _) |
+            Cont::Callee(_, s, _) |
+            Cont::Verb(_, _, _, s, _) => Some(s),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Lazy<'a> {
     Defer(otree::NodeId, &'a AST<'a>, &'a Cont<'a>),
@@ -45,6 +98,13 @@ pub struct Interpreter<'a> {
     pub registers: Lazy<'a>,
     pub counter: u64,
     pub task_id: usize,
+    pub fault: Option<usize>,
+    pub core: usize,
+    pub cores: usize,
+    pub gather: Vec<Option<Result<String, String>>>,
+    pub calls: usize,
+    code: vm::Cache,
+    machine: Option<vm::Machine>,
 }
 
 impl<'a> Interpreter<'a> {
@@ -59,6 +119,13 @@ impl<'a> Interpreter<'a> {
             registers: Lazy::Start,
             task_id: 0,
             counter: 1,
+            fault: None,
+            core: 0,
+            cores: 1,
+            gather: Vec::new(),
+            calls: 0,
+            code: vm::Cache::new(),
+            machine: None,
         };
         Ok(interpreter)
     }
@@ -71,17 +138,42 @@ impl<'a> Interpreter<'a> {
         let snd = s1.arena.intern_ast("snd".to_string());
         let rcv = s1.arena.intern_ast("rcv".to_string());
         let spawn = s1.arena.intern_ast("spawn".to_string());
-        s1.env.define(ast::extract_name(&print), print);
-        s1.env.define(ast::extract_name(&publ), publ);
-        s1.env.define(ast::extract_name(&subs), subs);
-        s1.env.define(ast::extract_name(&snd), snd);
-        s1.env.define(ast::extract_name(&rcv), rcv);
-        s1.env.define(ast::extract_name(&spawn), spawn);
+        let cols = s1.arena.intern_ast("cols".to_string());
+        let count = s1.arena.intern_ast("count".to_string());
+        let upsert = s1.arena.intern_ast("upsert".to_string());
+        let key = s1.arena.intern_ast("key".to_string());
+        let value = s1.arena.intern_ast("value".to_string());
+        let xbar = s1.arena.intern_ast("xbar".to_string());
+        let now = s1.arena.intern_ast("now".to_string());
+        let peach = s1.arena.intern_ast("peach".to_string());
+        let wait = s1.arena.intern_ast("wait".to_string());
+        let status = s1.arena.intern_ast("status".to_string());
+        let at = s1.arena.intern_ast("at".to_string());
+        let rcvall = s1.arena.intern_ast("rcvall".to_string());
+        let root = s1.env.root();
+        s1.env.define(root, ast::extract_name(&print), print);
+        s1.env.define(root, ast::extract_name(&publ), publ);
+        s1.env.define(root, ast::extract_name(&subs), subs);
+        s1.env.define(root, ast::extract_name(&snd), snd);
+        s1.env.define(root, ast::extract_name(&rcv), rcv);
+        s1.env.define(root, ast::extract_name(&spawn), spawn);
+        s1.env.define(root, ast::extract_name(&cols), cols);
+        s1.env.define(root, ast::extract_name(&count), count);
+        s1.env.define(root, ast::extract_name(&upsert), upsert);
+        s1.env.define(root, ast::extract_name(&key), key);
+        s1.env.define(root, ast::extract_name(&value), value);
+        s1.env.define(root, ast::extract_name(&xbar), xbar);
+        s1.env.define(root, ast::extract_name(&now), now);
+        s1.env.define(root, ast::extract_name(&peach), peach);
+        s1.env.define(root, ast::extract_name(&wait), wait);
+        s1.env.define(root, ast::extract_name(&status), status);
+        s1.env.define(root, ast::extract_name(&at), at);
+        s1.env.define(root, ast::extract_name(&rcvall), rcvall);
         let x = unsafe { &mut *s1.arena.asts.get() };
         s2.arena.builtins = x.len() as u16;
     }
 
-    pub fn parse(&'a mut self, s: &String) -> &'a AST<'a> {
+    pub fn parse(&'a mut self, s: &String) -> Result<&'a AST<'a>, Error> {
         ast::parse(&self.arena, s)
     }
 
@@ -90,7 +182,7 @@ impl<'a> Interpreter<'a> {
         match s2.registers {
             Lazy::Continuation(node, _, cont) => {
                 s1.env = env::Environment::new_root().unwrap();
-                s1.registers = Lazy::Continuation(s2.env.last(), ast, s2.arena.cont(Cont::Return))
+                s1.registers = Lazy::Continuation(s2.env.root(), ast, s2.arena.cont(Cont::Return))
             }
             ref x => (),
         }
@@ -108,17 +200,56 @@ impl<'a> Interpreter<'a> {
 
         match from_raw(h).registers {
             Lazy::Start => {
-                tick = try!(from_raw(h).evaluate_expr(from_raw(h).env.last(),
+                from_raw(h).fault = None;
+                from_raw(h).machine = None;
+                tick = try!(from_raw(h).evaluate_expr(from_raw(h).env.root(),
                                                       ast,
                                                       from_raw(h).arena.cont(Cont::Return)))
             }
             _ => tick = from_raw(h).registers.clone(),
         }
 
+        if let Some(s) = sched {
+            from_raw(h).core = s.bus.id;
+            from_raw(h).cores = s.bus.subscribers.len();
+        }
+
         match intercore.clone() {
             Context::NodeAck(_, value) => {
                 ret = from_raw(h).arena.ast(AST::Atom(Atom::Value(Value::Number(value as i64))));
             }
+            Context::Cont(_, &Message::AckPeach(ref a)) => {
+                // the chunk results are joined where peach was called
+                let r = match from_raw(h).gathered(a) {
+                    Some(r) => r,
+                    None => return Ok(from_raw(h).arena.ast(AST::Atom(Atom::Yield(Context::Nil)))),
+                };
+                tick = try!(from_raw(h).resume(tick, r, "peach"));
+            }
+            Context::Cont(_, &Message::AckEval(ref a)) if a.id == from_raw(h).calls => {
+                tick = try!(from_raw(h).resume(tick, a.result.clone(), "at"));
+            }
+            Context::Cont(_, &Message::AckEval(_)) => {
+                // the answer to a call this task is no longer waiting on
+                return Ok(from_raw(h).arena.ast(AST::Atom(Atom::Yield(Context::Nil))));
+            }
+            Context::Cont(_, &Message::AckPark(ref a)) => {
+                ret = from_raw(h).arena.ast(verb::from_value(a.value.clone()));
+            }
+            Context::Cont(_, &Message::AckSpawn(ref a)) => {
+                ret = from_raw(h).arena.ast(AST::Atom(Atom::Value(Value::VecInt(vec![a.from as i64, a.index as i64]))));
+            }
+            Context::Cont(_, &Message::AckAwait(ref a)) if a.block => {
+                let r = a.result.clone().unwrap_or(Err("wait: task is running".to_string()));
+                tick = try!(from_raw(h).resume(tick, r, "wait"));
+            }
+            Context::Cont(_, &Message::AckAwait(ref a)) => {
+                ret = try!(from_raw(h).arena.intern_symbol_ast(a.status().to_string()));
+            }
+            _ if from_raw(h).gather.len() > 0 => {
+                // chunks are still out, only their acks move this task on
+                return Ok(from_raw(h).arena.ast(AST::Atom(Atom::Yield(Context::Nil))));
+            }
             _ => (),
         }
 
@@ -131,7 +262,7 @@ impl<'a> Interpreter<'a> {
                         from_raw(h).counter = counter + 1;
                         return Ok(from_raw(h).arena.ast(AST::Atom(Atom::Yield(Context::Nil))));
                     } else {
-                        tick = try!({
+                        tick = match {
                             from_raw(h).edge = Message::Nop;
                             let a = match ast_ {
                                 &AST::Atom(Atom::Yield(..)) => ret,
@@ -139,7 +270,10 @@ impl<'a> Interpreter<'a> {
                             };
                             from_raw(h).counter = counter + 1;
                             from_raw(h).handle_defer(node, a, cont)
-                        })
+                        } {
+                            Ok(t) => t,
+                            Err(e) => try!(from_raw(h).catch(e, cont)),
+                        }
                     }
                 }
                 Lazy::Start => break,
@@ -178,13 +312,145 @@ impl<'a> Interpreter<'a> {
         })
     }
 
-    pub fn gc(&self) -> usize {
-        self.env.clean() + self.arena.clean()
+    fn resume(&'a mut self, tick: Lazy<'a>, r: Result<String, String>, what: &str) -> Result<Lazy<'a>, Error> {
+        // a result shipped back as source takes the place of the yield
+        let h = into_raw(self);
+        match tick {
+            Lazy::Defer(node, _, cont) => {
+                let x = match r {
+                    Ok(src) => {
+                        ast::parse(&from_raw(h).arena, &src).and_then(|x| from_raw(h).evaluate_expr(node, x, cont))
+                    }
+                    Err(desc) => {
+                        Err(Error::EvalError {
+                            desc: desc,
+                            ast: what.to_string(),
+                        })
+                    }
+                };
+                match x {
+                    Ok(t) => Ok(t),
+                    Err(e) => from_raw(h).catch(e, cont),
+                }
+            }
+            t => Ok(t),
+        }
+    }
+
+    fn gathered(&mut self, a: &AckPeach) -> Option<Result<String, String>> {
+        // store one chunk, once all are back give the source joining them in order
+        match self.gather.get_mut(a.seq) {
+            Some(slot) => *slot = Some(a.result.clone()),
+            None => return None,
+        }
+        if self.gather.iter().any(|r| r.is_none()) {
+            return None;
+        }
+        let r = mem::replace(&mut self.gather, Vec::new())
+            .into_iter()
+            .map(|r| r.unwrap().map(|x| format!("({})", x)))
+            .collect::<Result<Vec<String>, String>>();
+        Some(r.map(|v| v.join(",")))
+    }
+
+    pub fn gc(&mut self, roots: &[&'a AST<'a>]) -> usize {
+        // only between runs, a suspended continuation holds references gc can't see
+        match self.registers {
+            Lazy::Start => {
+                self.code.clear();
+                gc::collect(&self.arena, &self.env, roots)
+            }
+            _ => 0,
+        }
+    }
+
+    fn execute(&'a mut self,
+               node: otree::NodeId,
+               mut m: vm::Machine,
+               fun: &'a AST<'a>,
+               args: &'a AST<'a>,
+               cont: &'a Cont<'a>)
+               -> Result<Lazy<'a>, Error> {
+        // compiled code runs up to the next preemption point, then the scheduler gets a turn
+        let h = into_raw(self);
+        let budget = PREEMPTION - from_raw(h).counter % PREEMPTION;
+        let step = m.run(&from_raw(h).env, &mut from_raw(h).code, budget);
+        from_raw(h).counter += m.steps;
+        match step {
+            vm::Step::Done(v) => from_raw(h).run_cont(node, from_raw(h).arena.ast(AST::Atom(Atom::Value(v))), cont),
+            vm::Step::Preempt => {
+                m.steps = 0;
+                from_raw(h).machine = Some(m);
+                Ok(Lazy::Defer(node,
+                               from_raw(h).arena.nil(),
+                               from_raw(h).arena.cont(Cont::Compiled(fun, args, cont))))
+            }
+            vm::Step::Fallback => from_raw(h).interpret(node, fun, args, cont),
+        }
+    }
+
+    fn at<T>(&mut self, src: &AST<'a>, r: Result<T, Error>) -> Result<T, Error> {
+        // remember where the innermost failing application starts in the input
+        if r.is_err() && self.fault.is_none() {
+            self.fault = self.arena.span(src);
+        }
+        r
+    }
+
+    fn catch(&'a mut self, e: Error, cont: &'a Cont<'a>) -> Result<Lazy<'a>, Error> {
+        // unwind to the innermost protected application, its handler gets the error text
+        let h = into_raw(self);
+        let mut c = cont;
+        if from_raw(h).fault.is_none() {
+            from_raw(h).fault = c.source().and_then(|x| from_raw(h).arena.span(x));
+        }
+        loop {
+            match c {
+                &Cont::Protect(handler, node, next) => {
+                    from_raw(h).fault = None;
+                    let msg = AST::Atom(Atom::Value(Value::Sequence(e.message())));
+                    return Ok(match handler {
+                        &AST::Atom(Atom::Lambda(..)) |
+                        &AST::Atom(Atom::NameInt(_)) => {
+                            let args = from_raw(h).arena.ast(AST::Vector(vec![msg]));
+                            Lazy::Defer(node, from_raw(h).arena.ast(AST::Atom(Atom::Call(handler, args))), next)
+                        }
+                        x => Lazy::Defer(node, x, next),
+                    });
+                }
+                x => {
+                    match x.next() {
+                        Some(n) => {
+                            if from_raw(h).fault.is_none() {
+                                from_raw(h).fault = n.source().and_then(|x| from_raw(h).arena.span(x));
+                            }
+                            c = n
+                        }
+                        None => {
+                            // uncaught, a suspended run must not resume on the next line
+                            from_raw(h).registers = Lazy::Start;
+                            return Err(e);
+                        }
+                    }
+                }
+            }
+        }
     }
 
     fn handle_defer(&'a mut self, node: otree::NodeId, a: &'a AST<'a>, cont: &'a Cont<'a>) -> Result<Lazy<'a>, Error> {
         let h = into_raw(self);
         match a {
+            &AST::Atom(Atom::Verb(v @ Verb::At, &AST::Atom(Atom::Value(Value::Nil)), &AST::Atom(Atom::Dict(x @ &AST::Vector(_))))) |
+            &AST::Atom(Atom::Verb(v @ Verb::Dot, &AST::Atom(Atom::Value(Value::Nil)), &AST::Atom(Atom::Dict(x @ &AST::Vector(_)))))
+                if verb::count(x) == 3 => {
+                // @[f;x;h] and .[f;args;h]: evaluate all three, then apply f under a trap
+                from_raw(h).defer_dict(node, x, from_raw(h).arena.cont(Cont::Trap(v, cont)))
+            }
+            &AST::Atom(Atom::Adverb(Adverb::Each, &AST::Atom(Atom::Value(Value::Nil)), x))
+                if x != from_raw(h).arena.nil() => {
+                // 'x signals x
+                Ok(Lazy::Defer(node, x, from_raw(h).arena.cont(Cont::Signal(cont))))
+            }
             &AST::Atom(Atom::Assign(name, body)) => {
                 Ok(Lazy::Defer(node, body, from_raw(h).arena.cont(Cont::Assign(name, cont))))
             }
@@ -195,38 +461,103 @@ impl<'a> Interpreter<'a> {
             }
             &AST::Atom(Atom::List(x)) => from_raw(h).defer_dict(node, x, cont), // so far list are treated the same as dicts
             &AST::Atom(Atom::Dict(x)) => from_raw(h).defer_dict(node, x, cont),
-            &AST::Atom(Atom::Call(c, a)) => {
-                // println!("Defer call: {:?} {:?}", c, a);
-                Ok(Lazy::Defer(node, a, from_raw(h).arena.cont(Cont::Call(c, cont))))
+            &AST::Atom(Atom::Call(c, args)) => {
+                // println!("Defer call: {:?} {:?}", c, args);
+                Ok(Lazy::Defer(node, args, from_raw(h).arena.cont(Cont::Call(c, a, cont))))
             }
             &AST::Atom(Atom::Verb(ref verb, left, right)) => {
                 // println!("Defer Verb: {:?} {:?}", left, right);
                 match (left, right) {
+                    (&AST::Atom(Atom::Value(Value::Nil)), &AST::Atom(Atom::Value(Value::Nil))) => {
+                        from_raw(h).run_cont(node, a, cont)
+                    }
+                    (&AST::Atom(Atom::Value(Value::Nil)), _) => {
+                        // monadic application
+                        Ok(Lazy::Defer(node,
+                                       right,
+                                       from_raw(h).arena.cont(Cont::Verb(verb.clone(), left, 2, a, cont))))
+                    }
                     (&AST::Atom(Atom::Value(_)), _) => {
                         Ok(Lazy::Defer(node,
                                        right,
-                                       from_raw(h).arena.cont(Cont::Verb(verb.clone(), left, 0, cont))))
+                                       from_raw(h).arena.cont(Cont::Verb(verb.clone(), left, 0, a, cont))))
                     }
                     (_, &AST::Atom(Atom::Value(_))) => {
                         Ok(Lazy::Defer(node,
                                        left,
                                        from_raw(h)
                                            .arena
-                                           .cont(Cont::Verb(verb.clone(), right, 1, cont))))
+                                           .cont(Cont::Verb(verb.clone(), right, 1, a, cont))))
                     }
                     (x, y) => {
+                        // left first, swap 3 then evaluates the right side
                         Ok(Lazy::Defer(node,
                                        x,
                                        from_raw(h)
                                            .arena
-                                           .cont(Cont::Verb(verb.clone(), y, 0, cont))))
+                                           .cont(Cont::Verb(verb.clone(), y, 3, a, cont))))
+                    }
+                }
+            }
+            &AST::Atom(Atom::Table(keys, fields)) => {
+                // ([k:..] c:..;d) => evaluate column expressions, then build the table
+                let mut names = Vec::new();
+                let mut exprs = Vec::new();
+                let mut keyed = 0;
+                for (i, c) in keys.into_iter().chain(fields.into_iter()).enumerate() {
+                    if i < verb::count(keys) && c != &AST::Atom(Atom::Any) {
+                        keyed += 1;
+                    }
+                    match c {
+                        &AST::Atom(Atom::Any) => (),
+                        &AST::Atom(Atom::Assign(&AST::Atom(Atom::NameInt(n)), e)) => {
+                            names.push(from_raw(h).arena.name(n));
+                            exprs.push(e.clone());
+                        }
+                        &AST::Atom(Atom::NameInt(n)) => {
+                            names.push(from_raw(h).arena.name(n));
+                            exprs.push(c.clone());
+                        }
+                        x => {
+                            return Err(Error::EvalError {
+                                desc: "Table column needs a name".to_string(),
+                                ast: format!("{:?}", x),
+                            })
+                        }
+                    }
+                }
+                from_raw(h).defer_dict(node,
+                                       from_raw(h).arena.vec(exprs),
+                                       from_raw(h).arena.cont(Cont::Table(names, keyed, cont)))
+            }
+            &AST::Atom(Atom::Sql(_, _, _, t, _)) => {
+                Ok(Lazy::Defer(node, t, from_raw(h).arena.cont(Cont::Sql(a, node, cont))))
+            }
+            &AST::Atom(Atom::Adverb(adv, f, x)) => {
+                // evaluate [f;left;right] first, arity tells if left was given
+                let nil = from_raw(h).arena.nil();
+                let (f, l, r, arity) = match (f, x) {
+                    (&AST::Atom(Atom::Verb(v, l, _)), _) if l != nil => {
+                        (from_raw(h).arena.ast(AST::Atom(Atom::Verb(v, nil, nil))), l, x, 2)
+                    }
+                    (_, &AST::Atom(Atom::Dict(&AST::Vector(ref args)))) if args.len() == 2 => {
+                        (f, &args[0], &args[1], 2)
+                    }
+                    _ => (f, nil, x, 1),
+                };
+                match r {
+                    &AST::Atom(Atom::Value(Value::Nil)) => from_raw(h).run_cont(node, a, cont),
+                    _ => {
+                        from_raw(h).defer_dict(node,
+                                               from_raw(h).arena.vec(vec![f.clone(), l.clone(), r.clone()]),
+                                               from_raw(h).arena.cont(Cont::Adverb(adv, arity, cont)))
                     }
                 }
             }
             &AST::Atom(Atom::NameInt(name)) => {
                 let l = from_raw(h).lookup(node, name, &from_raw(h).env);
                 match l {
-                    Ok((v, f)) => from_raw(h).run_cont(f, v, cont),
+                    Ok((v, _)) => from_raw(h).run_cont(node, v, cont),
                     Err(x) => Err(x),
                 }
             }
@@ -266,32 +597,79 @@ impl<'a> Interpreter<'a> {
         let h = into_raw(self);
         match fun {
             &AST::Atom(Atom::Lambda(closure, names, body)) => {
-                self.run_cont(if closure == None {
-                                  node
-                              } else {
-                                  closure.unwrap()
-                              },
-                              body,
-                              from_raw(h).arena.cont(Cont::Func(names, args, body, cont)))
+                if let Some(code) = from_raw(h).code.get(names, body) {
+                    let scope = closure.unwrap_or(node);
+                    if !vm::check(&code, scope, &from_raw(h).env, &mut from_raw(h).code) {
+                        return from_raw(h).interpret(node, fun, args, cont);
+                    }
+                    if let Some(m) = vm::Machine::enter(code, scope, args) {
+                        return from_raw(h).execute(node, m, fun, args, cont);
+                    }
+                }
+                from_raw(h).interpret(node, fun, args, cont)
             }
             &AST::Atom(Atom::NameInt(s)) => {
                 // println!("{:?}", s);
                 let v = from_raw(h).lookup(node, s, &from_raw(h).env);
                 match v {
-                    Ok((c, f)) => {
+                    Ok((c, _)) => {
                         match c {
                             &AST::Atom(Atom::NameInt(n)) if n < from_raw(h).arena.builtins => {
-                                eval_context(f,
-                                             from_raw(h),
-                                             internals(from_raw(h), n, args, &from_raw(h).arena),
-                                             cont)
+                                let x = try!(internals(from_raw(h), n, args, &from_raw(h).arena));
+                                eval_context(node, from_raw(h), x, cont)
                             }
-                            _ => from_raw(h).evaluate_fun(f, c, args, cont),
+                            _ => from_raw(h).evaluate_fun(node, c, args, cont),
                         }
                     }
                     Err(x) => Err(x),
                 }
             }
+            &AST::Atom(Atom::Value(Value::Table(ref t))) => {
+                let a = try!(from_raw(h).index_table(t, args));
+                from_raw(h).run_cont(node, from_raw(h).arena.ast(a), cont)
+            }
+            &AST::Atom(Atom::Value(Value::Dict(ref d))) => {
+                let a = try!(from_raw(h).index_dict(d, args));
+                from_raw(h).run_cont(node, from_raw(h).arena.ast(a), cont)
+            }
+            &AST::Atom(Atom::Value(_)) |
+            &AST::Atom(Atom::Any) |
+            &AST::Vector(_) => {
+                Err(Error::EvalError {
+                    desc: "Call Error".to_string(),
+                    ast: format!("{:?}", fun),
+                })
+            }
+            x => {
+                // (`a`b!1 2)`a: an expression callee is evaluated first
+                Ok(Lazy::Defer(node, x, from_raw(h).arena.cont(Cont::Callee(args, x, cont))))
+            }
+        }
+    }
+
+    fn interpret(&'a mut self,
+                 node: otree::NodeId,
+                 fun: &'a AST<'a>,
+                 args: &'a AST<'a>,
+                 cont: &'a Cont<'a>)
+                 -> Result<Lazy<'a>, Error> {
+        // interpreted call, the body runs in a new env node
+        let h = into_raw(self);
+        match fun {
+            &AST::Atom(Atom::Lambda(closure, names, body)) => {
+                // the caller's scope comes back when the body is done, a tail call keeps it
+                let leave = match cont {
+                    &Cont::Leave(..) => cont,
+                    _ => from_raw(h).arena.cont(Cont::Leave(node, cont)),
+                };
+                self.run_cont(if closure == None {
+                                  node
+                              } else {
+                                  closure.unwrap()
+                              },
+                              body,
+                              from_raw(h).arena.cont(Cont::Func(names, args, body, leave)))
+            }
             x => {
                 Err(Error::EvalError {
                     desc: "Call Error".to_string(),
@@ -301,6 +679,219 @@ impl<'a> Interpreter<'a> {
         }
     }
 
+    fn index_table(&self, t: &Table, args: &'a AST<'a>) -> Result<AST<'a>, Error> {
+        // t`c is a column, t[i] a row, t[i j] and t`c`d select
+        let key = match args {
+            &AST::Vector(ref v) if v.len() == 1 => &v[0],
+            x => x,
+        };
+        let column = |s: u16| {
+            t.column(&self.arena.symbol(s)).map(|c| AST::Atom(Atom::Value(c.clone()))).ok_or(Error::EvalError {
+                desc: "Column not found".to_string(),
+                ast: format!("`{}", self.arena.symbol(s)),
+            })
+        };
+        let range = Error::EvalError {
+            desc: "index".to_string(),
+            ast: format!("{:?}", key),
+        };
+        match *key {
+            AST::Atom(Atom::Value(Value::SymbolInt(s))) => column(s),
+            AST::Atom(Atom::Value(Value::VecSym(ref v))) => {
+                let mut r = Vec::with_capacity(v.len());
+                for s in v.iter() {
+                    r.push(try!(column(*s)));
+                }
+                Ok(AST::Vector(r))
+            }
+            AST::Atom(Atom::Value(Value::Number(i))) if i >= 0 && (i as usize) < t.count() => {
+                self.row_dict(t, i as usize)
+            }
+            AST::Atom(Atom::Value(Value::VecInt(ref v))) if v.iter().all(|&i| i >= 0 && (i as usize) < t.count()) => {
+                Ok(AST::Atom(Atom::Value(Value::Table(t.rows(&v.iter().map(|&i| i as usize).collect::<Vec<usize>>())))))
+            }
+            _ => Err(range),
+        }
+    }
+
+    fn row_dict(&self, t: &Table, i: usize) -> Result<AST<'a>, Error> {
+        // a table row is a dictionary from column names to cells
+        let mut names = Vec::with_capacity(t.names.len());
+        for n in t.names.iter() {
+            match try!(self.arena.intern_symbol(n.clone())) {
+                AST::Atom(Atom::Value(Value::SymbolInt(s))) => names.push(s),
+                _ => (),
+            }
+        }
+        Ok(AST::Atom(Atom::Value(Value::Dict(Box::new(Dict {
+            keys: Value::VecSym(names),
+            values: Value::List(t.row(i)),
+        })))))
+    }
+
+    fn index_dict(&self, d: &Dict, args: &'a AST<'a>) -> Result<AST<'a>, Error> {
+        // d`k looks up values, kt[k] and kt[(k1;k2)] look up rows of a keyed table
+        let key = match args {
+            &AST::Vector(ref v) if v.len() == 1 => &v[0],
+            x => x,
+        };
+        match (&d.keys, &d.values) {
+            (&Value::Table(_), &Value::Table(ref t)) => {
+                let find = |k: &AST<'a>| {
+                    verb::to_value(k).and_then(|x| d.find(&x)).ok_or(Error::EvalError {
+                        desc: "Key not found".to_string(),
+                        ast: format!("{:?}", k),
+                    })
+                };
+                match find(key) {
+                    Ok(i) => self.row_dict(t, i),
+                    Err(_) if verb::is_list(key) => {
+                        let mut idx = Vec::with_capacity(verb::count(key));
+                        for k in verb::items(key) {
+                            idx.push(try!(find(&k)));
+                        }
+                        Ok(AST::Atom(Atom::Value(Value::Table(t.rows(&idx)))))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            _ => verb::dict::index(d, key),
+        }
+    }
+
+    fn iterate(&'a mut self,
+               node: otree::NodeId,
+               adv: Adverb,
+               scan: u8,
+               f: &'a AST<'a>,
+               l: Option<&'a AST<'a>>,
+               r: &'a AST<'a>,
+               acc: ASTAcc<'a>,
+               cont: &'a Cont<'a>)
+               -> Result<Lazy<'a>, Error> {
+        // one call of f per step, acc holds the results so far
+        let h = into_raw(self);
+        let i = acc.len();
+        let last = if i > 0 { Some(acc.get()[i - 1].clone()) } else { None };
+        let args = match (adv, l) {
+            (Adverb::Each, None) if i < verb::count(r) => Some(vec![verb::item(r, i)]),
+            (Adverb::Each, Some(y)) => {
+                let n = if verb::is_list(y) { verb::count(y) } else { verb::count(r) };
+                if verb::is_list(y) && verb::is_list(r) && verb::count(y) != verb::count(r) {
+                    return Err(Error::EvalError {
+                        desc: "length".to_string(),
+                        ast: format!("{:?} {:?}", y, r),
+                    });
+                }
+                if i < n {
+                    Some(vec![if verb::is_list(y) { verb::item(y, i) } else { y.clone() },
+                              if verb::is_list(r) { verb::item(r, i) } else { r.clone() }])
+                } else {
+                    None
+                }
+            }
+            (Adverb::EachLeft, Some(y)) if i < verb::count(y) => Some(vec![verb::item(y, i), r.clone()]),
+            (Adverb::EachRight, Some(y)) if i < verb::count(r) => Some(vec![y.clone(), verb::item(r, i)]),
+            (Adverb::EachPrio, _) if i < verb::count(r) => {
+                match (i, l) {
+                    (0, Some(y)) => Some(vec![verb::item(r, 0), y.clone()]),
+                    (0, None) => {
+                        acc.push(&verb::item(r, 0));
+                        return from_raw(h).iterate(node, adv, scan, f, l, r, acc, cont);
+                    }
+                    _ => Some(vec![verb::item(r, i), verb::item(r, i - 1)]),
+                }
+            }
+            (Adverb::Over, _) => {
+                // seeded fold starts from the seed, otherwise from the first item
+                let next = if l.is_some() { i - 1 } else { i };
+                if next < verb::count(r) {
+                    Some(vec![last.clone().unwrap(), verb::item(r, next)])
+                } else {
+                    None
+                }
+            }
+            (Adverb::Iterate, Some(&AST::Atom(Atom::Value(Value::Number(n))))) if (i as i64) <= n => {
+                Some(vec![last.clone().unwrap()])
+            }
+            (Adverb::Fixed, _) => Some(vec![last.clone().unwrap()]),
+            _ => None,
+        };
+        match args {
+            Some(a) => {
+                from_raw(h).evaluate_fun(node,
+                                         f,
+                                         from_raw(h).arena.vec(a),
+                                         from_raw(h).arena.cont(Cont::Iter(adv, scan, f, l, r, acc, cont)))
+            }
+            None => from_raw(h).finish(node, adv, scan, l, r, acc, cont),
+        }
+    }
+
+    fn value(&'a mut self,
+             node: otree::NodeId,
+             x: &'a AST<'a>,
+             src: &'a AST<'a>,
+             cont: &'a Cont<'a>)
+             -> Result<Lazy<'a>, Error> {
+        // .x runs a string as source and a list (f;a;b) as the call f[a;b]
+        let h = into_raw(self);
+        let s = match x {
+            &AST::Atom(Atom::Value(Value::Sequence(ref s))) => s.clone(),
+            &AST::Atom(Atom::Value(Value::Char(c))) => c.to_string(),
+            &AST::Vector(ref v) if v.len() > 1 => {
+                let a = match v[0] {
+                    AST::Atom(Atom::Verb(verb, _, _)) if v.len() == 2 => verb::monad::eval(verb, &v[1]),
+                    AST::Atom(Atom::Verb(verb, _, _)) if v.len() == 3 => verb::eval(verb, &v[1], &v[2]),
+                    AST::Atom(Atom::Verb(..)) => {
+                        Err(Error::EvalError {
+                            desc: "rank".to_string(),
+                            ast: format!("{:?}", x),
+                        })
+                    }
+                    _ => return from_raw(h).evaluate_fun(node, &v[0], from_raw(h).arena.vec(v[1..].to_vec()), cont),
+                };
+                let a = try!(from_raw(h).at(src, a));
+                return from_raw(h).run_cont(node, from_raw(h).arena.ast(a), cont);
+            }
+            _ => {
+                let a = try!(from_raw(h).at(src, verb::monad::value(x)));
+                return from_raw(h).run_cont(node, from_raw(h).arena.ast(a), cont);
+            }
+        };
+        let code = try!(from_raw(h).at(src, ast::parse(&from_raw(h).arena, &s)));
+        from_raw(h).evaluate_expr(node, code, cont)
+    }
+
+    fn split(&'a self, x: &'a AST<'a>) -> &'a AST<'a> {
+        match *x {
+            AST::Atom(Atom::Value(Value::Sequence(_))) => self.arena.ast(AST::Vector(verb::items(x))),
+            _ => x,
+        }
+    }
+
+    fn finish(&'a mut self,
+              node: otree::NodeId,
+              adv: Adverb,
+              scan: u8,
+              l: Option<&'a AST<'a>>,
+              r: &'a AST<'a>,
+              acc: ASTAcc<'a>,
+              cont: &'a Cont<'a>)
+              -> Result<Lazy<'a>, Error> {
+        let h = into_raw(self);
+        let mut v = acc.disown();
+        let res = match (adv, scan) {
+            (Adverb::Each, _) if !verb::is_list(r) && l.map_or(true, |y| !verb::is_list(y)) => v.pop().unwrap(),
+            (Adverb::Over, 0) |
+            (Adverb::Iterate, 0) |
+            (Adverb::Fixed, 0) => v.pop().unwrap(),
+            (Adverb::Over, _) if l.is_some() => verb::collapse(v.split_off(1)),
+            _ => verb::collapse(v),
+        };
+        from_raw(h).run_cont(node, from_raw(h).arena.ast(res), cont)
+    }
+
     pub fn evaluate_expr(&'a self,
                          node: otree::NodeId,
                          exprs: &'a AST<'a>,
@@ -312,20 +903,57 @@ impl<'a> Interpreter<'a> {
                        self.arena.cont(Cont::Expressions(exprs, None, cont))))
     }
 
-    pub fn defer_dict(&'a self, node: otree::NodeId, dict: &'a AST<'a>, cont: &'a Cont<'a>) -> Result<Lazy<'a>, Error> {
+    pub fn defer_dict(&'a mut self,
+                      node: otree::NodeId,
+                      dict: &'a AST<'a>,
+                      cont: &'a Cont<'a>)
+                      -> Result<Lazy<'a>, Error> {
 
         // println!("defer Dict: {:?}", dict);
         match dict {
             &AST::Vector(ref v) => {
                 // create new accumulator and start calculating dict values
-                Ok(Lazy::Defer(node,
-                               dict,
-                               self.arena.cont(Cont::Dict(ASTAcc::new(), v.as_slice().iter(), cont))))
+                self.dict_next(node, ASTAcc::new(), v.as_slice().iter(), cont)
             }
             x => Ok(Lazy::Defer(node, x, cont)),
         }
     }
 
+    fn dict_next(&'a mut self,
+                 node: otree::NodeId,
+                 acc: ASTAcc<'a>,
+                 rest: ASTIter<'a>,
+                 cont: &'a Cont<'a>)
+                 -> Result<Lazy<'a>, Error> {
+        let h = into_raw(self);
+        let mut r = rest;
+        match r.next() {
+            Some(v) => {
+                // println!("run_cont dict next: x={} #### cont: {:?}\n", v, cont);
+                match v {
+                    &AST::Atom(Atom::Dict(ref astv)) |
+                    &AST::Atom(Atom::List(ref astv)) // so far list are treated the same as dicts
+                        => {
+                        //println!("run_cont dict vec: val={} #### cont: {:?}\n", val, cont);
+                        let idx = acc.push( from_raw(h).arena.any()); // allocate empty place for vector result
+                        from_raw(h).defer_dict(node, astv,
+                                               from_raw(h).arena.cont(Cont::DictComplete(acc.clone(), r, idx, cont)))
+                    }
+                    x => {
+                        //println!("run_cont dict defer: x={} #### cont: {:?}\n", x, cont);
+                        Ok(Lazy::Defer(node,
+                                       x,
+                                       from_raw(h).arena.cont(Cont::Dict(acc, r, cont))))
+                    }
+                }
+            }
+            _ => {
+                // entire vector calculated, time to move calculation on
+                from_raw(h).run_cont(node, from_raw(h).arena.ast(AST::Vector(acc.disown())), cont)
+            }
+        }
+    }
+
     pub fn run_cont(&'a mut self, node: otree::NodeId, val: &'a AST<'a>, con: &'a Cont<'a>) -> Result<Lazy<'a>, Error> {
         // println!("run_cont: val: {:?} #### cont: {:?}\n", val, cont);
         let h = into_raw(self);
@@ -336,13 +964,31 @@ impl<'a> Interpreter<'a> {
                 Ok(Lazy::Continuation(node, val, cc))
             }
             &Cont::Yield(cc) => Ok(Lazy::Continuation(node, val, cc)),
-            &Cont::Call(callee, cont) => {
+            &Cont::Call(callee, src, cont) => {
                 let c;
                 match val {
                     &AST::Atom(Atom::Dict(v)) => c = from_raw(h).evaluate_fun(node, callee, v, cont),
                     x => c = from_raw(h).evaluate_fun(node, callee, x, cont),
                 };
-                c
+                from_raw(h).at(src, c)
+            }
+            &Cont::Callee(args, src, cont) => {
+                match val {
+                    &AST::Atom(Atom::Lambda(..)) |
+                    &AST::Atom(Atom::NameInt(_)) |
+                    &AST::Atom(Atom::Value(Value::Table(_))) |
+                    &AST::Atom(Atom::Value(Value::Dict(_))) => {
+                        let c = from_raw(h).evaluate_fun(node, val, args, cont);
+                        from_raw(h).at(src, c)
+                    }
+                    x => {
+                        from_raw(h).at(src,
+                                       Err(Error::EvalError {
+                                           desc: "Call Error".to_string(),
+                                           ast: format!("{:?}", x),
+                                       }))
+                    }
+                }
             }
             &Cont::Func(ref names, ref args, body, cont) => {
                 // println!("cont_func names={:?} args={:?}", names, args);
@@ -354,7 +1000,7 @@ impl<'a> Interpreter<'a> {
                     match v {
                         &AST::Atom(Atom::Any) => partial.push(k.clone()),
                         _ => {
-                            from_raw(h).env.define(ast::extract_name(k), v);
+                            from_raw(h).env.define(f, ast::extract_name(k), v);
                         }
                     };
                 }
@@ -388,7 +1034,7 @@ impl<'a> Interpreter<'a> {
                 match name {
                     &AST::Atom(Atom::NameInt(s)) => {
                         // println!("Assign: {:?}:{:?}", s, val);
-                        try!(from_raw(h).env.define(s, val));
+                        try!(from_raw(h).env.define(node, s, val));
                         from_raw(h).evaluate_expr(node, val, cont)
                     }
                     x => {
@@ -403,70 +1049,245 @@ impl<'a> Interpreter<'a> {
             &Cont::DictComplete(ref acc, ref rest, idx, cont) => {
                 // println!("run_cont dict_complete: acc={} idx={} val={} #### cont: {:?}\n", acc, idx, val, cont);
                 acc.set(idx, val.clone()); // TODO Get rid of clone!!!
-
-                Ok(Lazy::Defer(node,
-                               val,
-                               from_raw(h).arena.cont(Cont::Dict(acc.clone(), rest.clone(), cont))))
+                from_raw(h).dict_next(node, acc.clone(), rest.clone(), cont)
             }
             &Cont::Dict(ref acc, ref rest, cont) => {
                 // println!("run_cont dict: acc={} #### cont: {:?}\n", acc, cont);
-
+                acc.push(val);
+                from_raw(h).dict_next(node, acc.clone(), rest.clone(), cont)
+            }
+            &Cont::Adverb(adv, arity, cont) => {
                 match val {
-                    &AST::Atom(_) => {
-                        acc.push(val);
-                    }
-                    _ => {}
-                }
-
-                let mut r = rest.clone();
-                match r.next() {
-                    Some(v) => {
-                        // println!("run_cont dict next: x={} #### cont: {:?}\n", v, cont);
-                        match v {
-                            &AST::Atom(Atom::Dict(ref astv)) |
-                            &AST::Atom(Atom::List(ref astv)) // so far list are treated the same as dicts
-                                => {
-                                //println!("run_cont dict vec: val={} #### cont: {:?}\n", val, cont);
-                                let idx = acc.push( from_raw(h).arena.any()); // allocate empty place for vector result
-                                 from_raw(h).defer_dict(node, astv,
-                                                 from_raw(h).arena.cont(Cont::DictComplete(acc.clone(), r, idx, cont)))
+                    &AST::Vector(ref v) if v.len() == 3 => {
+                        let l = if arity == 2 { Some(&v[1]) } else { None };
+                        match v[0] {
+                            AST::Atom(Atom::Verb(verb, _, _)) => {
+                                let a = try!(adverb::eval(adv, verb, l, &v[2]));
+                                from_raw(h).run_cont(node, from_raw(h).arena.ast(a), cont)
                             }
-                            x => {
-                                //println!("run_cont dict defer: x={} #### cont: {:?}\n", x, cont);
-                                Ok(Lazy::Defer(node,
-                                               x,
-                                                from_raw(h).arena.cont(Cont::Dict(acc.clone(), r, cont))))
+                            ref f => {
+                                // monadic f over/scan iterates n times or until it converges
+                                let monadic = match *f {
+                                    AST::Atom(Atom::Lambda(_, &AST::Vector(ref names), _)) => names.len() == 1,
+                                    _ => true,
+                                };
+                                let (a, scan) = match adv {
+                                    Adverb::Over | Adverb::Scan if monadic => {
+                                        (if l.is_some() { Adverb::Iterate } else { Adverb::Fixed },
+                                         if adv == Adverb::Scan { 1 } else { 0 })
+                                    }
+                                    Adverb::Scan => (Adverb::Over, 1),
+                                    x => (x, 0),
+                                };
+                                let acc = ASTAcc::new();
+                                match (a, l) {
+                                    (Adverb::Iterate, _) |
+                                    (Adverb::Fixed, _) => {
+                                        acc.push(&v[2]);
+                                    }
+                                    (Adverb::Over, Some(seed)) => {
+                                        acc.push(seed);
+                                    }
+                                    (Adverb::Over, None) if verb::count(&v[2]) > 0 => {
+                                        acc.push(&verb::item(&v[2], 0));
+                                    }
+                                    (Adverb::Over, None) => return from_raw(h).run_cont(node, &v[2], cont),
+                                    (Adverb::EachLeft, None) |
+                                    (Adverb::EachRight, None) => {
+                                        return Err(Error::EvalError {
+                                            desc: "rank".to_string(),
+                                            ast: format!("{:?}", f),
+                                        })
+                                    }
+                                    _ => (),
+                                };
+                                // steps index their operands, split strings once up front
+                                let l = l.map(|y| from_raw(h).split(y));
+                                let r = from_raw(h).split(&v[2]);
+                                from_raw(h).iterate(node, a, scan, f, l, r, acc, cont)
                             }
                         }
                     }
-                    _ => {
-                        // entire vector calculated, time to move calculation on
-                        from_raw(h).run_cont(node, from_raw(h).arena.ast(AST::Vector(acc.disown())), cont)
+                    x => {
+                        Err(Error::EvalError {
+                            desc: "Adverb arguments".to_string(),
+                            ast: format!("{:?}", x),
+                        })
                     }
                 }
             }
-            &Cont::Verb(ref verb, right, swap, cont) => {
+            &Cont::Table(ref names, keyed, cont) => {
+                let mut cols = Vec::with_capacity(names.len());
+                for c in val.into_iter() {
+                    let col = match c {
+                        &AST::Vector(ref v) => verb::collapse(v.clone()),
+                        x => x.clone(),
+                    };
+                    match col {
+                        AST::Atom(Atom::Value(v)) => cols.push(v),
+                        x => {
+                            return Err(Error::EvalError {
+                                desc: "Table columns must be typed vectors".to_string(),
+                                ast: format!("{:?}", x),
+                            })
+                        }
+                    }
+                }
+                let t = try!(Table::new(names.clone(), cols));
+                let t = if keyed > 0 {
+                    let (k, v) = t.split(keyed);
+                    Value::Dict(Box::new(try!(Dict::new(Value::Table(k), Value::Table(v)))))
+                } else {
+                    Value::Table(t)
+                };
+                from_raw(h).run_cont(node, from_raw(h).arena.ast(AST::Atom(Atom::Value(t))), cont)
+            }
+            &Cont::Sql(q, scope, cont) => {
+                // the source table is evaluated, the rest runs over its columns
+                // with free names resolved where the query was written
+                let unkeyed = match val {
+                    &AST::Atom(Atom::Value(Value::Dict(ref d))) if d.is_keyed() => {
+                        Some(try!(verb::eval(Verb::Mod, &AST::Atom(Atom::Value(Value::Number(0))), val)))
+                    }
+                    _ => None,
+                };
+                let r = match unkeyed.as_ref().unwrap_or(val) {
+                    &AST::Atom(Atom::Value(Value::Table(ref t))) => {
+                        let name = |n: u16| from_raw(h).arena.name(n);
+                        let lookup = |n: u16| from_raw(h).env.get(n, scope).map(|(v, _)| v.clone());
+                        try!(verb::query::eval(q,
+                                               t,
+                                               &verb::query::Scope {
+                                                   name: &name,
+                                                   lookup: &lookup,
+                                               }))
+                    }
+                    x => {
+                        return Err(Error::EvalError {
+                            desc: "Query source must be a table".to_string(),
+                            ast: format!("{:?}", x),
+                        })
+                    }
+                };
+                from_raw(h).run_cont(node, from_raw(h).arena.ast(r), cont)
+            }
+            &Cont::Trap(v, cont) => {
+                match val {
+                    &AST::Vector(ref x) if x.len() == 3 => {
+                        // @ applies f to x, . spreads the argument list
+                        let args = match v {
+                            Verb::At => AST::Vector(vec![x[1].clone()]),
+                            _ => {
+                                match x[1] {
+                                    AST::Vector(_) => x[1].clone(),
+                                    ref a => AST::Vector(verb::items(a)),
+                                }
+                            }
+                        };
+                        let call = AST::Atom(Atom::Call(&x[0], from_raw(h).arena.ast(args)));
+                        Ok(Lazy::Defer(node,
+                                       from_raw(h).arena.ast(call),
+                                       from_raw(h).arena.cont(Cont::Protect(&x[2], node, cont))))
+                    }
+                    x => {
+                        Err(Error::EvalError {
+                            desc: "Trap arguments".to_string(),
+                            ast: format!("{:?}", x),
+                        })
+                    }
+                }
+            }
+            &Cont::Protect(_, _, cont) => from_raw(h).run_cont(node, val, cont),
+            &Cont::Leave(caller, cont) => from_raw(h).run_cont(caller, val, cont),
+            &Cont::Compiled(fun, args, cont) => {
+                match from_raw(h).machine.take() {
+                    Some(m) => from_raw(h).execute(node, m, fun, args, cont),
+                    None => from_raw(h).interpret(node, fun, args, cont),
+                }
+            }
+            &Cont::Signal(_) => {
+                let desc = match val {
+                    &AST::Atom(Atom::Value(Value::Sequence(ref s))) => s.clone(),
+                    &AST::Atom(Atom::Value(Value::Char(c))) => c.to_string(),
+                    &AST::Atom(Atom::Value(Value::SymbolInt(s))) => symbol::name(s),
+                    x => format!("{}", x),
+                };
+                Err(Error::EvalError {
+                    desc: desc,
+                    ast: format!("{:?}", val),
+                })
+            }
+            &Cont::Iter(adv, scan, f, l, r, ref acc, cont) => {
+                if adv == Adverb::Fixed {
+                    let done = {
+                        let v = acc.get();
+                        verb::list::same(val, &v[v.len() - 1]) || verb::list::same(val, &v[0])
+                    };
+                    if done {
+                        return from_raw(h).finish(node, adv, scan, l, r, acc.clone(), cont);
+                    }
+                }
+                acc.push(val);
+                // the next step starts from the run loop, so long iterations don't grow the stack
+                Ok(Lazy::Defer(node,
+                               from_raw(h).arena.nil(),
+                               from_raw(h).arena.cont(Cont::Step(adv, scan, f, l, r, acc.clone(), cont))))
+            }
+            &Cont::Step(adv, scan, f, l, r, ref acc, cont) => {
+                from_raw(h).iterate(node, adv, scan, f, l, r, acc.clone(), cont)
+            }
+            &Cont::Verb(Verb::Dot, _, 2, src, cont) => from_raw(h).value(node, val, src, cont),
+            &Cont::Verb(ref verb, _, 2, src, cont) => {
+                let a = try!(from_raw(h).at(src, verb::monad::eval(verb.clone(), val)));
+                from_raw(h).run_cont(node, from_raw(h).arena.ast(a), cont)
+            }
+            &Cont::Verb(ref verb, right, swap, src, cont) => {
                 // println!("Cont Verb: {:?}", val);
                 match (right, val) {
-                    (&AST::Atom(Atom::Value(_)), &AST::Atom(Atom::Value(_))) => {
+                    (x, y) if verb::is_value(x) && verb::is_value(y) => {
                         match swap {
                             0 => {
-                                let a = verb::eval(verb.clone(), right, val).unwrap();
+                                let a = try!(from_raw(h).at(src, verb::eval(verb.clone(), right, val)));
                                 from_raw(h).run_cont(node, from_raw(h).arena.ast(a), cont)
                             }
                             _ => {
-                                let a = verb::eval(verb.clone(), val, right).unwrap();
+                                let a = try!(from_raw(h).at(src, verb::eval(verb.clone(), val, right)));
                                 from_raw(h).run_cont(node, from_raw(h).arena.ast(a), cont)
                             }
                         }
                     }
                     (x, &AST::Atom(Atom::Value(Value::Nil))) => Ok(Lazy::Defer(node, x, cont)),
-                    (x, y) => {
+                    (x, y) if swap == 3 => {
                         Ok(Lazy::Defer(node,
                                        x,
                                        from_raw(h)
                                            .arena
-                                           .cont(Cont::Verb(verb.clone(), y, 0, cont))))
+                                           .cont(Cont::Verb(verb.clone(), y, 0, src, cont))))
+                    }
+                    _ => {
+                        // both sides are evaluated here, f@x and f . x call f
+                        let (l, r) = if swap == 0 { (right, val) } else { (val, right) };
+                        match (verb, l) {
+                            (&Verb::At, &AST::Atom(Atom::Lambda(..))) |
+                            (&Verb::At, &AST::Atom(Atom::NameInt(_))) |
+                            (&Verb::Dot, &AST::Atom(Atom::Lambda(..))) |
+                            (&Verb::Dot, &AST::Atom(Atom::NameInt(_))) => {
+                                let args = match (verb, r) {
+                                    (&Verb::Dot, &AST::Vector(_)) => r.clone(),
+                                    (&Verb::Dot, _) if verb::is_list(r) => AST::Vector(verb::items(r)),
+                                    _ => AST::Vector(vec![r.clone()]),
+                                };
+                                let c = from_raw(h).evaluate_fun(node, l, from_raw(h).arena.ast(args), cont);
+                                from_raw(h).at(src, c)
+                            }
+                            _ => {
+                                from_raw(h).at(src,
+                                               Err(Error::EvalError {
+                                                   desc: "type".to_string(),
+                                                   ast: format!("{:?} {:?} {:?}", verb, l, r),
+                                               }))
+                            }
+                        }
                     }
                 }
             }
@@ -5,3 +5,5 @@ pub mod interpreter;
 pub mod env;
 pub mod stack;
 pub mod otree;
+pub mod gc;
+pub mod vm;
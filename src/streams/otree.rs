@@ -1,10 +1,13 @@
 use std::fmt;
 use std::fmt::Debug;
+use std::mem;
 
 #[derive(PartialEq, Clone, Debug)]
 struct Node {
     bounds: (usize, usize),
     parent: Option<usize>,
+    // items bound after a later node took the end of the store, oldest first
+    later: Vec<usize>,
 }
 
 impl fmt::Display for Node {
@@ -20,6 +23,12 @@ impl fmt::Display for Node {
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub struct NodeId(usize);
 
+impl NodeId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
 #[derive(Debug)]
 pub struct Tree<T> {
     nodes: Vec<Node>,
@@ -33,6 +42,7 @@ impl<T: Debug> Tree<T> {
         n.push(Node {
             bounds: (0, 0),
             parent: None,
+            later: Vec::new(),
         });
         Tree {
             nodes: n,
@@ -59,24 +69,42 @@ impl<T: Debug> Tree<T> {
     }
 
     pub fn append_node(&mut self, n: NodeId) -> NodeId {
-        let bound = self.nodes.last().expect("There is no root in otree.").bounds.1;
+        let bound = self.items.len();
         self.nodes.push(Node {
             bounds: (bound, bound),
             parent: Some(n.0),
+            later: Vec::new(),
         });
         NodeId(self.nodes.len() - 1)
     }
 
-    pub fn insert(&mut self, item: T) {
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    pub fn insert(&mut self, n: NodeId, item: T) {
+        // items always go to the end of the store, a node whose block ends there just grows,
+        // the others note where it went until compact puts their items together again
+        let at = self.items.len();
         self.items.push(item);
-        let mut n = self.nodes.last_mut().unwrap();
-        n.bounds.1 += 1;
+        let nd = &mut self.nodes[n.0];
+        if nd.bounds.1 == at {
+            nd.bounds.1 += 1;
+        } else {
+            nd.later.push(at);
+        }
     }
 
     pub fn get<'a, F>(&'a self, n: NodeId, mut f: F) -> Option<(&'a T, NodeId)>
         where for<'r> F: FnMut(&'r &T) -> bool
     {
         let nd = self.nodes.get(n.0).expect("Error getting node.");
+        for j in nd.later.iter().rev() {
+            let i = &self.items[*j];
+            if f(&i) {
+                return Some((i, n));
+            }
+        }
         for i in self.items[nd.bounds.0..nd.bounds.1].iter().rev() {
             if f(&i) {
                 return Some((i, n));
@@ -88,15 +116,63 @@ impl<T: Debug> Tree<T> {
         }
     }
 
+    pub fn parent(&self, n: NodeId) -> Option<NodeId> {
+        self.nodes[n.0].parent.map(NodeId)
+    }
+
+    pub fn items(&self, n: NodeId) -> Vec<&T> {
+        let nd = &self.nodes[n.0];
+        self.items[nd.bounds.0..nd.bounds.1].iter().chain(nd.later.iter().map(|j| &self.items[*j])).collect()
+    }
+
+    pub fn compact<F>(&mut self, live: &[bool], mut keep: F) -> (Vec<Option<NodeId>>, usize)
+        where F: FnMut(NodeId, &T) -> bool
+    {
+        // drop dead nodes and the items keep refuses (it sees each node newest first),
+        // node order stays so parents still come before their children
+        let cap = self.items.capacity();
+        let old = mem::replace(&mut self.items, Vec::with_capacity(cap));
+        let mut old = old.into_iter().map(Some).collect::<Vec<Option<T>>>();
+        let cap = self.nodes.capacity();
+        let nodes = mem::replace(&mut self.nodes, Vec::with_capacity(cap));
+        let mut map = vec![None; nodes.len()];
+        let mut dropped = 0;
+        for (i, nd) in nodes.iter().enumerate() {
+            let all = nd.bounds.1 - nd.bounds.0 + nd.later.len();
+            if !live[i] {
+                dropped += all;
+                continue;
+            }
+            let kept = (nd.bounds.0..nd.bounds.1)
+                .chain(nd.later.iter().cloned())
+                .rev()
+                .filter(|j| keep(NodeId(i), old[*j].as_ref().unwrap()))
+                .collect::<Vec<usize>>();
+            dropped += all - kept.len();
+            let start = self.items.len();
+            for j in kept.into_iter().rev() {
+                self.items.push(old[j].take().unwrap());
+            }
+            map[i] = Some(NodeId(self.nodes.len()));
+            self.nodes.push(Node {
+                bounds: (start, self.items.len()),
+                parent: nd.parent.and_then(|p| map[p]).map(|p| p.0),
+                later: Vec::new(),
+            });
+        }
+        (map, dropped)
+    }
+
     pub fn clean(&mut self) -> usize {
         let l = self.items.len();
         unsafe {
             self.items.set_len(0);
-            self.nodes.set_len(0);
         }
+        self.nodes.clear();
         self.nodes.push(Node {
             bounds: (0, 0),
             parent: None,
+            later: Vec::new(),
         });
         l
     }
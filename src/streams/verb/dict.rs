@@ -0,0 +1,147 @@
+// Dictionaries: x!y, lookup by key, and verbs that align on keys
+
+use commands::ast::{AST, Atom, Value, Verb, Error};
+use tables::Dict;
+use streams::verb::{self, list, is_list, items, collapse, to_value, from_value};
+
+fn error<'a, 'b>(desc: &str, l: &'b AST<'a>, r: &'b AST<'a>) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{:?} {:?}", l, r),
+    }
+}
+
+fn dict<'a>(d: Dict) -> AST<'a> {
+    AST::Atom(Atom::Value(Value::Dict(Box::new(d))))
+}
+
+pub fn is_dict<'a>(x: &AST<'a>) -> bool {
+    match *x {
+        AST::Atom(Atom::Value(Value::Dict(_))) => true,
+        _ => false,
+    }
+}
+
+pub fn make<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    match (l, r) {
+        (&AST::Atom(Atom::Value(Value::Number(n))), &AST::Atom(Atom::Value(Value::Table(ref t)))) => {
+            // n!t keys a table on its first n columns
+            if n < 0 || n as usize >= t.names.len() {
+                return Err(error("length", l, r));
+            }
+            if n == 0 {
+                return Ok(r.clone());
+            }
+            let (k, v) = t.split(n as usize);
+            Ok(dict(Dict {
+                keys: Value::Table(k),
+                values: Value::Table(v),
+            }))
+        }
+        _ => {
+            if !is_list(r) {
+                return Err(error("length", l, r));
+            }
+            match (to_value(l), to_value(r)) {
+                (Some(k), Some(v)) => Ok(dict(try!(Dict::new(k, v)))),
+                _ => Err(error("type", l, r)),
+            }
+        }
+    }
+}
+
+pub fn key<'a>(x: &AST<'a>) -> Option<AST<'a>> {
+    match *x {
+        AST::Atom(Atom::Value(Value::Dict(ref d))) => Some(from_value(d.keys.clone())),
+        _ => None,
+    }
+}
+
+pub fn value<'a>(x: &AST<'a>) -> Option<AST<'a>> {
+    match *x {
+        AST::Atom(Atom::Value(Value::Dict(ref d))) => Some(from_value(d.values.clone())),
+        _ => None,
+    }
+}
+
+pub fn index<'a, 'b>(d: &Dict, k: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    // d`k, a list of keys gives a list, missing keys give the null of the values
+    let null = list::null(&from_value(d.values.clone()));
+    let one = |x: &Value| d.find(x).map_or(null.clone(), |i| from_value(d.get(i)));
+    match to_value(k) {
+        Some(ref x) if !is_list(k) || d.find(x).is_some() => Ok(one(x)),
+        Some(_) => {
+            let mut v = Vec::new();
+            for i in items(k) {
+                match to_value(&i) {
+                    Some(ref x) => v.push(one(x)),
+                    None => return Err(error("type", &dict(d.clone()), k)),
+                }
+            }
+            Ok(collapse(v))
+        }
+        None => Err(error("type", &dict(d.clone()), k)),
+    }
+}
+
+fn with_values<'a>(d: &Dict, v: AST<'a>) -> Result<AST<'a>, Error> {
+    match to_value(&v) {
+        Some(x) => Ok(dict(try!(Dict::new(d.keys.clone(), x)))),
+        None => Err(error("type", &dict(d.clone()), &v)),
+    }
+}
+
+fn union<'a>(verb: Verb, a: &Dict, b: &Dict) -> Result<AST<'a>, Error> {
+    // keys of a in order then new keys of b, common keys combine,
+    // d,e lets the right side win
+    let mut keys = items(&from_value(a.keys.clone()));
+    let mut vals = items(&from_value(a.values.clone()));
+    for (i, k) in items(&from_value(b.keys.clone())).into_iter().enumerate() {
+        let y = from_value(b.get(i));
+        match keys.iter().position(|x| list::same(x, &k)) {
+            Some(j) => {
+                vals[j] = match verb {
+                    Verb::Concat => y,
+                    _ => try!(verb::eval(verb, &vals[j], &y)),
+                }
+            }
+            None => {
+                keys.push(k);
+                vals.push(y);
+            }
+        }
+    }
+    match (to_value(&collapse(keys)), to_value(&collapse(vals))) {
+        (Some(k), Some(v)) => Ok(dict(try!(Dict::new(k, v)))),
+        _ => Err(error("type", &dict(a.clone()), &dict(b.clone()))),
+    }
+}
+
+pub fn eval<'a, 'b>(verb: Verb, l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    let atomic = match verb {
+        Verb::Plus | Verb::Minus | Verb::Times | Verb::Divide | Verb::Mod | Verb::Min | Verb::Max |
+        Verb::Lt | Verb::Le | Verb::Gt | Verb::Ge | Verb::Eq | Verb::NEq => true,
+        _ => false,
+    };
+    match (l, r) {
+        (&AST::Atom(Atom::Value(Value::Number(0))), &AST::Atom(Atom::Value(Value::Dict(ref d))))
+            if verb == Verb::Mod && d.is_keyed() => {
+            // 0!kt unkeys
+            match (&d.keys, &d.values) {
+                (&Value::Table(ref k), &Value::Table(ref v)) => Ok(AST::Atom(Atom::Value(Value::Table(try!(k.merge(v)))))),
+                _ => Err(error("type", l, r)),
+            }
+        }
+        _ if verb == Verb::Match => list::match_(l, r),
+        (&AST::Atom(Atom::Value(Value::Dict(ref d))), _) if verb == Verb::At => index(d, r),
+        (&AST::Atom(Atom::Value(Value::Dict(ref a))), &AST::Atom(Atom::Value(Value::Dict(ref b))))
+            if (atomic || verb == Verb::Concat) && !a.is_keyed() && !b.is_keyed() => union(verb, a, b),
+        (&AST::Atom(Atom::Value(Value::Dict(ref a))), _) if atomic && !a.is_keyed() && !is_dict(r) => {
+            with_values(a, try!(verb::eval(verb, &from_value(a.values.clone()), r)))
+        }
+        (_, &AST::Atom(Atom::Value(Value::Dict(ref b)))) if atomic && !b.is_keyed() && !is_dict(l) => {
+            with_values(b, try!(verb::eval(verb, l, &from_value(b.values.clone()))))
+        }
+        _ => Err(error("type", l, r)),
+    }
+}
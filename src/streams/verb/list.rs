@@ -0,0 +1,298 @@
+// Dyadic list verbs: take, drop/cut, join, except, find, index, match, cast
+
+use std::collections::HashMap;
+use commands::ast::{AST, Atom, Value, Error};
+use commands::symbol;
+use streams::verb::{NULL_INT, is_list, count, item, items, collapse};
+use streams::verb::monad::enlist;
+
+fn error<'a, 'b>(desc: &str, l: &'b AST<'a>, r: &'b AST<'a>) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{:?} {:?}", l, r),
+    }
+}
+
+pub fn null<'a>(x: &AST<'a>) -> AST<'a> {
+    // typed null matching the prototype of list x
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(_))) => AST::Atom(Atom::Value(Value::Number(NULL_INT))),
+        AST::Atom(Atom::Value(Value::VecFloat(_))) => {
+            AST::Atom(Atom::Value(Value::Float(::std::f64::NAN)))
+        }
+        AST::Atom(Atom::Value(Value::Sequence(_))) => AST::Atom(Atom::Value(Value::Char(' '))),
+        AST::Atom(Atom::Value(Value::VecTemporal(k, _))) => AST::Atom(Atom::Value(Value::Temporal(k, NULL_INT))),
+        _ => AST::Atom(Atom::Value(Value::Nil)),
+    }
+}
+
+fn select<'a>(x: &AST<'a>, idx: &[usize]) -> AST<'a> {
+    // keep the vector type of x even when the selection is empty
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => {
+            AST::Atom(Atom::Value(Value::VecInt(idx.iter().map(|&i| v[i]).collect())))
+        }
+        AST::Atom(Atom::Value(Value::VecFloat(ref v))) => {
+            AST::Atom(Atom::Value(Value::VecFloat(idx.iter().map(|&i| v[i]).collect())))
+        }
+        AST::Atom(Atom::Value(Value::VecSym(ref v))) => {
+            AST::Atom(Atom::Value(Value::VecSym(idx.iter().map(|&i| v[i]).collect())))
+        }
+        AST::Atom(Atom::Value(Value::VecTemporal(k, ref v))) => {
+            AST::Atom(Atom::Value(Value::VecTemporal(k, idx.iter().map(|&i| v[i]).collect())))
+        }
+        AST::Atom(Atom::Value(Value::Sequence(ref s))) => {
+            let v = s.chars().collect::<Vec<char>>();
+            AST::Atom(Atom::Value(Value::Sequence(idx.iter().map(|&i| v[i]).collect())))
+        }
+        _ => AST::Vector(idx.iter().map(|&i| item(x, i)).collect()),
+    }
+}
+
+fn shape<'a>(dims: &[i64], x: &AST<'a>, pos: &mut usize) -> AST<'a> {
+    let n = count(x);
+    if dims.len() == 1 {
+        let idx = (0..dims[0] as usize).map(|i| (*pos + i) % n).collect::<Vec<usize>>();
+        *pos += dims[0] as usize;
+        return select(x, &idx);
+    }
+    AST::Vector((0..dims[0]).map(|_| shape(&dims[1..], x, pos)).collect())
+}
+
+pub fn take<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    match *l {
+        AST::Atom(Atom::Value(Value::Number(n))) => {
+            if !is_list(r) {
+                // 0#` still has the type of the atom
+                return Ok(match n {
+                    0 => select(&try!(enlist(r)), &[]),
+                    _ => collapse((0..n.abs()).map(|_| r.clone()).collect()),
+                });
+            }
+            let len = count(r) as i64;
+            let m = n.abs();
+            if len == 0 {
+                return if m == 0 {
+                    Ok(r.clone())
+                } else {
+                    Err(error("length", l, r))
+                };
+            }
+            // negative take counts from the end, both directions wrap around
+            let start = if n < 0 { ((len - m) % len + len) % len } else { 0 };
+            let idx = (0..m).map(|i| ((start + i) % len) as usize).collect::<Vec<usize>>();
+            Ok(select(r, &idx))
+        }
+        AST::Atom(Atom::Value(Value::VecInt(ref dims))) => {
+            // reshape
+            if dims.len() == 0 || dims.iter().any(|&d| d < 0) || count(r) == 0 {
+                return Err(error("domain", l, r));
+            }
+            let src = if is_list(r) { r.clone() } else { AST::Vector(vec![r.clone()]) };
+            Ok(shape(dims, &src, &mut 0))
+        }
+        _ => Err(error("type", l, r)),
+    }
+}
+
+pub fn drop<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    match *l {
+        AST::Atom(Atom::Value(Value::Number(n))) => {
+            if !is_list(r) {
+                return Err(error("type", l, r));
+            }
+            let len = count(r);
+            let m = ::std::cmp::min(n.abs() as usize, len);
+            let idx = if n < 0 {
+                (0..len - m).collect::<Vec<usize>>()
+            } else {
+                (m..len).collect::<Vec<usize>>()
+            };
+            Ok(select(r, &idx))
+        }
+        AST::Atom(Atom::Value(Value::VecInt(ref cuts))) => {
+            // cut: x are ascending start indices into y
+            let len = count(r) as i64;
+            if !is_list(r) || cuts.windows(2).any(|w| w[0] > w[1]) ||
+               cuts.iter().any(|&c| c < 0 || c > len) {
+                return Err(error("domain", l, r));
+            }
+            Ok(AST::Vector((0..cuts.len())
+                .map(|i| {
+                    let end = if i + 1 < cuts.len() { cuts[i + 1] } else { len };
+                    select(r, &(cuts[i] as usize..end as usize).collect::<Vec<usize>>())
+                })
+                .collect()))
+        }
+        _ => Err(error("type", l, r)),
+    }
+}
+
+pub fn concat<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    match (l, r) {
+        (&AST::Atom(Atom::Value(Value::Table(ref t))), &AST::Atom(Atom::Value(Value::Table(ref u)))) => {
+            return Ok(AST::Atom(Atom::Value(Value::Table(try!(t.append(u))))));
+        }
+        (&AST::Atom(Atom::Value(Value::Table(ref t))), _) => {
+            return Ok(AST::Atom(Atom::Value(Value::Table(try!(t.upsert(try!(row(r))))))));
+        }
+        _ => (),
+    }
+    let mut v = items(l);
+    v.extend(items(r));
+    Ok(collapse(v))
+}
+
+pub fn row<'a>(x: &AST<'a>) -> Result<Vec<Value>, Error> {
+    // t,(1;2.5;`a) appends one row
+    let mut v = Vec::with_capacity(count(x));
+    for i in items(x) {
+        match i {
+            AST::Atom(Atom::Value(a)) => v.push(a),
+            a => return Err(error("type", x, &a)),
+        }
+    }
+    Ok(v)
+}
+
+pub fn except<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    if !is_list(l) {
+        return Err(error("type", l, r));
+    }
+    let drop = items(r);
+    let idx = items(l)
+        .iter()
+        .enumerate()
+        .filter(|&(_, x)| !drop.iter().any(|y| same(x, y)))
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    Ok(select(l, &idx))
+}
+
+pub fn find<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    if !is_list(l) {
+        return Err(error("type", l, r));
+    }
+    let n = count(l);
+    match (l, r) {
+        (&AST::Atom(Atom::Value(Value::VecInt(ref x))),
+         &AST::Atom(Atom::Value(Value::VecInt(ref y)))) => {
+            let mut first = HashMap::new();
+            for (i, k) in x.iter().enumerate().rev() {
+                first.insert(*k, i as i64);
+            }
+            Ok(AST::Atom(Atom::Value(Value::VecInt(y.iter()
+                .map(|k| *first.get(k).unwrap_or(&(n as i64)))
+                .collect()))))
+        }
+        (_, &AST::Atom(Atom::Value(Value::VecInt(_)))) |
+        (_, &AST::Atom(Atom::Value(Value::VecFloat(_)))) => {
+            let xs = items(l);
+            Ok(AST::Atom(Atom::Value(Value::VecInt(items(r)
+                .iter()
+                .map(|y| xs.iter().position(|x| same(x, y)).unwrap_or(n) as i64)
+                .collect()))))
+        }
+        _ => {
+            let i = items(l).iter().position(|x| same(x, r)).unwrap_or(n);
+            Ok(AST::Atom(Atom::Value(Value::Number(i as i64))))
+        }
+    }
+}
+
+pub fn at<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    if !is_list(l) {
+        return Err(error("rank", l, r));
+    }
+    index(l, &items(l), r)
+}
+
+fn index<'a, 'b>(l: &'b AST<'a>, xs: &[AST<'a>], r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    // xs are the items of l, split once for every index in r
+    match *r {
+        AST::Atom(Atom::Value(Value::Number(i))) => {
+            if i >= 0 && (i as usize) < xs.len() {
+                Ok(xs[i as usize].clone())
+            } else {
+                Ok(null(l))
+            }
+        }
+        AST::Atom(Atom::Value(Value::VecInt(_))) |
+        AST::Vector(_) => {
+            let mut v = Vec::with_capacity(count(r));
+            for i in items(r) {
+                v.push(try!(index(l, xs, &i)));
+            }
+            Ok(match *r {
+                AST::Vector(_) => AST::Vector(v),
+                _ => collapse(v),
+            })
+        }
+        _ => Err(error("type", l, r)),
+    }
+}
+
+pub fn same<'a>(x: &AST<'a>, y: &AST<'a>) -> bool {
+    // typed and general lists with equal items match
+    match (is_list(x), is_list(y)) {
+        (true, true) => {
+            count(x) == count(y) && items(x).iter().zip(items(y).iter()).all(|(a, b)| same(a, b))
+        }
+        (false, false) => {
+            match (x, y) {
+                (&AST::Atom(Atom::Value(Value::Float(a))),
+                 &AST::Atom(Atom::Value(Value::Float(b)))) => a == b || (a.is_nan() && b.is_nan()),
+                _ => x == y,
+            }
+        }
+        _ => false,
+    }
+}
+
+pub fn match_<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    Ok(AST::Atom(Atom::Value(Value::Number(if same(l, r) { 1 } else { 0 }))))
+}
+
+fn cast_atom<'a>(t: i64, x: &AST<'a>) -> Option<AST<'a>> {
+    // q type codes: 1 boolean, 7 long, 9 float
+    match (t.abs(), x) {
+        (1, &AST::Atom(Atom::Value(Value::Number(n)))) => {
+            Some(AST::Atom(Atom::Value(Value::Number(if n != 0 { 1 } else { 0 }))))
+        }
+        (1, &AST::Atom(Atom::Value(Value::Float(n)))) => {
+            Some(AST::Atom(Atom::Value(Value::Number(if n != 0.0 { 1 } else { 0 }))))
+        }
+        (7, &AST::Atom(Atom::Value(Value::Number(n)))) => Some(AST::Atom(Atom::Value(Value::Number(n)))),
+        (7, &AST::Atom(Atom::Value(Value::Float(n)))) => {
+            Some(AST::Atom(Atom::Value(Value::Number(if n.is_nan() { NULL_INT } else { n.round() as i64 }))))
+        }
+        (9, &AST::Atom(Atom::Value(Value::Number(n)))) => {
+            Some(AST::Atom(Atom::Value(Value::Float(if n == NULL_INT { ::std::f64::NAN } else { n as f64 }))))
+        }
+        (9, &AST::Atom(Atom::Value(Value::Float(n)))) => Some(AST::Atom(Atom::Value(Value::Float(n)))),
+        _ => None,
+    }
+}
+
+pub fn cast<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    let t = match (l, r) {
+        (&AST::Atom(Atom::Value(Value::Number(t))), _) => t,
+        // `$"abc" makes a symbol from a string
+        (&AST::Atom(Atom::Value(Value::SymbolInt(_))), &AST::Atom(Atom::Value(Value::Sequence(ref s)))) => {
+            return symbol::intern(s).map(|x| AST::Atom(Atom::Value(Value::SymbolInt(x)))).map_err(|e| error(e, l, r));
+        }
+        (&AST::Atom(Atom::Value(Value::SymbolInt(_))), &AST::Atom(Atom::Value(Value::Char(c)))) => {
+            return symbol::intern(&c.to_string()).map(|x| AST::Atom(Atom::Value(Value::SymbolInt(x)))).map_err(|e| error(e, l, r));
+        }
+        (&AST::Atom(Atom::Value(Value::SymbolInt(_))), &AST::Vector(_)) => 11,
+        _ => return Err(error("type", l, r)),
+    };
+    if !is_list(r) {
+        return cast_atom(t, r).ok_or(error("type", l, r));
+    }
+    let mut v = Vec::with_capacity(count(r));
+    for x in items(r) {
+        v.push(try!(cast(l, &x)));
+    }
+    Ok(collapse(v))
+}
@@ -1,6 +1,14 @@
 pub mod dot;
+pub mod monad;
+pub mod list;
+pub mod dict;
+pub mod text;
+pub mod temporal;
+pub mod query;
+pub mod simd;
 
 use commands::ast::*;
+use commands::ipc;
 
 // workaround for quoting operators
 // see https://github.com/rust-lang/rust/issues/8853
@@ -35,6 +43,33 @@ macro_rules! dyad_map_expr {
     ($l:tt, <=, $r:tt, $sink:tt) => {
         if $l <= $r { 1 } else { 0 }
     };
+    ($l:tt, min, $r:tt, $sink:tt) => {
+        if $l < $r { $l.clone() } else { $r.clone() }
+    };
+    ($l:tt, max, $r:tt, $sink:tt) => {
+        if $l > $r { $l.clone() } else { $r.clone() }
+    };
+    ($l:tt, mod, $r:tt, Number) => {
+        // k: x!y is y modulo x, result takes the sign of x; 0N!-1 and -1!0N can't overflow
+        {
+            let d = $l.clone();
+            let m = if d == 0 { $r.clone() } else { $r.clone().wrapping_rem(d) };
+            if d != 0 && m != 0 && (m < 0) != (d < 0) { m.wrapping_add(d) } else { m }
+        }
+    };
+    // ints wrap like q's and the SIMD kernels do: 0N-1 is 0W, 0W+1 is 0N
+    ($l:tt, +, $r:tt, Number) => {
+        $l.clone().wrapping_add($r.clone())
+    };
+    ($l:tt, -, $r:tt, Number) => {
+        $l.clone().wrapping_sub($r.clone())
+    };
+    ($l:tt, *, $r:tt, Number) => {
+        $l.clone().wrapping_mul($r.clone())
+    };
+    ($l:tt, mod, $r:tt, Float) => {
+        { let d = $l.clone(); if d == 0.0 { $r.clone() } else { $r.clone() - d * ($r.clone() / d).floor() } }
+    };
     ($l:tt, $op:tt, $r:tt, $sink:tt) => {
         // all simple operators
         op!($l $op $r)
@@ -67,6 +102,12 @@ macro_rules! dyad_arith_match{
 
                 (&AST::Atom(Atom::Value(Value::$vec(ref l))),
                  &AST::Atom(Atom::Value(Value::$vec(ref r)))) => {
+                    if l.len() != r.len() {
+                        return Err(Error::EvalError {
+                            desc: "length".to_string(),
+                            ast: format!("{:?} {:?}", $l, $r),
+                        });
+                    }
                     let a: Vec<$atype> = l.iter()
                         .zip(r)
                         .map(|(l,r)| dyad_map_expr!(l,$op,r,$atom))
@@ -89,7 +130,7 @@ macro_rules! dyad_arith{
             use commands::ast::Value;
             use commands::ast::{AST, Atom, Error};
 
-            pub fn eval<'a>(l: &'a AST<'a>, r: &'a AST<'a>) -> Result<AST<'a>, Error> {
+            pub fn eval<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
 
                 dyad_arith_match!( $name, l, r,
                                    [$op, $atype, Number, VecInt, $r_atom, $r_vec],
@@ -102,7 +143,7 @@ macro_rules! dyad_arith{
             use commands::ast::Value;
             use commands::ast::{AST, Atom, Error};
 
-            pub fn eval<'a>(l: &'a AST<'a>, r: &'a AST<'a>) -> Result<AST<'a>, Error> {
+            pub fn eval<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
 
                 dyad_arith_match!( $name, l, r,
                                    [$op, i64, Number, VecInt, Number, VecInt],
@@ -116,6 +157,9 @@ dyad_arith!(plus, "Dyad plus", +);
 dyad_arith!(minus, "Dyad minus", -);
 dyad_arith!(mul, "Dyad mul", *);
 dyad_arith!(div, "Dyad div", /);
+dyad_arith!(min, "Dyad min", min);
+dyad_arith!(max, "Dyad max", max);
+dyad_arith!(rem, "Dyad mod", mod);
 dyad_arith!(eq, "Dyad eq", =, i64, Number, VecInt);
 dyad_arith!(neq, "Dyad neq", !=, i64, Number, VecInt);
 dyad_arith!(gt, "Dyad gt", >, i64, Number, VecInt);
@@ -123,18 +167,305 @@ dyad_arith!(ge, "Dyad ge", >=, i64, Number, VecInt);
 dyad_arith!(lt, "Dyad lt", <, i64, Number, VecInt);
 dyad_arith!(le, "Dyad le", <=, i64, Number, VecInt);
 
-pub fn eval<'ast>(verb: Verb, left: &'ast AST<'ast>, right: &'ast AST<'ast>) -> Result<AST<'ast>, Error> {
+// int null (0N), floats use NaN
+pub const NULL_INT: i64 = ::std::i64::MIN;
+
+pub fn is_value<'a>(x: &AST<'a>) -> bool {
+    match *x {
+        AST::Atom(Atom::Value(_)) |
+        AST::Vector(_) => true,
+        _ => false,
+    }
+}
+
+pub fn is_list<'a>(x: &AST<'a>) -> bool {
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(_))) |
+        AST::Atom(Atom::Value(Value::VecFloat(_))) |
+        AST::Atom(Atom::Value(Value::VecSym(_))) |
+        AST::Atom(Atom::Value(Value::Sequence(_))) |
+        AST::Atom(Atom::Value(Value::VecTemporal(..))) |
+        AST::Vector(_) => true,
+        _ => false,
+    }
+}
+
+pub fn count<'a>(x: &AST<'a>) -> usize {
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => v.len(),
+        AST::Atom(Atom::Value(Value::VecFloat(ref v))) => v.len(),
+        AST::Atom(Atom::Value(Value::VecSym(ref v))) => v.len(),
+        AST::Atom(Atom::Value(Value::Sequence(ref s))) if s.is_ascii() => s.len(),
+        AST::Atom(Atom::Value(Value::Sequence(ref s))) => s.chars().count(),
+        AST::Atom(Atom::Value(Value::VecTemporal(_, ref v))) => v.len(),
+        AST::Atom(Atom::Value(Value::Table(ref t))) => t.count(),
+        AST::Atom(Atom::Value(Value::Dict(ref d))) => d.count(),
+        AST::Vector(ref v) => v.len(),
+        _ => 1,
+    }
+}
+
+pub fn item<'a>(x: &AST<'a>, i: usize) -> AST<'a> {
+    // i-th item of a list, atoms are their own items
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => AST::Atom(Atom::Value(Value::Number(v[i]))),
+        AST::Atom(Atom::Value(Value::VecFloat(ref v))) => AST::Atom(Atom::Value(Value::Float(v[i]))),
+        AST::Atom(Atom::Value(Value::VecSym(ref v))) => AST::Atom(Atom::Value(Value::SymbolInt(v[i]))),
+        AST::Atom(Atom::Value(Value::Sequence(ref s))) if s.is_ascii() => {
+            // ascii strings index their bytes, others walk their chars
+            AST::Atom(Atom::Value(Value::Char(s.as_bytes().get(i).map_or(' ', |&b| b as char))))
+        }
+        AST::Atom(Atom::Value(Value::Sequence(ref s))) => {
+            AST::Atom(Atom::Value(Value::Char(s.chars().nth(i).unwrap_or(' '))))
+        }
+        AST::Atom(Atom::Value(Value::VecTemporal(k, ref v))) => AST::Atom(Atom::Value(Value::Temporal(k, v[i]))),
+        AST::Vector(ref v) => v[i].clone(),
+        ref a => a.clone(),
+    }
+}
+
+pub fn items<'a>(x: &AST<'a>) -> Vec<AST<'a>> {
+    // item walks a string from its start, so split it in one pass
+    match *x {
+        AST::Atom(Atom::Value(Value::Sequence(ref s))) => {
+            s.chars().map(|c| AST::Atom(Atom::Value(Value::Char(c)))).collect()
+        }
+        _ => (0..count(x)).map(|i| item(x, i)).collect(),
+    }
+}
+
+pub fn zip<'a>(l: &AST<'a>, r: &AST<'a>) -> Vec<(AST<'a>, AST<'a>)> {
+    // pairs for an atomic dyad, an atom side is repeated against the list
+    match (is_list(l), is_list(r)) {
+        (true, true) => items(l).into_iter().zip(items(r)).collect(),
+        (true, false) => items(l).into_iter().map(|a| (a, r.clone())).collect(),
+        (false, _) => items(r).into_iter().map(|b| (l.clone(), b)).collect(),
+    }
+}
+
+pub fn to_value<'a>(x: &AST<'a>) -> Option<Value> {
+    // general lists are stored as Value::List inside dictionaries
+    match *x {
+        AST::Atom(Atom::Value(ref v)) => Some(v.clone()),
+        AST::Vector(ref v) => {
+            let mut r = Vec::with_capacity(v.len());
+            for i in v.iter() {
+                match to_value(i) {
+                    Some(a) => r.push(a),
+                    None => return None,
+                }
+            }
+            Some(Value::List(r))
+        }
+        _ => None,
+    }
+}
+
+pub fn from_value<'a>(v: Value) -> AST<'a> {
+    match v {
+        Value::List(v) => AST::Vector(v.into_iter().map(from_value).collect()),
+        v => AST::Atom(Atom::Value(v)),
+    }
+}
+
+pub fn collapse<'a>(v: Vec<AST<'a>>) -> AST<'a> {
+    // general list of atoms => VecInt/VecFloat/VecSym/string when uniform
+    let ints = v.len() > 0 &&
+               v.iter().all(|x| match *x {
+        AST::Atom(Atom::Value(Value::Number(_))) => true,
+        _ => false,
+    });
+    let floats = v.len() > 0 &&
+                 v.iter().all(|x| match *x {
+        AST::Atom(Atom::Value(Value::Float(_))) => true,
+        _ => false,
+    });
+    let symbols = v.len() > 0 &&
+                  v.iter().all(|x| match *x {
+        AST::Atom(Atom::Value(Value::SymbolInt(_))) => true,
+        _ => false,
+    });
+    let chars = v.len() > 0 &&
+                v.iter().all(|x| match *x {
+        AST::Atom(Atom::Value(Value::Char(_))) => true,
+        _ => false,
+    });
+    let kind = match v.first() {
+        Some(&AST::Atom(Atom::Value(Value::Temporal(k, _)))) => Some(k),
+        _ => None,
+    };
+    let temporals = kind.is_some() &&
+                    v.iter().all(|x| match *x {
+        AST::Atom(Atom::Value(Value::Temporal(k, _))) => Some(k) == kind,
+        _ => false,
+    });
+    if temporals {
+        return AST::Atom(Atom::Value(Value::VecTemporal(kind.unwrap(),
+                                                        v.iter()
+                                                            .map(|x| match *x {
+                                                                AST::Atom(Atom::Value(Value::Temporal(_, n))) => n,
+                                                                _ => 0,
+                                                            })
+                                                            .collect())));
+    }
+    if chars {
+        return AST::Atom(Atom::Value(Value::Sequence(v.iter()
+            .map(|x| match *x {
+                AST::Atom(Atom::Value(Value::Char(c))) => c,
+                _ => ' ',
+            })
+            .collect())));
+    }
+    if symbols {
+        return AST::Atom(Atom::Value(Value::VecSym(v.iter()
+            .map(|x| match *x {
+                AST::Atom(Atom::Value(Value::SymbolInt(n))) => n,
+                _ => 0,
+            })
+            .collect())));
+    }
+    if ints {
+        AST::Atom(Atom::Value(Value::VecInt(v.iter()
+            .map(|x| match *x {
+                AST::Atom(Atom::Value(Value::Number(n))) => n,
+                _ => 0,
+            })
+            .collect())))
+    } else if floats {
+        AST::Atom(Atom::Value(Value::VecFloat(v.iter()
+            .map(|x| match *x {
+                AST::Atom(Atom::Value(Value::Float(n))) => n,
+                _ => 0.0,
+            })
+            .collect())))
+    } else {
+        AST::Vector(v)
+    }
+}
+
+fn is_float<'a>(x: &AST<'a>) -> bool {
+    match *x {
+        AST::Atom(Atom::Value(Value::Float(_))) |
+        AST::Atom(Atom::Value(Value::VecFloat(_))) => true,
+        _ => false,
+    }
+}
+
+fn to_float<'a>(x: &AST<'a>) -> AST<'a> {
+    // int => float promotion, 0N becomes 0n
+    let f = |n: i64| if n == NULL_INT { ::std::f64::NAN } else { n as f64 };
+    match *x {
+        AST::Atom(Atom::Value(Value::Number(n))) => AST::Atom(Atom::Value(Value::Float(f(n)))),
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => {
+            AST::Atom(Atom::Value(Value::VecFloat(v.iter().map(|n| f(*n)).collect())))
+        }
+        ref a => a.clone(),
+    }
+}
+
+fn fdiv<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    // % is always float division
+    div::eval(&to_float(l), &to_float(r))
+}
+
+fn internal<'a, 'b>(n: i64, x: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    // -8!x is x as kdb+ IPC bytes, -9!x reads them back;
+    // there is no byte type, so bytes travel as an int vector
+    let r = match (n, x) {
+        (-8, x) => {
+            match to_value(x) {
+                Some(v) => ipc::encode(&v, ipc::ASYNC, false).map(|b| Value::VecInt(b.iter().map(|c| *c as i64).collect())),
+                None => Err(Error::EvalError {
+                    desc: "-8!: type".to_string(),
+                    ast: format!("{:?}", x),
+                }),
+            }
+        }
+        (_, &AST::Atom(Atom::Value(Value::VecInt(ref b)))) if b.iter().all(|c| *c >= 0 && *c < 256) => {
+            ipc::decode(&b.iter().map(|c| *c as u8).collect::<Vec<u8>>()).map(|(_, v)| v)
+        }
+        (_, x) => {
+            Err(Error::EvalError {
+                desc: "-9!: type".to_string(),
+                ast: format!("{:?}", x),
+            })
+        }
+    };
+    r.map(from_value)
+}
+
+fn atomic<'a, 'b, F>(f: &F, l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error>
+    where F: for<'c> Fn(&'c AST<'a>, &'c AST<'a>) -> Result<AST<'a>, Error>
+{
+    // typed operands go straight to f, general lists pair up item by item
+    let general = |x: &AST<'a>| match *x {
+        AST::Vector(_) => true,
+        _ => false,
+    };
+    if !general(l) && !general(r) {
+        return match (is_float(l), is_float(r)) {
+            (true, false) => f(l, &to_float(r)),
+            (false, true) => f(&to_float(l), r),
+            _ => f(l, r),
+        };
+    }
+    if is_list(l) && is_list(r) && count(l) != count(r) {
+        return Err(Error::EvalError {
+            desc: "length".to_string(),
+            ast: format!("{:?} {:?}", l, r),
+        });
+    }
+    let mut v = Vec::with_capacity(count(l).max(count(r)));
+    for (a, b) in zip(l, r) {
+        v.push(try!(atomic(f, &a, &b)));
+    }
+    Ok(collapse(v))
+}
+
+pub fn eval<'a, 'b>(verb: Verb, left: &'b AST<'a>, right: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    match (verb, left) {
+        (Verb::Mod, &AST::Atom(Atom::Value(Value::Number(n)))) if n == -8 || n == -9 => return internal(n, right),
+        _ => (),
+    }
+    if dict::is_dict(left) || dict::is_dict(right) {
+        return dict::eval(verb, left, right);
+    }
+    if let Some(r) = simd::dyad(verb, left, right) {
+        return Ok(r);
+    }
     match verb {
-        Verb::Plus => plus::eval(left, right),
-        Verb::Minus => minus::eval(left, right),
-        Verb::Times => mul::eval(left, right),
-        Verb::Divide => div::eval(left, right),
-        Verb::Eq => eq::eval(left, right),
-        Verb::NEq => neq::eval(left, right),
-        Verb::Gt => gt::eval(left, right),
-        Verb::Ge => ge::eval(left, right),
-        Verb::Lt => lt::eval(left, right),
-        Verb::Le => le::eval(left, right),
+        Verb::Eq | Verb::NEq | Verb::Gt | Verb::Ge | Verb::Lt | Verb::Le
+            if text::is_text(left) || text::is_text(right) => text::compare(verb, left, right),
+        Verb::Plus | Verb::Minus | Verb::Times | Verb::Min | Verb::Max | Verb::Eq | Verb::NEq | Verb::Gt |
+        Verb::Ge | Verb::Lt | Verb::Le | Verb::Dot
+            if temporal::is_temporal(left) || temporal::is_temporal(right) => temporal::eval(verb, left, right),
+        Verb::Plus => atomic(&plus::eval, left, right),
+        Verb::Minus => atomic(&minus::eval, left, right),
+        Verb::Times => atomic(&mul::eval, left, right),
+        Verb::Divide => atomic(&fdiv, left, right),
+        Verb::Eq => atomic(&eq::eval, left, right),
+        Verb::NEq => atomic(&neq::eval, left, right),
+        Verb::Gt => atomic(&gt::eval, left, right),
+        Verb::Ge => atomic(&ge::eval, left, right),
+        Verb::Lt => atomic(&lt::eval, left, right),
+        Verb::Le => atomic(&le::eval, left, right),
+        Verb::Min => atomic(&min::eval, left, right),
+        Verb::Max => atomic(&max::eval, left, right),
+        Verb::Mod if is_list(left) => dict::make(left, right),
+        Verb::Mod => {
+            match right {
+                &AST::Atom(Atom::Value(Value::Table(_))) => dict::make(left, right),
+                _ => atomic(&rem::eval, left, right),
+            }
+        }
+        Verb::Take => list::take(left, right),
+        Verb::Drop => list::drop(left, right),
+        Verb::Concat => list::concat(left, right),
+        Verb::Except => list::except(left, right),
+        Verb::Find => list::find(left, right),
+        Verb::At => list::at(left, right),
+        Verb::Match => list::match_(left, right),
+        Verb::Cast => list::cast(left, right),
         x => {
             Err(Error::EvalError {
                 desc: "Verb is not implemented".to_string(),
@@ -0,0 +1,417 @@
+
+// Monadic verbs: the "a" and "l" columns of the k verb table
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use commands::ast::{AST, Atom, Value, Verb, Monadic, Error};
+use commands::{symbol, kfmt};
+use commands::temporal::Temporal;
+use streams::verb::{list, dict, NULL_INT, count, item, items, collapse, from_value};
+
+fn error<'a>(desc: &str, x: &AST<'a>) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{:?}", x),
+    }
+}
+
+fn int<'a>(n: i64) -> AST<'a> {
+    AST::Atom(Atom::Value(Value::Number(n)))
+}
+
+fn float<'a>(n: f64) -> AST<'a> {
+    AST::Atom(Atom::Value(Value::Float(n)))
+}
+
+fn vec_int<'a>(v: Vec<i64>) -> AST<'a> {
+    AST::Atom(Atom::Value(Value::VecInt(v)))
+}
+
+fn vec_float<'a>(v: Vec<f64>) -> AST<'a> {
+    AST::Atom(Atom::Value(Value::VecFloat(v)))
+}
+
+fn atomic<'a, F>(x: &AST<'a>, f: &F) -> Result<AST<'a>, Error>
+    where F: Fn(&AST<'a>) -> Result<AST<'a>, Error>
+{
+    // apply f to every atom, general lists keep their shape
+    match *x {
+        AST::Vector(ref v) => {
+            let mut r = Vec::with_capacity(v.len());
+            for i in v.iter() {
+                r.push(try!(atomic(i, f)));
+            }
+            Ok(collapse(r))
+        }
+        AST::Atom(Atom::Value(Value::VecInt(_))) |
+        AST::Atom(Atom::Value(Value::VecFloat(_))) => {
+            let mut r = Vec::with_capacity(count(x));
+            for i in items(x) {
+                r.push(try!(f(&i)));
+            }
+            Ok(collapse(r))
+        }
+        ref a => f(a),
+    }
+}
+
+fn sort_key<'a>(x: &AST<'a>) -> f64 {
+    match *x {
+        AST::Atom(Atom::Value(Value::Number(n))) => n as f64,
+        AST::Atom(Atom::Value(Value::Float(n))) => n,
+        _ => 0.0,
+    }
+}
+
+pub fn flip<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        AST::Vector(ref v) if v.len() > 0 => {
+            let n = v.iter().map(|c| count(c)).max().unwrap_or(0);
+            if v.iter().any(|c| count(c) != n && count(c) != 1) {
+                return Err(error("length", x));
+            }
+            let cols = v.iter().map(items).collect::<Vec<Vec<AST<'a>>>>();
+            Ok(AST::Vector((0..n)
+                .map(|i| collapse(cols.iter().map(|c| if c.len() == 1 { c[0].clone() } else { c[i].clone() }).collect()))
+                .collect()))
+        }
+        ref a => Ok(a.clone()),
+    }
+}
+
+pub fn negate<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        // 0N is the most negative int, wrapping keeps it null
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => Ok(vec_int(v.iter().map(|x| x.wrapping_neg()).collect())),
+        AST::Atom(Atom::Value(Value::VecFloat(ref v))) => Ok(vec_float(v.iter().map(|x| -x).collect())),
+        _ => {
+            atomic(x, &|a| match *a {
+                AST::Atom(Atom::Value(Value::Number(n))) => Ok(int(n.wrapping_neg())),
+                AST::Atom(Atom::Value(Value::Float(n))) => Ok(float(-n)),
+                AST::Atom(Atom::Value(Value::Temporal(k @ Temporal::Timespan, n))) |
+                AST::Atom(Atom::Value(Value::Temporal(k @ Temporal::Time, n))) => {
+                    Ok(AST::Atom(Atom::Value(Value::Temporal(k, if n == NULL_INT { n } else { -n }))))
+                }
+                ref a => Err(error("type", a)),
+            })
+        }
+    }
+}
+
+pub fn first<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => Ok(int(*v.first().unwrap_or(&NULL_INT))),
+        AST::Atom(Atom::Value(Value::VecFloat(ref v))) => Ok(float(*v.first().unwrap_or(&::std::f64::NAN))),
+        AST::Vector(ref v) => Ok(v.first().cloned().unwrap_or(AST::Atom(Atom::Value(Value::Nil)))),
+        AST::Atom(Atom::Value(Value::VecSym(_))) |
+        AST::Atom(Atom::Value(Value::VecTemporal(..))) |
+        AST::Atom(Atom::Value(Value::Sequence(_))) => Ok(if count(x) > 0 { item(x, 0) } else { list::null(x) }),
+        ref a => Ok(a.clone()),
+    }
+}
+
+pub fn sqrt<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    atomic(x, &|a| match *a {
+        AST::Atom(Atom::Value(Value::Number(n))) => Ok(float((n as f64).sqrt())),
+        AST::Atom(Atom::Value(Value::Float(n))) => Ok(float(n.sqrt())),
+        ref a => Err(error("type", a)),
+    })
+}
+
+pub fn iota<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        AST::Atom(Atom::Value(Value::Dict(ref d))) => Ok(from_value(d.keys.clone())),
+        AST::Atom(Atom::Value(Value::Number(n))) if n >= 0 => Ok(vec_int((0..n).collect())),
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) if v.iter().all(|d| *d >= 0) => {
+            // odometer: !2 3 => (0 0 0 1 1 1;0 1 2 0 1 2)
+            let total = v.iter().fold(1, |acc, d| acc * d);
+            let mut rows = vec![vec![0i64; total as usize]; v.len()];
+            let mut repeat = total;
+            for (r, d) in v.iter().enumerate() {
+                if *d == 0 {
+                    break;
+                }
+                repeat = repeat / d;
+                for i in 0..total {
+                    rows[r][i as usize] = (i / repeat) % d;
+                }
+            }
+            Ok(AST::Vector(rows.into_iter().map(vec_int).collect()))
+        }
+        AST::Atom(Atom::Value(Value::Number(_))) |
+        AST::Atom(Atom::Value(Value::VecInt(_))) => Err(error("domain", x)),
+        ref a => Err(error("type", a)),
+    }
+}
+
+pub fn where_<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        AST::Atom(Atom::Value(Value::Number(n))) if n >= 0 => Ok(vec_int(vec![0; n as usize])),
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) if v.iter().all(|d| *d >= 0) => {
+            let mut r = Vec::with_capacity(v.iter().fold(0, |acc, d| acc + d) as usize);
+            for (i, d) in v.iter().enumerate() {
+                for _ in 0..*d {
+                    r.push(i as i64);
+                }
+            }
+            Ok(vec_int(r))
+        }
+        AST::Vector(ref v) if v.len() == 0 => Ok(vec_int(vec![])),
+        AST::Atom(Atom::Value(Value::Number(_))) |
+        AST::Atom(Atom::Value(Value::VecInt(_))) => Err(error("domain", x)),
+        ref a => Err(error("type", a)),
+    }
+}
+
+pub fn rev<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => Ok(vec_int(v.iter().rev().cloned().collect())),
+        AST::Atom(Atom::Value(Value::VecFloat(ref v))) => Ok(vec_float(v.iter().rev().cloned().collect())),
+        AST::Vector(ref v) => Ok(AST::Vector(v.iter().rev().cloned().collect())),
+        AST::Atom(Atom::Value(Value::VecSym(ref v))) => {
+            Ok(AST::Atom(Atom::Value(Value::VecSym(v.iter().rev().cloned().collect()))))
+        }
+        AST::Atom(Atom::Value(Value::Sequence(ref s))) => {
+            Ok(AST::Atom(Atom::Value(Value::Sequence(s.chars().rev().collect()))))
+        }
+        AST::Atom(Atom::Value(Value::VecTemporal(k, ref v))) => {
+            Ok(AST::Atom(Atom::Value(Value::VecTemporal(k, v.iter().rev().cloned().collect()))))
+        }
+        ref a => Ok(a.clone()),
+    }
+}
+
+pub fn grade<'a>(x: &AST<'a>, desc: bool) -> Result<AST<'a>, Error> {
+    // stable sort, equal items keep their relative order in both directions
+    let mut idx: Vec<usize> = (0..count(x)).collect();
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) |
+        AST::Atom(Atom::Value(Value::VecTemporal(_, ref v))) => {
+            if desc {
+                idx.sort_by(|a, b| v[*b].cmp(&v[*a]))
+            } else {
+                idx.sort_by(|a, b| v[*a].cmp(&v[*b]))
+            }
+        }
+        AST::Atom(Atom::Value(Value::VecFloat(ref v))) => {
+            if desc {
+                idx.sort_by(|a, b| v[*b].partial_cmp(&v[*a]).unwrap_or(Ordering::Equal))
+            } else {
+                idx.sort_by(|a, b| v[*a].partial_cmp(&v[*b]).unwrap_or(Ordering::Equal))
+            }
+        }
+        AST::Atom(Atom::Value(Value::VecSym(ref v))) => {
+            // by text
+            let k = symbol::names(v);
+            if desc {
+                idx.sort_by(|a, b| k[*b].cmp(&k[*a]))
+            } else {
+                idx.sort_by(|a, b| k[*a].cmp(&k[*b]))
+            }
+        }
+        AST::Atom(Atom::Value(Value::Sequence(ref s))) => {
+            let v = s.chars().collect::<Vec<char>>();
+            if desc {
+                idx.sort_by(|a, b| v[*b].cmp(&v[*a]))
+            } else {
+                idx.sort_by(|a, b| v[*a].cmp(&v[*b]))
+            }
+        }
+        AST::Vector(ref v) => {
+            let k: Vec<f64> = v.iter().map(sort_key).collect();
+            if desc {
+                idx.sort_by(|a, b| k[*b].partial_cmp(&k[*a]).unwrap_or(Ordering::Equal))
+            } else {
+                idx.sort_by(|a, b| k[*a].partial_cmp(&k[*b]).unwrap_or(Ordering::Equal))
+            }
+        }
+        ref a => return Err(error("rank", a)),
+    }
+    Ok(vec_int(idx.into_iter().map(|i| i as i64).collect()))
+}
+
+pub fn group<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    // k3 group: list of index lists in order of first occurrence
+    let mut groups: Vec<Vec<i64>> = vec![];
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => {
+            let mut seen: HashMap<i64, usize> = HashMap::new();
+            for (i, k) in v.iter().enumerate() {
+                let g = *seen.entry(*k).or_insert(groups.len());
+                if g == groups.len() {
+                    groups.push(vec![]);
+                }
+                groups[g].push(i as i64);
+            }
+        }
+        AST::Atom(Atom::Value(Value::VecFloat(_))) |
+        AST::Atom(Atom::Value(Value::VecSym(_))) |
+        AST::Atom(Atom::Value(Value::VecTemporal(..))) |
+        AST::Atom(Atom::Value(Value::Sequence(_))) |
+        AST::Vector(_) => {
+            let all = items(x);
+            let mut keys: Vec<&AST<'a>> = vec![];
+            for (i, k) in all.iter().enumerate() {
+                match keys.iter().position(|u| *u == k) {
+                    Some(g) => groups[g].push(i as i64),
+                    None => {
+                        keys.push(k);
+                        groups.push(vec![i as i64]);
+                    }
+                }
+            }
+        }
+        ref a => return Err(error("rank", a)),
+    }
+    Ok(AST::Vector(groups.into_iter().map(vec_int).collect()))
+}
+
+pub fn not<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => {
+            Ok(vec_int(v.iter().map(|x| if *x == 0 { 1 } else { 0 }).collect()))
+        }
+        _ => {
+            atomic(x, &|a| match *a {
+                AST::Atom(Atom::Value(Value::Number(n))) => Ok(int(if n == 0 { 1 } else { 0 })),
+                AST::Atom(Atom::Value(Value::Float(n))) => Ok(int(if n == 0.0 { 1 } else { 0 })),
+                ref a => Err(error("type", a)),
+            })
+        }
+    }
+}
+
+pub fn enlist<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        AST::Atom(Atom::Value(Value::Number(n))) => Ok(vec_int(vec![n])),
+        AST::Atom(Atom::Value(Value::Float(n))) => Ok(vec_float(vec![n])),
+        AST::Atom(Atom::Value(Value::SymbolInt(s))) => Ok(AST::Atom(Atom::Value(Value::VecSym(vec![s])))),
+        ref a => Ok(AST::Vector(vec![a.clone()])),
+    }
+}
+
+pub fn null<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    atomic(x, &|a| match *a {
+        AST::Atom(Atom::Value(Value::Number(n))) => Ok(int(if n == NULL_INT { 1 } else { 0 })),
+        AST::Atom(Atom::Value(Value::Float(n))) => Ok(int(if n.is_nan() { 1 } else { 0 })),
+        AST::Atom(Atom::Value(Value::Temporal(_, n))) => Ok(int(if n == NULL_INT { 1 } else { 0 })),
+        AST::Atom(Atom::Value(Value::Nil)) => Ok(int(1)),
+        _ => Ok(int(0)),
+    })
+}
+
+fn floor_int(x: f64) -> i64 {
+    // 0n floors to 0N, infinities to 0W and -0W
+    let m = ::std::i64::MAX as f64;
+    if x.is_nan() {
+        NULL_INT
+    } else if x >= m {
+        ::std::i64::MAX
+    } else if x <= -m {
+        -::std::i64::MAX
+    } else {
+        x.floor() as i64
+    }
+}
+
+pub fn floor<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        AST::Atom(Atom::Value(Value::VecFloat(ref v))) => Ok(vec_int(v.iter().map(|x| floor_int(*x)).collect())),
+        _ => {
+            atomic(x, &|a| match *a {
+                AST::Atom(Atom::Value(Value::Number(n))) => Ok(int(n)),
+                AST::Atom(Atom::Value(Value::Float(n))) => Ok(int(floor_int(n))),
+                ref a => Err(error("type", a)),
+            })
+        }
+    }
+}
+
+pub fn unique<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref v))) => {
+            let mut seen: HashMap<i64, ()> = HashMap::new();
+            Ok(vec_int(v.iter().cloned().filter(|k| seen.insert(*k, ()).is_none()).collect()))
+        }
+        AST::Atom(Atom::Value(Value::VecFloat(_))) |
+        AST::Atom(Atom::Value(Value::VecSym(_))) |
+        AST::Atom(Atom::Value(Value::VecTemporal(..))) |
+        AST::Atom(Atom::Value(Value::Sequence(_))) |
+        AST::Vector(_) => {
+            let mut r: Vec<AST<'a>> = vec![];
+            for i in items(x) {
+                if !r.contains(&i) {
+                    r.push(i);
+                }
+            }
+            match *x {
+                AST::Vector(_) => Ok(AST::Vector(r)),
+                _ => Ok(collapse(r)),
+            }
+        }
+        ref a => Err(error("rank", a)),
+    }
+}
+
+pub fn type_<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    // q type codes: negative for atoms, positive for vectors
+    Ok(int(match *x {
+        AST::Atom(Atom::Value(Value::Number(_))) => -7,
+        AST::Atom(Atom::Value(Value::Float(_))) => -9,
+        AST::Atom(Atom::Value(Value::SymbolInt(_))) => -11,
+        AST::Atom(Atom::Value(Value::Char(_))) => -10,
+        AST::Atom(Atom::Value(Value::Sequence(_))) => 10,
+        AST::Atom(Atom::Value(Value::VecInt(_))) => 7,
+        AST::Atom(Atom::Value(Value::VecFloat(_))) => 9,
+        AST::Atom(Atom::Value(Value::VecSym(_))) => 11,
+        AST::Atom(Atom::Value(Value::Temporal(k, _))) => -k.code(),
+        AST::Atom(Atom::Value(Value::VecTemporal(k, _))) => k.code(),
+        AST::Atom(Atom::Value(Value::Table(_))) => 98,
+        AST::Atom(Atom::Value(Value::Dict(_))) => 99,
+        AST::Vector(_) => 0,
+        AST::Atom(Atom::Lambda(..)) => 100,
+        AST::Atom(Atom::Verb(..)) => 102,
+        _ => 101,
+    }))
+}
+
+pub fn value<'a>(x: &AST<'a>) -> Result<AST<'a>, Error> {
+    // source and calls run in the interpreter, what reaches here is data
+    match *x {
+        AST::Atom(Atom::Value(Value::Dict(_))) => dict::value(x).ok_or(error("type", x)),
+        AST::Atom(Atom::Value(Value::Sequence(_))) |
+        AST::Atom(Atom::Value(Value::Char(_))) => Err(error("eval: needs the interpreter", x)),
+        AST::Vector(_) => Err(error("rank", x)),
+        ref a => Ok(a.clone()),
+    }
+}
+
+pub fn eval<'a, 'b>(verb: Verb, x: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    match Monadic::from_verb(verb) {
+        Some(Monadic::Flip) => flip(x),
+        Some(Monadic::Negate) => negate(x),
+        Some(Monadic::First) => first(x),
+        Some(Monadic::Sqrt) => sqrt(x),
+        Some(Monadic::Iota) => iota(x),
+        Some(Monadic::Where) => where_(x),
+        Some(Monadic::Rev) => rev(x),
+        Some(Monadic::Asc) => grade(x, false),
+        Some(Monadic::Desc) => grade(x, true),
+        Some(Monadic::Group) => group(x),
+        Some(Monadic::Not) => not(x),
+        Some(Monadic::List) => enlist(x),
+        Some(Monadic::Nil) => null(x),
+        Some(Monadic::Count) => Ok(int(count(x) as i64)),
+        Some(Monadic::Floor) => floor(x),
+        Some(Monadic::Unique) => unique(x),
+        Some(Monadic::Type) => type_(x),
+        Some(Monadic::Fmt) => Ok(AST::Atom(Atom::Value(Value::Sequence(kfmt::fmt(x))))),
+        Some(Monadic::Eval) => value(x),
+        _ => {
+            Err(Error::EvalError {
+                desc: "Monadic verb is not implemented".to_string(),
+                ast: format!("{:?} {:?}", verb, x),
+            })
+        }
+    }
+}
@@ -0,0 +1,472 @@
+// q-sql: select/exec/update/delete lowered to column vector verbs
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use commands::ast::{AST, Atom, Value, Verb, Adverb, Sql, Error};
+use commands::symbol;
+use tables::{Table, Dict};
+use streams::{verb, adverb};
+use streams::verb::{NULL_INT, is_list, count, item, items, collapse, to_value};
+
+// names are resolved against the table columns first, then through lookup
+pub struct Scope<'s, 'a: 's> {
+    pub name: &'s Fn(u16) -> String,
+    pub lookup: &'s Fn(u16) -> Option<AST<'a>>,
+}
+
+fn error<'a>(desc: &str, x: &AST<'a>) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{:?}", x),
+    }
+}
+
+fn value<'a>(v: Value) -> AST<'a> {
+    AST::Atom(Atom::Value(v))
+}
+
+fn clause<'a, 'b>(x: &'b AST<'a>) -> &'b [AST<'a>] {
+    match *x {
+        AST::Vector(ref v) => v,
+        _ => &[],
+    }
+}
+
+fn body<'a, 'b>(x: &'b AST<'a>) -> &'b AST<'a> {
+    match *x {
+        AST::Atom(Atom::Assign(_, e)) => e,
+        ref e => e,
+    }
+}
+
+fn column<'a>(x: AST<'a>) -> Result<Value, Error> {
+    // query results become typed columns, mixed ints and floats promote
+    let x = match x {
+        AST::Vector(v) => {
+            let float = v.iter().any(|i| match *i {
+                AST::Atom(Atom::Value(Value::Float(_))) => true,
+                _ => false,
+            });
+            collapse(v.into_iter()
+                .map(|i| match i {
+                    AST::Atom(Atom::Value(Value::Number(n))) if float => {
+                        value(Value::Float(if n == NULL_INT { ::std::f64::NAN } else { n as f64 }))
+                    }
+                    i => i,
+                })
+                .collect())
+        }
+        x => x,
+    };
+    // lists of lists stay general, one list per row
+    to_value(&x).ok_or(error("type", &x))
+}
+
+fn null<'a>(x: &AST<'a>) -> AST<'a> {
+    match *x {
+        AST::Atom(Atom::Value(Value::Number(_))) => value(Value::Number(NULL_INT)),
+        AST::Atom(Atom::Value(Value::Float(_))) => value(Value::Float(::std::f64::NAN)),
+        AST::Atom(Atom::Value(Value::Temporal(k, _))) => value(Value::Temporal(k, NULL_INT)),
+        AST::Atom(Atom::Value(Value::SymbolInt(_))) => value(Value::SymbolInt(symbol::NULL)),
+        AST::Atom(Atom::Value(Value::Char(_))) => value(Value::Char(' ')),
+        _ => value(Value::Nil),
+    }
+}
+
+// A group key is the by values of a row; floats compare by their bits so
+// that 0n rows fall in one group.
+
+struct Key(Vec<Value>);
+
+fn bits(x: f64) -> u64 {
+    if x.is_nan() { ::std::f64::NAN.to_bits() } else { x.to_bits() }
+}
+
+fn digest<H: Hasher>(v: &Value, h: &mut H) {
+    match *v {
+        Value::Number(x) |
+        Value::Temporal(_, x) => x.hash(h),
+        Value::Float(x) => bits(x).hash(h),
+        Value::SymbolInt(x) => x.hash(h),
+        Value::Char(x) => x.hash(h),
+        Value::Sequence(ref x) => x.hash(h),
+        Value::VecInt(ref x) |
+        Value::VecTemporal(_, ref x) => x.hash(h),
+        Value::VecFloat(ref x) => for f in x.iter() {
+            bits(*f).hash(h)
+        },
+        Value::VecSym(ref x) => x.hash(h),
+        Value::List(ref x) => for v in x.iter() {
+            digest(v, h)
+        },
+        _ => (),
+    }
+}
+
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (&Value::Float(x), &Value::Float(y)) => bits(x) == bits(y),
+        (&Value::VecFloat(ref x), &Value::VecFloat(ref y)) => {
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| bits(*x) == bits(*y))
+        }
+        (&Value::List(ref x), &Value::List(ref y)) => x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| same(x, y)),
+        (a, b) => a == b,
+    }
+}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        for v in self.0.iter() {
+            digest(v, h);
+        }
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(other.0.iter()).all(|(a, b)| same(a, b))
+    }
+}
+
+impl Eq for Key {}
+
+fn aggregate<'a>(f: &str, x: &AST<'a>) -> Option<Result<AST<'a>, Error>> {
+    let n = count(x);
+    Some(match f {
+        "sum" => adverb::eval(Adverb::Over, Verb::Plus, None, x),
+        "max" => adverb::eval(Adverb::Over, Verb::Max, None, x),
+        "min" => adverb::eval(Adverb::Over, Verb::Min, None, x),
+        "avg" => {
+            adverb::eval(Adverb::Over, Verb::Plus, None, x)
+                .and_then(|s| verb::eval(Verb::Divide, &s, &value(Value::Number(n as i64))))
+        }
+        "count" => Ok(value(Value::Number(n as i64))),
+        "first" if n > 0 => Ok(item(x, 0)),
+        "last" if n > 0 => Ok(item(x, n - 1)),
+        "first" | "last" => Ok(null(x)),
+        _ => return None,
+    })
+}
+
+impl<'s, 'a> Scope<'s, 'a> {
+    fn expr(&self, t: &Table, e: &AST<'a>) -> Result<AST<'a>, Error> {
+        match *e {
+            AST::Atom(Atom::Value(_)) => Ok(e.clone()),
+            AST::Atom(Atom::NameInt(n)) => {
+                let s = (self.name)(n);
+                match t.column(&s) {
+                    Some(c) => Ok(value(c.clone())),
+                    None if s == "i" => Ok(value(Value::VecInt((0..t.count() as i64).collect()))),
+                    None => (self.lookup)(n).ok_or(error("Identifier not found", e)),
+                }
+            }
+            AST::Atom(Atom::Verb(v, l, r)) => {
+                match (l, r) {
+                    (&AST::Atom(Atom::Value(Value::Nil)), &AST::Atom(Atom::Value(Value::Nil))) => {
+                        Err(error("rank", e))
+                    }
+                    (&AST::Atom(Atom::Value(Value::Nil)), _) => verb::monad::eval(v, &try!(self.expr(t, r))),
+                    _ => verb::eval(v, &try!(self.expr(t, l)), &try!(self.expr(t, r))),
+                }
+            }
+            AST::Atom(Atom::Adverb(adv, &AST::Atom(Atom::Verb(v, l, _)), r)) => {
+                let x = try!(self.expr(t, r));
+                match *l {
+                    AST::Atom(Atom::Value(Value::Nil)) => adverb::eval(adv, v, None, &x),
+                    _ => adverb::eval(adv, v, Some(&try!(self.expr(t, l))), &x),
+                }
+            }
+            AST::Atom(Atom::Call(&AST::Atom(Atom::NameInt(f)), &AST::Atom(Atom::Dict(&AST::Vector(ref v)))))
+                if v.len() == 2 && (self.name)(f) == "xbar" => {
+                verb::temporal::xbar(&try!(self.expr(t, &v[0])), &try!(self.expr(t, &v[1])))
+            }
+            AST::Atom(Atom::Call(&AST::Atom(Atom::NameInt(f)), x)) => {
+                let x = match *x {
+                    AST::Atom(Atom::Dict(&AST::Vector(ref v))) if v.len() == 1 => &v[0],
+                    ref x => x,
+                };
+                let x = try!(self.expr(t, x));
+                aggregate(&(self.name)(f), &x).unwrap_or(Err(error("Query function is not supported", e)))
+            }
+            AST::Atom(Atom::List(x)) => self.expr(t, x),
+            AST::Vector(ref v) => {
+                let mut r = Vec::with_capacity(v.len());
+                for x in v.iter() {
+                    r.push(try!(self.expr(t, x)));
+                }
+                Ok(collapse(r))
+            }
+            _ => Err(error("Query expression is not supported", e)),
+        }
+    }
+
+    fn label(&self, e: &AST<'a>) -> Option<String> {
+        // a result column is named after its target or first column mentioned
+        match *e {
+            AST::Atom(Atom::Assign(&AST::Atom(Atom::NameInt(n)), _)) |
+            AST::Atom(Atom::NameInt(n)) => Some((self.name)(n)),
+            AST::Atom(Atom::Call(_, x)) |
+            AST::Atom(Atom::Dict(x)) |
+            AST::Atom(Atom::List(x)) |
+            AST::Atom(Atom::Adverb(_, _, x)) => self.label(x),
+            AST::Atom(Atom::Verb(_, l, r)) => self.label(l).or_else(|| self.label(r)),
+            AST::Vector(ref v) => v.iter().filter_map(|x| self.label(x)).next(),
+            _ => None,
+        }
+    }
+
+    fn names(&self, cols: &[AST<'a>], taken: &[String]) -> Vec<String> {
+        // clashing names get a numeric suffix: a, a1, a2
+        let mut names = taken.to_vec();
+        for c in cols {
+            let base = self.label(c).unwrap_or("x".to_string());
+            let mut n = base.clone();
+            let mut i = 1;
+            while names.contains(&n) {
+                n = format!("{}{}", base, i);
+                i += 1;
+            }
+            names.push(n);
+        }
+        names.split_off(taken.len())
+    }
+
+    fn filter(&self, t: &Table, w: &AST<'a>) -> Result<Vec<usize>, Error> {
+        // where constraints apply in turn, each to the rows left by the last
+        let mut idx = (0..t.count()).collect::<Vec<usize>>();
+        for c in clause(w) {
+            let m = try!(self.expr(&t.rows(&idx), c));
+            idx = match m {
+                AST::Atom(Atom::Value(Value::VecInt(ref b))) if b.len() == idx.len() => {
+                    idx.iter().zip(b.iter()).filter(|&(_, b)| *b != 0).map(|(i, _)| *i).collect()
+                }
+                AST::Atom(Atom::Value(Value::Number(b))) => if b != 0 { idx } else { vec![] },
+                x => return Err(error("type", &x)),
+            };
+        }
+        Ok(idx)
+    }
+
+    fn group(&self, t: &Table, by: &[AST<'a>]) -> Result<(Vec<Vec<AST<'a>>>, Vec<Vec<usize>>), Error> {
+        // groups come in order of first appearance
+        let mut keys = Vec::with_capacity(by.len());
+        for b in by {
+            let k = try!(self.expr(t, body(b)));
+            if is_list(&k) && count(&k) != t.count() {
+                return Err(error("length", &k));
+            }
+            keys.push(k);
+        }
+        let mut seen = HashMap::new();
+        let mut heads = Vec::new();
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let keys = keys.iter()
+            .map(|k| if is_list(k) { items(k) } else { vec![k.clone(); t.count()] })
+            .collect::<Vec<Vec<AST<'a>>>>();
+        for r in 0..t.count() {
+            let key = keys.iter().map(|k| k[r].clone()).collect::<Vec<AST<'a>>>();
+            let id = match key.iter().map(to_value).collect::<Option<Vec<Value>>>() {
+                Some(v) => Key(v),
+                None => return Err(error("type", &AST::Vector(key))),
+            };
+            let g = *seen.entry(id).or_insert(groups.len());
+            if g == groups.len() {
+                heads.push(key);
+                groups.push(vec![]);
+            }
+            groups[g].push(r);
+        }
+        Ok((heads, groups))
+    }
+
+    fn select(&self, t: &Table, cols: &[AST<'a>], by: &[AST<'a>]) -> Result<Value, Error> {
+        // select by is keyed on the by columns
+        let r = try!(self.table(t, cols, by));
+        if by.len() == 0 {
+            return Ok(Value::Table(r));
+        }
+        let (k, v) = r.split(by.len());
+        Dict::new(Value::Table(k), Value::Table(v)).map(|d| Value::Dict(Box::new(d)))
+    }
+
+    fn table(&self, t: &Table, cols: &[AST<'a>], by: &[AST<'a>]) -> Result<Table, Error> {
+        if by.len() == 0 {
+            if cols.len() == 0 {
+                return Ok(t.clone());
+            }
+            let mut vals = Vec::with_capacity(cols.len());
+            for c in cols {
+                vals.push(try!(column(try!(self.expr(t, body(c))))));
+            }
+            return Table::new(self.names(cols, &[]), vals);
+        }
+        let (heads, groups) = try!(self.group(t, by));
+        let mut names = self.names(by, &[]);
+        let mut vals = Vec::with_capacity(by.len() + cols.len());
+        for i in 0..by.len() {
+            vals.push(try!(column(AST::Vector(heads.iter().map(|k| k[i].clone()).collect()))));
+        }
+        if cols.len() == 0 {
+            // select by k from t keeps the last row of each group
+            for (n, c) in t.names.iter().zip(t.columns.iter()) {
+                if !names.contains(n) {
+                    let c = items(&value(c.clone()));
+                    vals.push(try!(column(AST::Vector(groups.iter().map(|g| c[g[g.len() - 1]].clone()).collect()))));
+                    names.push(n.clone());
+                }
+            }
+            return Table::new(names, vals);
+        }
+        let rest = self.names(cols, &names);
+        names.extend(rest);
+        for c in cols {
+            // a column that doesn't aggregate keeps each group's list
+            vals.push(try!(column(AST::Vector(try!(self.each(t, &groups, body(c)))))));
+        }
+        Table::new(names, vals)
+    }
+
+    fn each(&self, t: &Table, groups: &[Vec<usize>], e: &AST<'a>) -> Result<Vec<AST<'a>>, Error> {
+        let mut v = Vec::with_capacity(groups.len());
+        for g in groups.iter() {
+            v.push(try!(self.expr(&t.rows(g), e)));
+        }
+        Ok(v)
+    }
+
+    fn exec(&self, t: &Table, cols: &[AST<'a>], by: &[AST<'a>]) -> Result<AST<'a>, Error> {
+        // exec c by k is a dictionary from the keys to c of each group,
+        // several by or result columns make a table of that side
+        let (heads, groups) = try!(self.group(t, by));
+        let mut keys = Vec::with_capacity(by.len());
+        for i in 0..by.len() {
+            keys.push(try!(column(AST::Vector(heads.iter().map(|k| k[i].clone()).collect()))));
+        }
+        let mut vals = Vec::with_capacity(cols.len());
+        for c in cols {
+            vals.push(try!(column(AST::Vector(try!(self.each(t, &groups, body(c)))))));
+        }
+        let k = match by.len() {
+            1 => keys.pop().expect("by key"),
+            _ => Value::Table(try!(Table::new(self.names(by, &[]), keys))),
+        };
+        let v = match cols.len() {
+            1 => vals.pop().expect("exec column"),
+            _ => Value::Table(try!(Table::new(self.names(cols, &[]), vals))),
+        };
+        Dict::new(k, v).map(|d| value(Value::Dict(Box::new(d))))
+    }
+
+    fn update(&self, t: &Table, rows: &[usize], cols: &[AST<'a>], by: &[AST<'a>]) -> Result<Table, Error> {
+        // every expression sees the table as it was before the update
+        let sub = t.rows(rows);
+        let groups = if by.len() == 0 {
+            vec![(0..rows.len()).collect::<Vec<usize>>()]
+        } else {
+            try!(self.group(&sub, by)).1
+        };
+        let mut out = t.clone();
+        for (c, name) in cols.iter().zip(self.names(cols, &[])) {
+            let mut cells: Vec<Option<AST<'a>>> = match t.column(&name) {
+                Some(v) => {
+                    items(&value(v.clone())).into_iter().map(Some).collect()
+                }
+                None => vec![None; t.count()],
+            };
+            for g in groups.iter() {
+                let r = try!(self.expr(&sub.rows(g), body(c)));
+                if is_list(&r) && count(&r) != g.len() {
+                    return Err(error("length", &r));
+                }
+                let xs = if is_list(&r) { items(&r) } else { vec![r.clone(); g.len()] };
+                for (x, &i) in xs.into_iter().zip(g.iter()) {
+                    cells[rows[i]] = Some(x);
+                }
+            }
+            let proto = cells.iter().filter_map(|x| x.clone()).next().map_or(value(Value::Nil), |x| null(&x));
+            let v = try!(column(AST::Vector(cells.into_iter().map(|x| x.unwrap_or(proto.clone())).collect())));
+            match out.names.iter().position(|n| *n == name) {
+                Some(i) => out.columns[i] = v,
+                None => {
+                    out.names.push(name);
+                    out.columns.push(v);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn delete(&self, t: &Table, rows: &[usize], cols: &[AST<'a>], w: &AST<'a>) -> Result<Table, Error> {
+        if cols.len() > 0 && clause(w).len() > 0 {
+            return self.clear(t, rows, cols);
+        }
+        if cols.len() == 0 {
+            let mut keep = vec![true; t.count()];
+            for &i in rows {
+                keep[i] = false;
+            }
+            return Ok(t.rows(&(0..t.count()).filter(|&i| keep[i]).collect::<Vec<usize>>()));
+        }
+        let mut out = t.clone();
+        for c in cols {
+            let i = match *c {
+                AST::Atom(Atom::NameInt(n)) => out.names.iter().position(|x| *x == (self.name)(n)),
+                _ => None,
+            };
+            match i {
+                Some(i) => {
+                    out.names.remove(i);
+                    out.columns.remove(i);
+                }
+                None => return Err(error("Column not found", c)),
+            }
+        }
+        Ok(out)
+    }
+
+    fn clear(&self, t: &Table, rows: &[usize], cols: &[AST<'a>]) -> Result<Table, Error> {
+        // delete c from t where ..: the rows keep their place, c goes null in them
+        let mut out = t.clone();
+        for c in cols {
+            let i = match *c {
+                AST::Atom(Atom::NameInt(n)) => out.names.iter().position(|x| *x == (self.name)(n)),
+                _ => None,
+            };
+            let i = match i {
+                Some(i) => i,
+                None => return Err(error("Column not found", c)),
+            };
+            let mut cells = items(&value(out.columns[i].clone()));
+            for &r in rows {
+                cells[r] = null(&cells[r]);
+            }
+            out.columns[i] = try!(column(AST::Vector(cells)));
+        }
+        Ok(out)
+    }
+}
+
+pub fn eval<'s, 'a>(q: &AST<'a>, t: &Table, scope: &Scope<'s, 'a>) -> Result<AST<'a>, Error> {
+    let (sql, cols, by, w) = match *q {
+        AST::Atom(Atom::Sql(sql, c, b, _, w)) => (sql, clause(c), clause(b), w),
+        ref x => return Err(error("Query expected", x)),
+    };
+    let rows = try!(scope.filter(t, w));
+    let table = |x: Table| value(Value::Table(x));
+    match sql {
+        Sql::Select => scope.select(&t.rows(&rows), cols, by).map(value),
+        Sql::Exec if by.len() == 0 && cols.len() == 1 => scope.expr(&t.rows(&rows), body(&cols[0])),
+        Sql::Exec if by.len() == 0 && cols.len() > 1 => {
+            let t = t.rows(&rows);
+            let mut v = Vec::with_capacity(cols.len());
+            for c in cols {
+                v.push(try!(scope.expr(&t, body(c))));
+            }
+            Ok(AST::Vector(v))
+        }
+        Sql::Exec if cols.len() == 0 => Err(error("exec: no columns", q)),
+        Sql::Exec => scope.exec(&t.rows(&rows), cols, by),
+        Sql::Update => scope.update(t, &rows, cols, by).map(table),
+        Sql::Delete => scope.delete(t, &rows, cols, w).map(table),
+    }
+}
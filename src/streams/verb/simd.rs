@@ -0,0 +1,502 @@
+// AVX2 kernels for typed vector dyads (+ * = <> > >= < <=) and
+// reductions (+/ &/ |/), picked at run time with a scalar fallback.
+// The scalar kernels are the default, build with --features avx2 on a
+// compiler that has std::arch to get the others.
+//
+// Both kernels walk vectors in the same four lanes and combine them in the
+// same order, so float sums round identically whichever one runs.
+// Integer arithmetic wraps.
+
+use commands::ast::{AST, Atom, Value, Verb};
+
+const LANES: usize = 4;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Kernel {
+    Scalar,
+    Avx2,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Arg<'x, T: 'x> {
+    Vec(&'x [T]),
+    Atom(T),
+}
+
+impl<'x, T: Copy> Arg<'x, T> {
+    #[inline(always)]
+    fn at(&self, i: usize) -> T {
+        match *self {
+            Arg::Vec(v) => v[i],
+            Arg::Atom(x) => x,
+        }
+    }
+
+    fn len(&self) -> Option<usize> {
+        match *self {
+            Arg::Vec(v) => Some(v.len()),
+            Arg::Atom(_) => None,
+        }
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "avx2"))]
+pub fn detect() -> Kernel {
+    if is_x86_feature_detected!("avx2") {
+        Kernel::Avx2
+    } else {
+        Kernel::Scalar
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", feature = "avx2")))]
+pub fn detect() -> Kernel {
+    Kernel::Scalar
+}
+
+fn length<T: Copy>(l: &Arg<T>, r: &Arg<T>) -> Option<usize> {
+    match (l.len(), r.len()) {
+        (Some(a), Some(b)) if a == b && a > 0 => Some(a),
+        (Some(a), None) | (None, Some(a)) if a > 0 => Some(a),
+        _ => None,
+    }
+}
+
+fn flag(x: bool) -> i64 {
+    if x { 1 } else { 0 }
+}
+
+pub mod scalar {
+    use commands::ast::Verb;
+    use super::{Arg, LANES, flag};
+
+    pub fn ints(v: Verb, l: Arg<i64>, r: Arg<i64>, n: usize) -> Vec<i64> {
+        (0..n)
+            .map(|i| {
+                let (a, b) = (l.at(i), r.at(i));
+                match v {
+                    Verb::Plus => a.wrapping_add(b),
+                    Verb::Times => a.wrapping_mul(b),
+                    Verb::Eq => flag(a == b),
+                    Verb::NEq => flag(a != b),
+                    Verb::Gt => flag(a > b),
+                    Verb::Ge => flag(a >= b),
+                    Verb::Lt => flag(a < b),
+                    _ => flag(a <= b),
+                }
+            })
+            .collect()
+    }
+
+    pub fn floats(v: Verb, l: Arg<f64>, r: Arg<f64>, n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| {
+                let (a, b) = (l.at(i), r.at(i));
+                if v == Verb::Plus { a + b } else { a * b }
+            })
+            .collect()
+    }
+
+    pub fn compare(v: Verb, l: Arg<f64>, r: Arg<f64>, n: usize) -> Vec<i64> {
+        (0..n)
+            .map(|i| {
+                let (a, b) = (l.at(i), r.at(i));
+                match v {
+                    Verb::Eq => flag((a - b).abs() < 1e-10),
+                    Verb::NEq => flag((a - b).abs() > 1e-10),
+                    Verb::Gt => flag(a > b),
+                    Verb::Ge => flag(a >= b),
+                    Verb::Lt => flag(a < b),
+                    _ => flag(a <= b),
+                }
+            })
+            .collect()
+    }
+
+    fn fold<T: Copy, F: Fn(T, T) -> T>(x: &[T], zero: Option<T>, f: F) -> T {
+        // lanes start from zero (sums) or the first items (min, max)
+        let body = x.len() - x.len() % LANES;
+        let (mut acc, from) = match zero {
+            Some(z) => ([z; LANES], 0),
+            None if body > 0 => ([x[0], x[1], x[2], x[3]], LANES),
+            None => {
+                return x[1..].iter().fold(x[0], |a, b| f(a, *b));
+            }
+        };
+        let mut i = from;
+        while i < body {
+            for j in 0..LANES {
+                acc[j] = f(acc[j], x[i + j]);
+            }
+            i += LANES;
+        }
+        x[body..].iter().fold(f(f(acc[0], acc[1]), f(acc[2], acc[3])), |a, b| f(a, *b))
+    }
+
+    pub fn sum_int(x: &[i64]) -> i64 {
+        fold(x, Some(0), |a: i64, b| a.wrapping_add(b))
+    }
+
+    pub fn sum_float(x: &[f64]) -> f64 {
+        fold(x, Some(0.0), |a, b| a + b)
+    }
+
+    pub fn min_int(x: &[i64]) -> i64 {
+        fold(x, None, |a, b| if a < b { a } else { b })
+    }
+
+    pub fn max_int(x: &[i64]) -> i64 {
+        fold(x, None, |a, b| if a > b { a } else { b })
+    }
+
+    pub fn min_float(x: &[f64]) -> f64 {
+        fold(x, None, |a, b| if a < b { a } else { b })
+    }
+
+    pub fn max_float(x: &[f64]) -> f64 {
+        fold(x, None, |a, b| if a > b { a } else { b })
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "avx2"))]
+pub mod avx2 {
+    use std::arch::x86_64::*;
+    use commands::ast::Verb;
+    use super::{Arg, LANES, scalar};
+
+    #[inline(always)]
+    unsafe fn ld_i(x: &Arg<i64>, i: usize) -> __m256i {
+        match *x {
+            Arg::Vec(v) => _mm256_loadu_si256(v.as_ptr().offset(i as isize) as *const __m256i),
+            Arg::Atom(a) => _mm256_set1_epi64x(a),
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn ld_f(x: &Arg<f64>, i: usize) -> __m256d {
+        match *x {
+            Arg::Vec(v) => _mm256_loadu_pd(v.as_ptr().offset(i as isize)),
+            Arg::Atom(a) => _mm256_set1_pd(a),
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn mul_i(a: __m256i, b: __m256i) -> __m256i {
+        // low 64 bits of a*b from 32 bit halves
+        let lo = _mm256_mul_epu32(a, b);
+        let cross = _mm256_add_epi64(_mm256_mul_epu32(_mm256_srli_epi64(a, 32), b),
+                                     _mm256_mul_epu32(a, _mm256_srli_epi64(b, 32)));
+        _mm256_add_epi64(lo, _mm256_slli_epi64(cross, 32))
+    }
+
+    fn part<'x, T: Copy>(x: &Arg<'x, T>, from: usize) -> Arg<'x, T> {
+        match *x {
+            Arg::Vec(v) => Arg::Vec(&v[from..]),
+            Arg::Atom(a) => Arg::Atom(a),
+        }
+    }
+
+    fn tail<T: Copy, R>(l: &Arg<T>, r: &Arg<T>, from: usize, n: usize, out: &mut Vec<R>, f: &Fn(Arg<T>, Arg<T>, usize) -> Vec<R>) {
+        // the last n % 4 items go through the scalar kernel
+        out.extend(f(part(l, from), part(r, from), n - from));
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn ints(v: Verb, l: Arg<i64>, r: Arg<i64>, n: usize) -> Vec<i64> {
+        let body = n - n % LANES;
+        let one = _mm256_set1_epi64x(1);
+        let mut out: Vec<i64> = Vec::with_capacity(n);
+        let p = out.as_mut_ptr();
+        let mut i = 0;
+        while i < body {
+            let (a, b) = (ld_i(&l, i), ld_i(&r, i));
+            let x = match v {
+                Verb::Plus => _mm256_add_epi64(a, b),
+                Verb::Times => mul_i(a, b),
+                Verb::Eq => _mm256_and_si256(_mm256_cmpeq_epi64(a, b), one),
+                Verb::NEq => _mm256_andnot_si256(_mm256_cmpeq_epi64(a, b), one),
+                Verb::Gt => _mm256_and_si256(_mm256_cmpgt_epi64(a, b), one),
+                Verb::Ge => _mm256_andnot_si256(_mm256_cmpgt_epi64(b, a), one),
+                Verb::Lt => _mm256_and_si256(_mm256_cmpgt_epi64(b, a), one),
+                _ => _mm256_andnot_si256(_mm256_cmpgt_epi64(a, b), one),
+            };
+            _mm256_storeu_si256(p.offset(i as isize) as *mut __m256i, x);
+            i += LANES;
+        }
+        out.set_len(body);
+        tail(&l, &r, body, n, &mut out, &|a, b, k| scalar::ints(v, a, b, k));
+        out
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn floats(v: Verb, l: Arg<f64>, r: Arg<f64>, n: usize) -> Vec<f64> {
+        let body = n - n % LANES;
+        let mut out: Vec<f64> = Vec::with_capacity(n);
+        let p = out.as_mut_ptr();
+        let mut i = 0;
+        while i < body {
+            let (a, b) = (ld_f(&l, i), ld_f(&r, i));
+            let x = if v == Verb::Plus { _mm256_add_pd(a, b) } else { _mm256_mul_pd(a, b) };
+            _mm256_storeu_pd(p.offset(i as isize), x);
+            i += LANES;
+        }
+        out.set_len(body);
+        tail(&l, &r, body, n, &mut out, &|a, b, k| scalar::floats(v, a, b, k));
+        out
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn compare(v: Verb, l: Arg<f64>, r: Arg<f64>, n: usize) -> Vec<i64> {
+        let body = n - n % LANES;
+        let sign = _mm256_set1_pd(-0.0);
+        let eps = _mm256_set1_pd(1e-10);
+        let mut out: Vec<i64> = Vec::with_capacity(n);
+        let p = out.as_mut_ptr();
+        let mut i = 0;
+        while i < body {
+            let (a, b) = (ld_f(&l, i), ld_f(&r, i));
+            let m = match v {
+                Verb::Eq => _mm256_cmp_pd(_mm256_andnot_pd(sign, _mm256_sub_pd(a, b)), eps, _CMP_LT_OQ),
+                Verb::NEq => _mm256_cmp_pd(_mm256_andnot_pd(sign, _mm256_sub_pd(a, b)), eps, _CMP_GT_OQ),
+                Verb::Gt => _mm256_cmp_pd(a, b, _CMP_GT_OQ),
+                Verb::Ge => _mm256_cmp_pd(a, b, _CMP_GE_OQ),
+                Verb::Lt => _mm256_cmp_pd(a, b, _CMP_LT_OQ),
+                _ => _mm256_cmp_pd(a, b, _CMP_LE_OQ),
+            };
+            _mm256_storeu_si256(p.offset(i as isize) as *mut __m256i,
+                                _mm256_srli_epi64(_mm256_castpd_si256(m), 63));
+            i += LANES;
+        }
+        out.set_len(body);
+        tail(&l, &r, body, n, &mut out, &|a, b, k| scalar::compare(v, a, b, k));
+        out
+    }
+
+    #[inline(always)]
+    unsafe fn lanes_i(x: __m256i) -> [i64; LANES] {
+        let mut a = [0; LANES];
+        _mm256_storeu_si256(a.as_mut_ptr() as *mut __m256i, x);
+        a
+    }
+
+    #[inline(always)]
+    unsafe fn lanes_f(x: __m256d) -> [f64; LANES] {
+        let mut a = [0.0; LANES];
+        _mm256_storeu_pd(a.as_mut_ptr(), x);
+        a
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_int(x: &[i64]) -> i64 {
+        let body = x.len() - x.len() % LANES;
+        let mut acc = _mm256_setzero_si256();
+        let mut i = 0;
+        while i < body {
+            acc = _mm256_add_epi64(acc, ld_i(&Arg::Vec(x), i));
+            i += LANES;
+        }
+        let a = lanes_i(acc);
+        x[body..].iter().fold(a[0].wrapping_add(a[1]).wrapping_add(a[2].wrapping_add(a[3])),
+                              |s, b| s.wrapping_add(*b))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_float(x: &[f64]) -> f64 {
+        let body = x.len() - x.len() % LANES;
+        let mut acc = _mm256_setzero_pd();
+        let mut i = 0;
+        while i < body {
+            acc = _mm256_add_pd(acc, ld_f(&Arg::Vec(x), i));
+            i += LANES;
+        }
+        let a = lanes_f(acc);
+        x[body..].iter().fold((a[0] + a[1]) + (a[2] + a[3]), |s, b| s + *b)
+    }
+
+    // min and max keep `if a < b { a } else { b }`: the second operand wins ties and NaN
+    #[target_feature(enable = "avx2")]
+    unsafe fn fold_int(x: &[i64], min: bool) -> i64 {
+        let f = |a: i64, b: i64| if (min && a < b) || (!min && a > b) { a } else { b };
+        let body = x.len() - x.len() % LANES;
+        if body == 0 {
+            return x[1..].iter().fold(x[0], |a, b| f(a, *b));
+        }
+        let mut acc = ld_i(&Arg::Vec(x), 0);
+        let mut i = LANES;
+        while i < body {
+            let b = ld_i(&Arg::Vec(x), i);
+            let m = if min { _mm256_cmpgt_epi64(b, acc) } else { _mm256_cmpgt_epi64(acc, b) };
+            acc = _mm256_blendv_epi8(b, acc, m);
+            i += LANES;
+        }
+        let a = lanes_i(acc);
+        x[body..].iter().fold(f(f(a[0], a[1]), f(a[2], a[3])), |s, b| f(s, *b))
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn fold_float(x: &[f64], min: bool) -> f64 {
+        let f = |a: f64, b: f64| if (min && a < b) || (!min && a > b) { a } else { b };
+        let body = x.len() - x.len() % LANES;
+        if body == 0 {
+            return x[1..].iter().fold(x[0], |a, b| f(a, *b));
+        }
+        let mut acc = ld_f(&Arg::Vec(x), 0);
+        let mut i = LANES;
+        while i < body {
+            let b = ld_f(&Arg::Vec(x), i);
+            acc = if min { _mm256_min_pd(acc, b) } else { _mm256_max_pd(acc, b) };
+            i += LANES;
+        }
+        let a = lanes_f(acc);
+        x[body..].iter().fold(f(f(a[0], a[1]), f(a[2], a[3])), |s, b| f(s, *b))
+    }
+
+    pub unsafe fn min_int(x: &[i64]) -> i64 {
+        fold_int(x, true)
+    }
+
+    pub unsafe fn max_int(x: &[i64]) -> i64 {
+        fold_int(x, false)
+    }
+
+    pub unsafe fn min_float(x: &[f64]) -> f64 {
+        fold_float(x, true)
+    }
+
+    pub unsafe fn max_float(x: &[f64]) -> f64 {
+        fold_float(x, false)
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", feature = "avx2")))]
+pub mod avx2 {
+    // never picked by detect, the scalar kernels stand in
+    use commands::ast::Verb;
+    use super::{Arg, scalar};
+
+    pub unsafe fn ints(v: Verb, l: Arg<i64>, r: Arg<i64>, n: usize) -> Vec<i64> {
+        scalar::ints(v, l, r, n)
+    }
+
+    pub unsafe fn floats(v: Verb, l: Arg<f64>, r: Arg<f64>, n: usize) -> Vec<f64> {
+        scalar::floats(v, l, r, n)
+    }
+
+    pub unsafe fn compare(v: Verb, l: Arg<f64>, r: Arg<f64>, n: usize) -> Vec<i64> {
+        scalar::compare(v, l, r, n)
+    }
+
+    pub unsafe fn sum_int(x: &[i64]) -> i64 {
+        scalar::sum_int(x)
+    }
+
+    pub unsafe fn sum_float(x: &[f64]) -> f64 {
+        scalar::sum_float(x)
+    }
+
+    pub unsafe fn min_int(x: &[i64]) -> i64 {
+        scalar::min_int(x)
+    }
+
+    pub unsafe fn max_int(x: &[i64]) -> i64 {
+        scalar::max_int(x)
+    }
+
+    pub unsafe fn min_float(x: &[f64]) -> f64 {
+        scalar::min_float(x)
+    }
+
+    pub unsafe fn max_float(x: &[f64]) -> f64 {
+        scalar::max_float(x)
+    }
+}
+
+fn kernel(k: Kernel) -> bool {
+    // true when the AVX2 kernel can run here
+    k == Kernel::Avx2 && detect() == Kernel::Avx2
+}
+
+pub fn ints(k: Kernel, v: Verb, l: Arg<i64>, r: Arg<i64>) -> Option<Vec<i64>> {
+    let n = match length(&l, &r) {
+        Some(n) => n,
+        None => return None,
+    };
+    Some(if kernel(k) { unsafe { avx2::ints(v, l, r, n) } } else { scalar::ints(v, l, r, n) })
+}
+
+pub fn floats(k: Kernel, v: Verb, l: Arg<f64>, r: Arg<f64>) -> Option<Value> {
+    let n = match length(&l, &r) {
+        Some(n) => n,
+        None => return None,
+    };
+    let avx = kernel(k);
+    Some(match v {
+        Verb::Plus | Verb::Times => {
+            Value::VecFloat(if avx { unsafe { avx2::floats(v, l, r, n) } } else { scalar::floats(v, l, r, n) })
+        }
+        _ => Value::VecInt(if avx { unsafe { avx2::compare(v, l, r, n) } } else { scalar::compare(v, l, r, n) }),
+    })
+}
+
+pub fn fold_int(k: Kernel, v: Verb, x: &[i64]) -> Option<i64> {
+    let avx = kernel(k);
+    match v {
+        Verb::Plus if avx => Some(unsafe { avx2::sum_int(x) }),
+        Verb::Plus => Some(scalar::sum_int(x)),
+        _ if x.len() == 0 => None,
+        Verb::Min if avx => Some(unsafe { avx2::min_int(x) }),
+        Verb::Min => Some(scalar::min_int(x)),
+        Verb::Max if avx => Some(unsafe { avx2::max_int(x) }),
+        Verb::Max => Some(scalar::max_int(x)),
+        _ => None,
+    }
+}
+
+pub fn fold_float(k: Kernel, v: Verb, x: &[f64]) -> Option<f64> {
+    let avx = kernel(k);
+    match v {
+        Verb::Plus if avx => Some(unsafe { avx2::sum_float(x) }),
+        Verb::Plus => Some(scalar::sum_float(x)),
+        _ if x.len() == 0 => None,
+        Verb::Min if avx => Some(unsafe { avx2::min_float(x) }),
+        Verb::Min => Some(scalar::min_float(x)),
+        Verb::Max if avx => Some(unsafe { avx2::max_float(x) }),
+        Verb::Max => Some(scalar::max_float(x)),
+        _ => None,
+    }
+}
+
+pub fn dyad<'a, 'b>(v: Verb, l: &'b AST<'a>, r: &'b AST<'a>) -> Option<AST<'a>> {
+    // typed vectors of one type, an atom of the same type spreads over the other side
+    match v {
+        Verb::Plus | Verb::Times | Verb::Eq | Verb::NEq | Verb::Gt | Verb::Ge | Verb::Lt | Verb::Le => (),
+        _ => return None,
+    }
+    let int = |x: &'b AST<'a>| match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref a))) => Some(Arg::Vec(&a[..])),
+        AST::Atom(Atom::Value(Value::Number(a))) => Some(Arg::Atom(a)),
+        _ => None,
+    };
+    let float = |x: &'b AST<'a>| match *x {
+        AST::Atom(Atom::Value(Value::VecFloat(ref a))) => Some(Arg::Vec(&a[..])),
+        AST::Atom(Atom::Value(Value::Float(a))) => Some(Arg::Atom(a)),
+        _ => None,
+    };
+    let k = detect();
+    let r = match (int(l), int(r), float(l), float(r)) {
+        (Some(a), Some(b), _, _) => ints(k, v, a, b).map(Value::VecInt),
+        (_, _, Some(a), Some(b)) => floats(k, v, a, b),
+        _ => None,
+    };
+    r.map(|x| AST::Atom(Atom::Value(x)))
+}
+
+pub fn fold<'a, 'b>(v: Verb, x: &'b AST<'a>) -> Option<AST<'a>> {
+    let k = detect();
+    match *x {
+        AST::Atom(Atom::Value(Value::VecInt(ref a))) => fold_int(k, v, a).map(Value::Number),
+        AST::Atom(Atom::Value(Value::VecFloat(ref a))) => fold_float(k, v, a).map(Value::Float),
+        _ => None,
+    }
+    .map(|x| AST::Atom(Atom::Value(x)))
+}
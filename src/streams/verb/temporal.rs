@@ -0,0 +1,195 @@
+// Temporal arithmetic, comparison, field extraction (t.date) and xbar
+
+use commands::ast::{AST, Atom, Value, Verb, Error};
+use commands::symbol;
+use commands::temporal::{Temporal, DAY, NS, MS, floor, civil};
+use streams::verb::{NULL_INT, is_list, count, zip, collapse};
+
+fn error<'a, 'b>(desc: &str, l: &'b AST<'a>, r: &'b AST<'a>) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{:?} {:?}", l, r),
+    }
+}
+
+pub fn is_temporal<'a>(x: &AST<'a>) -> bool {
+    match *x {
+        AST::Atom(Atom::Value(Value::Temporal(..))) |
+        AST::Atom(Atom::Value(Value::VecTemporal(..))) => true,
+        AST::Vector(ref v) => v.iter().any(is_temporal),
+        _ => false,
+    }
+}
+
+fn scalar<'a>(x: &AST<'a>) -> Option<(Option<Temporal>, i64)> {
+    // ints mix with temporals in the units of the temporal
+    match *x {
+        AST::Atom(Atom::Value(Value::Temporal(k, n))) => Some((Some(k), n)),
+        AST::Atom(Atom::Value(Value::Number(n))) => Some((None, n)),
+        _ => None,
+    }
+}
+
+fn atom<'a>(k: Option<Temporal>, x: i64) -> AST<'a> {
+    AST::Atom(Atom::Value(match k {
+        Some(k) => Value::Temporal(k, x),
+        None => Value::Number(x),
+    }))
+}
+
+fn arith(v: Verb, a: (Option<Temporal>, i64), b: (Option<Temporal>, i64)) -> Option<(Option<Temporal>, i64)> {
+    use commands::temporal::Temporal::*;
+    let ((ka, x), (kb, y)) = (a, b);
+    if v == Verb::Min || v == Verb::Max {
+        // nulls are the smallest value, as for ints
+        if ka != kb && ka.is_some() && kb.is_some() {
+            return None;
+        }
+        return Some((ka.or(kb), if v == Verb::Min { x.min(y) } else { x.max(y) }));
+    }
+    // nulls stay null of the result type
+    let null = x == NULL_INT || y == NULL_INT;
+    let (x, y) = if null { (0, 0) } else { (x, y) };
+    let r = match (v, ka, kb) {
+        (Verb::Plus, Some(k), None) |
+        (Verb::Plus, None, Some(k)) => (Some(k), x + y),
+        (Verb::Plus, Some(Timestamp), Some(Timespan)) |
+        (Verb::Plus, Some(Timespan), Some(Timestamp)) => (Some(Timestamp), x + y),
+        (Verb::Plus, Some(Date), Some(Timespan)) => (Some(Timestamp), x * DAY + y),
+        (Verb::Plus, Some(Timespan), Some(Date)) => (Some(Timestamp), x + y * DAY),
+        (Verb::Plus, Some(Time), Some(Timespan)) => (Some(Time), x + y / MS),
+        (Verb::Plus, Some(Timespan), Some(Time)) => (Some(Time), x / MS + y),
+        (Verb::Plus, Some(Time), Some(Time)) => (Some(Time), x + y),
+        (Verb::Plus, Some(Timespan), Some(Timespan)) => (Some(Timespan), x + y),
+
+        (Verb::Minus, Some(k), None) => (Some(k), x - y),
+        (Verb::Minus, Some(Timestamp), Some(Timestamp)) => (Some(Timespan), x - y),
+        (Verb::Minus, Some(Timestamp), Some(Timespan)) => (Some(Timestamp), x - y),
+        (Verb::Minus, Some(Date), Some(Date)) => (None, x - y),
+        (Verb::Minus, Some(Date), Some(Timespan)) => (Some(Timestamp), x * DAY - y),
+        (Verb::Minus, Some(Time), Some(Timespan)) => (Some(Time), x - y / MS),
+        (Verb::Minus, Some(Time), Some(Time)) => (Some(Time), x - y),
+        (Verb::Minus, Some(Timespan), Some(Timespan)) => (Some(Timespan), x - y),
+
+        (Verb::Times, Some(Timespan), None) |
+        (Verb::Times, Some(Time), None) => (ka, x * y),
+        (Verb::Times, None, Some(Timespan)) |
+        (Verb::Times, None, Some(Time)) => (kb, x * y),
+        _ => return None,
+    };
+    Some(if null { (r.0, NULL_INT) } else { r })
+}
+
+fn compare(v: Verb, a: (Option<Temporal>, i64), b: (Option<Temporal>, i64)) -> i64 {
+    // dates and timestamps compare on one scale, ints on the raw count
+    let ((ka, x), (kb, y)) = (a, b);
+    let (x, y) = match (ka, kb) {
+        (Some(ka), Some(kb)) if ka != kb && x != NULL_INT && y != NULL_INT => (ka.to_ns(x), kb.to_ns(y)),
+        _ => (x, y),
+    };
+    let r = match v {
+        Verb::Eq => x == y,
+        Verb::NEq => x != y,
+        Verb::Lt => x < y,
+        Verb::Le => x <= y,
+        Verb::Gt => x > y,
+        _ => x >= y,
+    };
+    if r { 1 } else { 0 }
+}
+
+fn field<'a, 'b>(x: &'b AST<'a>, f: &str) -> Result<AST<'a>, Error> {
+    use commands::temporal::Temporal::*;
+    let (k, n) = match *x {
+        AST::Atom(Atom::Value(Value::Temporal(k, n))) => (k, n),
+        _ => return Err(error("type", x, &AST::Atom(Atom::Value(Value::Sequence(f.to_string()))))),
+    };
+    // day number and nanoseconds into the day
+    let null = n == NULL_INT;
+    let n = if null { 0 } else { n };
+    let (day, ns) = match k {
+        Timestamp => (floor(n, DAY), n - floor(n, DAY) * DAY),
+        Date => (n, 0),
+        Time => (0, n * MS),
+        Timespan => (floor(n, DAY), n - floor(n, DAY) * DAY),
+    };
+    let dated = k == Timestamp || k == Date;
+    let r = match f {
+        "date" if dated => (Some(Date), day),
+        "time" => (Some(Time), ns / MS),
+        "minute" => (None, ns / (60 * NS) % 60),
+        "second" => (None, ns / NS % 60),
+        "year" if dated => (None, civil(day).0),
+        "mm" if dated => (None, civil(day).1),
+        "dd" if dated => (None, civil(day).2),
+        "hh" => (None, ns / (3600 * NS)),
+        "mm" | "uu" => (None, ns / (60 * NS) % 60),
+        "ss" => (None, ns / NS % 60),
+        _ => return Err(error("domain", x, &AST::Atom(Atom::Value(Value::Sequence(f.to_string()))))),
+    };
+    Ok(atom(r.0, if null { NULL_INT } else { r.1 }))
+}
+
+fn each<'a, 'b, F>(f: &F, l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error>
+    where F: for<'c> Fn(&'c AST<'a>, &'c AST<'a>) -> Result<AST<'a>, Error>
+{
+    if is_list(l) || is_list(r) {
+        if is_list(l) && is_list(r) && count(l) != count(r) {
+            return Err(error("length", l, r));
+        }
+        let mut x = Vec::with_capacity(count(l).max(count(r)));
+        for (a, b) in zip(l, r) {
+            x.push(try!(each(f, &a, &b)));
+        }
+        return Ok(collapse(x));
+    }
+    f(l, r)
+}
+
+pub fn eval<'a, 'b>(v: Verb, l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    each(&|a: &AST<'a>, b: &AST<'a>| {
+             if v == Verb::Dot {
+                 return match *b {
+                     AST::Atom(Atom::Value(Value::SymbolInt(s))) => field(a, &symbol::name(s)),
+                     _ => Err(error("type", a, b)),
+                 };
+             }
+             match (scalar(a), scalar(b)) {
+                 (Some(x), Some(y)) => {
+                     match v {
+                         Verb::Eq | Verb::NEq | Verb::Lt | Verb::Le | Verb::Gt | Verb::Ge => {
+                             Ok(atom(None, compare(v, x, y)))
+                         }
+                         _ => arith(v, x, y).map(|(k, n)| atom(k, n)).ok_or(error("type", a, b)),
+                     }
+                 }
+                 _ => Err(error("type", a, b)),
+             }
+         },
+         l,
+         r)
+}
+
+pub fn xbar<'a, 'b>(l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    // x xbar y rounds y down to a multiple of x, a timespan step buckets times
+    each(&|a: &AST<'a>, b: &AST<'a>| {
+             match (scalar(a), scalar(b)) {
+                 (Some((s, n)), Some((k, y))) => {
+                     let n = match (s, k) {
+                         (None, _) => n,
+                         (Some(Temporal::Timespan), Some(Temporal::Time)) => n / MS,
+                         (Some(Temporal::Timespan), Some(Temporal::Timestamp)) |
+                         (Some(Temporal::Timespan), Some(Temporal::Timespan)) => n,
+                         _ => return Err(error("type", a, b)),
+                     };
+                     if n <= 0 {
+                         return Err(error("domain", a, b));
+                     }
+                     Ok(atom(k, if y == NULL_INT { y } else { floor(y, n) * n }))
+                 }
+                 _ => Err(error("type", a, b)),
+             }
+         },
+         l,
+         r)
+}
@@ -0,0 +1,81 @@
+// Symbols and chars compare by text
+
+use std::cmp::Ordering;
+use commands::ast::{AST, Atom, Value, Verb, Error};
+use commands::symbol;
+use streams::verb::{self, is_list, count, zip, collapse};
+
+fn error<'a, 'b>(desc: &str, l: &'b AST<'a>, r: &'b AST<'a>) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{:?} {:?}", l, r),
+    }
+}
+
+pub fn is_text<'a>(x: &AST<'a>) -> bool {
+    match *x {
+        AST::Atom(Atom::Value(Value::SymbolInt(_))) |
+        AST::Atom(Atom::Value(Value::VecSym(_))) |
+        AST::Atom(Atom::Value(Value::Char(_))) |
+        AST::Atom(Atom::Value(Value::Sequence(_))) => true,
+        AST::Vector(ref v) => v.iter().any(is_text),
+        _ => false,
+    }
+}
+
+fn order<'a>(l: &AST<'a>, r: &AST<'a>) -> Option<Ordering> {
+    match (l, r) {
+        (&AST::Atom(Atom::Value(Value::SymbolInt(a))), &AST::Atom(Atom::Value(Value::SymbolInt(b)))) => {
+            Some(symbol::cmp(a, b))
+        }
+        (&AST::Atom(Atom::Value(Value::Char(a))), &AST::Atom(Atom::Value(Value::Char(b)))) => Some(a.cmp(&b)),
+        _ => None,
+    }
+}
+
+fn names<'a>(x: &AST<'a>) -> Option<Vec<String>> {
+    match *x {
+        AST::Atom(Atom::Value(Value::VecSym(ref v))) => Some(symbol::names(v)),
+        AST::Atom(Atom::Value(Value::SymbolInt(s))) => Some(symbol::names(&[s])),
+        _ => None,
+    }
+}
+
+fn holds<'a, 'b>(v: Verb, o: Option<Ordering>, l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    let b = match (v, o) {
+        (Verb::Eq, _) => o == Some(Ordering::Equal),
+        (Verb::NEq, _) => o != Some(Ordering::Equal),
+        (Verb::Lt, Some(o)) => o == Ordering::Less,
+        (Verb::Le, Some(o)) => o != Ordering::Greater,
+        (Verb::Gt, Some(o)) => o == Ordering::Greater,
+        (Verb::Ge, Some(o)) => o != Ordering::Less,
+        _ => return Err(error("type", l, r)),
+    };
+    Ok(AST::Atom(Atom::Value(Value::Number(if b { 1 } else { 0 }))))
+}
+
+pub fn compare<'a, 'b>(v: Verb, l: &'b AST<'a>, r: &'b AST<'a>) -> Result<AST<'a>, Error> {
+    // = and <> hold across types, ordering needs both sides of one type
+    if is_list(l) || is_list(r) {
+        if is_list(l) && is_list(r) && count(l) != count(r) {
+            return Err(error("length", l, r));
+        }
+        let mut x = Vec::with_capacity(count(l).max(count(r)));
+        if let (Some(a), Some(b)) = (names(l), names(r)) {
+            // symbol lists look their names up once, not per pair
+            for i in 0..(if is_list(l) { count(l) } else { count(r) }) {
+                let o = a[if is_list(l) { i } else { 0 }].cmp(&b[if is_list(r) { i } else { 0 }]);
+                x.push(try!(holds(v, Some(o), l, r)));
+            }
+            return Ok(collapse(x));
+        }
+        for (a, b) in zip(l, r) {
+            x.push(try!(compare(v, &a, &b)));
+        }
+        return Ok(collapse(x));
+    }
+    if !is_text(l) && !is_text(r) {
+        return verb::eval(v, l, r);
+    }
+    holds(v, order(l, r), l, r)
+}
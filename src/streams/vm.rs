@@ -0,0 +1,484 @@
+// Register bytecode for scalar lambdas.
+//
+// A lambda body made of numbers, names, dyadic arithmetic and comparison,
+// $[c;t;f], local assignment and calls of global functions compiles to a flat
+// list of register ops. Parameters take the first registers, then locals, then
+// temporaries. Before a call runs, every global it can reach is checked to be a
+// scalar or a compiled lambda, so a call that would need the interpreter never
+// starts. Such code has no side effects, so whenever the machine still meets
+// something it can't run (a null local) it gives up and the call is
+// interpreted from the start.
+
+use std::rc::Rc;
+use std::collections::HashMap;
+use commands::ast::{AST, Atom, Value, Verb};
+use streams::env::Environment;
+use streams::otree::NodeId;
+use streams::verb;
+
+macro_rules! try_opt {
+    ($e:expr) => {
+        match $e {
+            Some(x) => x,
+            None => return None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    Int(u16, i64),
+    Float(u16, f64),
+    Move(u16, u16),
+    Global(u16, u16),
+    Dyad(Verb, u16, u16, u16),
+    Jz(u16, usize),
+    Jmp(usize),
+    Call(u16, u16, u16, u16),
+    Ret(u16),
+}
+
+#[derive(Debug)]
+pub struct Code {
+    pub ops: Vec<Op>,
+    pub args: usize,
+    pub regs: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Val {
+    Unset,
+    Int(i64),
+    Float(f64),
+}
+
+pub enum Step {
+    Done(Value),
+    Preempt,
+    Fallback,
+}
+
+fn dyadic(v: Verb) -> bool {
+    match v {
+        Verb::Plus | Verb::Minus | Verb::Times | Verb::Divide | Verb::Mod | Verb::Min | Verb::Max | Verb::Eq |
+        Verb::NEq | Verb::Lt | Verb::Le | Verb::Gt | Verb::Ge => true,
+        _ => false,
+    }
+}
+
+fn assigns<'a>(e: &AST<'a>) -> bool {
+    match *e {
+        AST::Atom(Atom::Assign(..)) => true,
+        AST::Atom(Atom::Verb(_, l, r)) => assigns(l) || assigns(r),
+        AST::Atom(Atom::Cond(c, t, f)) => assigns(c) || assigns(t) || assigns(f),
+        AST::Atom(Atom::Call(_, a)) => assigns(a),
+        AST::Atom(Atom::Dict(a)) => assigns(a),
+        AST::Vector(ref v) => v.iter().any(assigns),
+        _ => false,
+    }
+}
+
+struct Compiler {
+    ops: Vec<Op>,
+    names: Vec<u16>,
+    top: u16,
+    regs: u16,
+}
+
+impl Compiler {
+    fn alloc(&mut self) -> u16 {
+        let r = self.top;
+        self.top += 1;
+        if self.top > self.regs {
+            self.regs = self.top;
+        }
+        r
+    }
+
+    fn local(&self, n: u16) -> Option<u16> {
+        self.names.iter().position(|x| *x == n).map(|i| i as u16)
+    }
+
+    fn locals<'a>(&mut self, e: &AST<'a>) {
+        match *e {
+            AST::Atom(Atom::Assign(&AST::Atom(Atom::NameInt(n)), x)) => {
+                if self.local(n).is_none() {
+                    self.names.push(n);
+                }
+                self.locals(x)
+            }
+            AST::Atom(Atom::Verb(_, l, r)) => {
+                self.locals(l);
+                self.locals(r)
+            }
+            AST::Atom(Atom::Cond(c, t, f)) => {
+                self.locals(c);
+                self.locals(t);
+                self.locals(f)
+            }
+            AST::Atom(Atom::Call(_, a)) |
+            AST::Atom(Atom::Dict(a)) => self.locals(a),
+            AST::Vector(ref v) => {
+                for x in v.iter() {
+                    self.locals(x)
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn copy(&mut self, r: u16) -> u16 {
+        // the value in a fresh temporary
+        let d = self.alloc();
+        self.ops.push(Op::Move(d, r));
+        d
+    }
+
+    fn expr<'a>(&mut self, e: &AST<'a>) -> Option<u16> {
+        let mark = self.top;
+        match *e {
+            AST::Atom(Atom::Value(Value::Number(n))) => {
+                let d = self.alloc();
+                self.ops.push(Op::Int(d, n));
+                Some(d)
+            }
+            AST::Atom(Atom::Value(Value::Float(n))) => {
+                let d = self.alloc();
+                self.ops.push(Op::Float(d, n));
+                Some(d)
+            }
+            AST::Atom(Atom::NameInt(n)) => {
+                match self.local(n) {
+                    Some(r) => Some(r),
+                    None => {
+                        let d = self.alloc();
+                        self.ops.push(Op::Global(d, n));
+                        Some(d)
+                    }
+                }
+            }
+            AST::Atom(Atom::Verb(v, l, r)) if dyadic(v) && l != &AST::Atom(Atom::Value(Value::Nil)) => {
+                // the left operand is taken before the right one runs, as the interpreter does
+                let a = try_opt!(self.expr(l));
+                let a = if a < mark && assigns(r) { self.copy(a) } else { a };
+                let b = try_opt!(self.expr(r));
+                self.top = mark;
+                let d = self.alloc();
+                self.ops.push(Op::Dyad(v, d, a, b));
+                Some(d)
+            }
+            AST::Atom(Atom::Assign(&AST::Atom(Atom::NameInt(n)), x)) => {
+                let s = try_opt!(self.local(n));
+                let r = try_opt!(self.expr(x));
+                self.top = mark;
+                self.ops.push(Op::Move(s, r));
+                Some(s)
+            }
+            AST::Atom(Atom::Cond(c, t, f)) => {
+                let d = self.alloc();
+                let c = try_opt!(self.expr(c));
+                let jz = self.ops.len();
+                self.ops.push(Op::Jz(c, 0));
+                self.top = mark + 1;
+                let t = try_opt!(self.expr(t));
+                self.ops.push(Op::Move(d, t));
+                let jmp = self.ops.len();
+                self.ops.push(Op::Jmp(0));
+                self.ops[jz] = Op::Jz(c, self.ops.len());
+                self.top = mark + 1;
+                let f = try_opt!(self.expr(f));
+                self.ops.push(Op::Move(d, f));
+                self.ops[jmp] = Op::Jmp(self.ops.len());
+                self.top = mark + 1;
+                Some(d)
+            }
+            AST::Atom(Atom::Call(&AST::Atom(Atom::NameInt(f)), &AST::Atom(Atom::Dict(&AST::Vector(ref args))))) => {
+                if self.local(f).is_some() {
+                    return None;
+                }
+                // arguments go to consecutive registers
+                for a in args.iter() {
+                    let at = self.top;
+                    let r = try_opt!(self.expr(a));
+                    if r != at {
+                        self.top = at;
+                        self.copy(r);
+                    }
+                }
+                self.top = mark;
+                let d = self.alloc();
+                self.ops.push(Op::Call(d, f, mark, args.len() as u16));
+                Some(d)
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn compile<'a>(names: &'a AST<'a>, body: &'a AST<'a>) -> Option<Code> {
+    let mut c = Compiler {
+        ops: Vec::new(),
+        names: Vec::new(),
+        top: 0,
+        regs: 0,
+    };
+    for n in names.into_iter() {
+        match *n {
+            AST::Atom(Atom::NameInt(n)) => c.names.push(n),
+            _ => return None,
+        }
+    }
+    let args = c.names.len();
+    c.locals(body);
+    c.top = c.names.len() as u16;
+    c.regs = c.top;
+    let mut last = None;
+    for e in body.into_iter() {
+        c.top = c.names.len() as u16;
+        last = Some(try_opt!(c.expr(e)));
+    }
+    c.ops.push(Op::Ret(try_opt!(last)));
+    Some(Code {
+        ops: c.ops,
+        args: args,
+        regs: c.regs as usize,
+    })
+}
+
+// Compiled bodies by lambda, None when a body doesn't compile.
+// Keys are arena addresses, so the cache is cleared whenever slots are collected.
+pub struct Cache(HashMap<(usize, usize), Option<Rc<Code>>>);
+
+impl Cache {
+    pub fn new() -> Cache {
+        Cache(HashMap::new())
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    pub fn get<'a>(&mut self, names: &'a AST<'a>, body: &'a AST<'a>) -> Option<Rc<Code>> {
+        let key = (names as *const AST<'a> as usize, body as *const AST<'a> as usize);
+        self.0.entry(key).or_insert_with(|| compile(names, body).map(Rc::new)).clone()
+    }
+}
+
+fn closed<'a>(code: &Rc<Code>,
+             scope: NodeId,
+             env: &'a Environment<'a>,
+             cache: &mut Cache,
+             seen: &mut Vec<(usize, NodeId)>)
+             -> bool {
+    // recursive callees are checked once per scope
+    let key = (&**code as *const Code as usize, scope);
+    if seen.contains(&key) {
+        return true;
+    }
+    seen.push(key);
+    for op in code.ops.iter() {
+        match *op {
+            Op::Global(_, n) => {
+                match env.get(n, scope) {
+                    Some((x, _)) => {
+                        if let Val::Unset = scalar(x) {
+                            return false;
+                        }
+                    }
+                    None => return false,
+                }
+            }
+            Op::Call(_, n, _, argc) => {
+                match env.get(n, scope) {
+                    Some((&AST::Atom(Atom::Lambda(closure, names, body)), _)) => {
+                        match cache.get(names, body) {
+                            Some(ref c) if c.args == argc as usize => {
+                                if !closed(c, closure.unwrap_or(scope), env, cache, seen) {
+                                    return false;
+                                }
+                            }
+                            _ => return false,
+                        }
+                    }
+                    _ => return false,
+                }
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+pub fn check<'a>(code: &Rc<Code>, scope: NodeId, env: &'a Environment<'a>, cache: &mut Cache) -> bool {
+    // globals can't change while compiled code runs, so one look before it starts is enough
+    closed(code, scope, env, cache, &mut Vec::new())
+}
+
+struct Frame {
+    code: Rc<Code>,
+    pc: usize,
+    base: usize,
+    scope: NodeId,
+    ret: usize,
+}
+
+pub struct Machine {
+    frames: Vec<Frame>,
+    regs: Vec<Val>,
+    pub steps: u64,
+}
+
+fn scalar<'a>(x: &AST<'a>) -> Val {
+    match *x {
+        AST::Atom(Atom::Value(Value::Number(n))) => Val::Int(n),
+        AST::Atom(Atom::Value(Value::Float(n))) => Val::Float(n),
+        _ => Val::Unset,
+    }
+}
+
+fn ast<'a>(x: Val) -> AST<'a> {
+    AST::Atom(Atom::Value(match x {
+        Val::Int(n) => Value::Number(n),
+        Val::Float(n) => Value::Float(n),
+        Val::Unset => Value::Nil,
+    }))
+}
+
+fn dyad(v: Verb, l: Val, r: Val) -> Val {
+    match (v, l, r) {
+        // wrapping, as the tree interpreter does
+        (Verb::Plus, Val::Int(a), Val::Int(b)) => Val::Int(a.wrapping_add(b)),
+        (Verb::Minus, Val::Int(a), Val::Int(b)) => Val::Int(a.wrapping_sub(b)),
+        (Verb::Times, Val::Int(a), Val::Int(b)) => Val::Int(a.wrapping_mul(b)),
+        (Verb::Min, Val::Int(a), Val::Int(b)) => Val::Int(if a < b { a } else { b }),
+        (Verb::Max, Val::Int(a), Val::Int(b)) => Val::Int(if a > b { a } else { b }),
+        (Verb::Eq, Val::Int(a), Val::Int(b)) => Val::Int((a == b) as i64),
+        (Verb::NEq, Val::Int(a), Val::Int(b)) => Val::Int((a != b) as i64),
+        (Verb::Lt, Val::Int(a), Val::Int(b)) => Val::Int((a < b) as i64),
+        (Verb::Le, Val::Int(a), Val::Int(b)) => Val::Int((a <= b) as i64),
+        (Verb::Gt, Val::Int(a), Val::Int(b)) => Val::Int((a > b) as i64),
+        (Verb::Ge, Val::Int(a), Val::Int(b)) => Val::Int((a >= b) as i64),
+        (_, Val::Unset, _) |
+        (_, _, Val::Unset) => Val::Unset,
+        // floats and the rest keep the verb semantics
+        (v, l, r) => {
+            match verb::eval(v, &ast(l), &ast(r)) {
+                Ok(x) => scalar(&x),
+                Err(_) => Val::Unset,
+            }
+        }
+    }
+}
+
+impl Machine {
+    pub fn enter<'a>(code: Rc<Code>, scope: NodeId, args: &'a AST<'a>) -> Option<Machine> {
+        let mut regs = Vec::with_capacity(code.regs);
+        for a in args.into_iter() {
+            match scalar(a) {
+                Val::Unset => return None,
+                x => regs.push(x),
+            }
+        }
+        if regs.len() != code.args {
+            return None;
+        }
+        regs.resize(code.regs, Val::Unset);
+        Some(Machine {
+            frames: vec![Frame {
+                             code: code,
+                             pc: 0,
+                             base: 0,
+                             scope: scope,
+                             ret: 0,
+                         }],
+            regs: regs,
+            steps: 0,
+        })
+    }
+
+    pub fn run<'a>(&mut self, env: &'a Environment<'a>, cache: &mut Cache, budget: u64) -> Step {
+        let mut budget = budget;
+        loop {
+            if budget == 0 {
+                return Step::Preempt;
+            }
+            budget -= 1;
+            self.steps += 1;
+            let (op, base, scope) = {
+                let f = self.frames.last_mut().unwrap();
+                f.pc += 1;
+                (f.code.ops[f.pc - 1], f.base, f.scope)
+            };
+            match op {
+                Op::Int(d, n) => self.regs[base + d as usize] = Val::Int(n),
+                Op::Float(d, n) => self.regs[base + d as usize] = Val::Float(n),
+                Op::Move(d, s) => self.regs[base + d as usize] = self.regs[base + s as usize],
+                Op::Global(d, n) => {
+                    let v = match env.get(n, scope) {
+                        Some((x, _)) => scalar(x),
+                        None => Val::Unset,
+                    };
+                    if let Val::Unset = v {
+                        return Step::Fallback;
+                    }
+                    self.regs[base + d as usize] = v;
+                }
+                Op::Dyad(v, d, l, r) => {
+                    let x = dyad(v, self.regs[base + l as usize], self.regs[base + r as usize]);
+                    if let Val::Unset = x {
+                        return Step::Fallback;
+                    }
+                    self.regs[base + d as usize] = x;
+                }
+                Op::Jz(c, to) => {
+                    match self.regs[base + c as usize] {
+                        Val::Int(0) => self.frames.last_mut().unwrap().pc = to,
+                        Val::Unset => return Step::Fallback,
+                        _ => {}
+                    }
+                }
+                Op::Jmp(to) => self.frames.last_mut().unwrap().pc = to,
+                Op::Call(d, n, a, argc) => {
+                    let (code, closure) = match env.get(n, scope) {
+                        Some((&AST::Atom(Atom::Lambda(closure, names, body)), _)) => {
+                            match cache.get(names, body) {
+                                Some(c) => (c, closure.unwrap_or(scope)),
+                                None => return Step::Fallback,
+                            }
+                        }
+                        _ => return Step::Fallback,
+                    };
+                    if code.args != argc as usize {
+                        return Step::Fallback;
+                    }
+                    let callee = self.regs.len();
+                    for i in 0..argc as usize {
+                        let x = self.regs[base + a as usize + i];
+                        self.regs.push(x);
+                    }
+                    self.regs.resize(callee + code.regs, Val::Unset);
+                    self.frames.push(Frame {
+                        code: code,
+                        pc: 0,
+                        base: callee,
+                        scope: closure,
+                        ret: base + d as usize,
+                    });
+                }
+                Op::Ret(r) => {
+                    let x = self.regs[base + r as usize];
+                    let f = self.frames.pop().unwrap();
+                    match x {
+                        Val::Unset => return Step::Fallback,
+                        Val::Int(n) if self.frames.is_empty() => return Step::Done(Value::Number(n)),
+                        Val::Float(n) if self.frames.is_empty() => return Step::Done(Value::Float(n)),
+                        x => {
+                            self.regs.truncate(f.base);
+                            self.regs[f.ret] = x;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+// Dictionary: a key list mapped onto a value list of the same length.
+// A keyed table is a dictionary from a key table to a value table.
+
+use std::fmt;
+use commands::ast::{Value, Error};
+use tables::table::{Table, len, cell, show};
+
+#[derive(PartialEq,Debug,Clone)]
+pub struct Dict {
+    pub keys: Value,
+    pub values: Value,
+}
+
+fn error(desc: &str, x: &fmt::Debug) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{:?}", x),
+    }
+}
+
+pub fn count(v: &Value) -> usize {
+    match *v {
+        Value::List(ref x) => x.len(),
+        Value::Table(ref t) => t.count(),
+        ref a => len(a).unwrap_or(1),
+    }
+}
+
+pub fn at(v: &Value, i: usize) -> Value {
+    // table rows are general lists of their cells
+    match *v {
+        Value::Table(ref t) => Value::List(t.row(i)),
+        ref a => cell(a, i),
+    }
+}
+
+impl Dict {
+    pub fn new(keys: Value, values: Value) -> Result<Dict, Error> {
+        if count(&keys) != count(&values) {
+            return Err(error("length", &keys));
+        }
+        Ok(Dict {
+            keys: keys,
+            values: values,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        count(&self.keys)
+    }
+
+    pub fn is_keyed(&self) -> bool {
+        match (&self.keys, &self.values) {
+            (&Value::Table(_), &Value::Table(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn key(&self, i: usize) -> Value {
+        // a single key column matches by atom, several by row
+        match self.keys {
+            Value::Table(ref t) if t.names.len() == 1 => cell(&t.columns[0], i),
+            ref k => at(k, i),
+        }
+    }
+
+    pub fn find(&self, k: &Value) -> Option<usize> {
+        // (1;2) is lexed as a typed vector, rows of a key table are general lists
+        let k = match self.keys {
+            Value::Table(ref t) if t.names.len() > 1 => Value::List((0..count(k)).map(|i| at(k, i)).collect()),
+            _ => k.clone(),
+        };
+        (0..self.count()).position(|i| self.key(i) == k)
+    }
+
+    pub fn get(&self, i: usize) -> Value {
+        at(&self.values, i)
+    }
+}
+
+impl fmt::Display for Dict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.keys, &self.values) {
+            (&Value::Table(ref k), &Value::Table(ref v)) => {
+                // keyed table: key columns | value columns
+                let (k, v) = (format!("{}", k), format!("{}", v));
+                let lines = k.lines()
+                    .zip(v.lines())
+                    .map(|(a, b)| format!("{}| {}", a, b))
+                    .collect::<Vec<String>>();
+                write!(f, "{}", lines.join("\n"))
+            }
+            _ => {
+                let keys = (0..self.count()).map(|i| show(&self.key(i))).collect::<Vec<String>>();
+                let w = keys.iter().map(|s| s.len()).max().unwrap_or(0);
+                let lines = keys.iter()
+                    .enumerate()
+                    .map(|(i, k)| format!("{:2$}| {}", k, show(&self.get(i)), w))
+                    .collect::<Vec<String>>();
+                write!(f, "{}", lines.join("\n"))
+            }
+        }
+    }
+}
@@ -0,0 +1,7 @@
+// In-memory columnar tables, see readme.md
+
+pub mod table;
+pub mod dict;
+
+pub use self::table::Table;
+pub use self::dict::Dict;
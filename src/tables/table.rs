@@ -0,0 +1,219 @@
+// Columnar table: named typed columns of equal length
+
+use std::fmt;
+use commands::ast::{Value, Error};
+use commands::{symbol, temporal, kfmt};
+
+#[derive(PartialEq,Debug,Clone)]
+pub struct Table {
+    pub names: Vec<String>,
+    pub columns: Vec<Value>,
+}
+
+fn error(desc: &str, x: &fmt::Debug) -> Error {
+    Error::EvalError {
+        desc: desc.to_string(),
+        ast: format!("{:?}", x),
+    }
+}
+
+pub fn len(v: &Value) -> Option<usize> {
+    match *v {
+        Value::VecInt(ref x) => Some(x.len()),
+        Value::VecFloat(ref x) => Some(x.len()),
+        Value::VecSym(ref x) => Some(x.len()),
+        Value::VecTemporal(_, ref x) => Some(x.len()),
+        Value::List(ref x) => Some(x.len()),
+        _ => None,
+    }
+}
+
+pub fn cell(v: &Value, i: usize) -> Value {
+    match *v {
+        Value::VecInt(ref x) => Value::Number(x[i]),
+        Value::VecFloat(ref x) => Value::Float(x[i]),
+        Value::VecSym(ref x) => Value::SymbolInt(x[i]),
+        Value::VecTemporal(k, ref x) => Value::Temporal(k, x[i]),
+        Value::List(ref x) => x[i].clone(),
+        ref a => a.clone(),
+    }
+}
+
+fn repeat(v: &Value, n: usize) -> Option<Value> {
+    // atoms are broadcast to the table length
+    match *v {
+        Value::Number(x) => Some(Value::VecInt(vec![x; n])),
+        Value::Float(x) => Some(Value::VecFloat(vec![x; n])),
+        Value::SymbolInt(x) => Some(Value::VecSym(vec![x; n])),
+        Value::Temporal(k, x) => Some(Value::VecTemporal(k, vec![x; n])),
+        _ => None,
+    }
+}
+
+fn select(v: &Value, idx: &[usize]) -> Value {
+    match *v {
+        Value::VecInt(ref x) => Value::VecInt(idx.iter().map(|&i| x[i]).collect()),
+        Value::VecFloat(ref x) => Value::VecFloat(idx.iter().map(|&i| x[i]).collect()),
+        Value::VecSym(ref x) => Value::VecSym(idx.iter().map(|&i| x[i]).collect()),
+        Value::VecTemporal(k, ref x) => Value::VecTemporal(k, idx.iter().map(|&i| x[i]).collect()),
+        Value::List(ref x) => Value::List(idx.iter().map(|&i| x[i].clone()).collect()),
+        ref a => a.clone(),
+    }
+}
+
+fn join(a: &Value, b: &Value) -> Option<Value> {
+    match (a, b) {
+        (&Value::VecInt(ref x), &Value::VecInt(ref y)) => Some(Value::VecInt(x.iter().chain(y).cloned().collect())),
+        (&Value::VecFloat(ref x), &Value::VecFloat(ref y)) => {
+            Some(Value::VecFloat(x.iter().chain(y).cloned().collect()))
+        }
+        (&Value::VecFloat(ref x), &Value::VecInt(ref y)) => {
+            Some(Value::VecFloat(x.iter().cloned().chain(y.iter().map(|i| *i as f64)).collect()))
+        }
+        (&Value::VecSym(ref x), &Value::VecSym(ref y)) => Some(Value::VecSym(x.iter().chain(y).cloned().collect())),
+        (&Value::VecTemporal(k, ref x), &Value::VecTemporal(j, ref y)) if k == j => {
+            Some(Value::VecTemporal(k, x.iter().chain(y).cloned().collect()))
+        }
+        (&Value::List(ref x), &Value::List(ref y)) => Some(Value::List(x.iter().chain(y).cloned().collect())),
+        _ => None,
+    }
+}
+
+impl Table {
+    pub fn new(names: Vec<String>, columns: Vec<Value>) -> Result<Table, Error> {
+        if names.len() != columns.len() {
+            return Err(error("length", &names));
+        }
+        for (i, n) in names.iter().enumerate() {
+            if names[..i].contains(n) {
+                return Err(error("dup", n));
+            }
+        }
+        let n = columns.iter().filter_map(len).max().unwrap_or(1);
+        let mut cols = Vec::with_capacity(columns.len());
+        for c in columns.iter() {
+            match len(c) {
+                Some(l) if l == n => cols.push(c.clone()),
+                Some(_) => return Err(error("length", c)),
+                None => {
+                    match repeat(c, n) {
+                        Some(v) => cols.push(v),
+                        None => return Err(error("type", c)),
+                    }
+                }
+            }
+        }
+        Ok(Table {
+            names: names,
+            columns: cols,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.columns.first().and_then(len).unwrap_or(0)
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Value> {
+        self.names.iter().position(|n| n == name).map(|i| &self.columns[i])
+    }
+
+    pub fn row(&self, i: usize) -> Vec<Value> {
+        self.columns.iter().map(|c| cell(c, i)).collect()
+    }
+
+    pub fn rows(&self, idx: &[usize]) -> Table {
+        Table {
+            names: self.names.clone(),
+            columns: self.columns.iter().map(|c| select(c, idx)).collect(),
+        }
+    }
+
+    pub fn split(&self, n: usize) -> (Table, Table) {
+        // first n columns and the rest, as for keying a table
+        (Table {
+            names: self.names[..n].to_vec(),
+            columns: self.columns[..n].to_vec(),
+        },
+         Table {
+            names: self.names[n..].to_vec(),
+            columns: self.columns[n..].to_vec(),
+        })
+    }
+
+    pub fn merge(&self, other: &Table) -> Result<Table, Error> {
+        // side by side, the inverse of split
+        let mut names = self.names.clone();
+        let mut columns = self.columns.clone();
+        names.extend(other.names.iter().cloned());
+        columns.extend(other.columns.iter().cloned());
+        Table::new(names, columns)
+    }
+
+    pub fn append(&self, other: &Table) -> Result<Table, Error> {
+        // columns are matched by name, so order may differ
+        if self.names.len() != other.names.len() {
+            return Err(error("mismatch", &other.names));
+        }
+        let mut cols = Vec::with_capacity(self.columns.len());
+        for (n, c) in self.names.iter().zip(self.columns.iter()) {
+            match other.column(n).and_then(|o| join(c, o)) {
+                Some(v) => cols.push(v),
+                None => return Err(error("mismatch", n)),
+            }
+        }
+        Ok(Table {
+            names: self.names.clone(),
+            columns: cols,
+        })
+    }
+
+    pub fn upsert(&self, row: Vec<Value>) -> Result<Table, Error> {
+        // a row is one atom per column, in column order
+        if row.len() != self.names.len() {
+            return Err(error("length", &row));
+        }
+        let one = try!(Table::new(self.names.clone(), row));
+        self.append(&one)
+    }
+}
+
+pub fn show(v: &Value) -> String {
+    match *v {
+        Value::Float(x) => format!("{}", x),
+        Value::Number(x) => format!("{}", x),
+        Value::SymbolInt(x) => symbol::name(x),
+        Value::Sequence(ref s) => s.clone(),
+        Value::Char(c) => c.to_string(),
+        Value::Temporal(k, x) => temporal::format(k, x),
+        ref a => kfmt::value(a),
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // q console layout: header, dashes, one line per row
+        let cells = self.columns
+            .iter()
+            .map(|c| (0..self.count()).map(|i| show(&cell(c, i))).collect::<Vec<String>>())
+            .collect::<Vec<Vec<String>>>();
+        let widths = self.names
+            .iter()
+            .zip(cells.iter())
+            .map(|(n, c)| c.iter().map(|s| s.len()).fold(n.len(), ::std::cmp::max))
+            .collect::<Vec<usize>>();
+        let line = |v: Vec<&String>| {
+            v.iter()
+                .zip(widths.iter())
+                .map(|(s, w)| format!("{:1$}", s, w))
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        try!(write!(f, "{}\n", line(self.names.iter().collect())));
+        let dashes = widths.iter().sum::<usize>() + widths.len().saturating_sub(1);
+        try!(write!(f, "{}", ::std::iter::repeat("-").take(dashes).collect::<String>()));
+        for i in 0..self.count() {
+            try!(write!(f, "\n{}", line(cells.iter().map(|c| &c[i]).collect())));
+        }
+        Ok(())
+    }
+}
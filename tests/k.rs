@@ -3,16 +3,23 @@ extern crate test;
 extern crate kernel;
 
 use kernel::commands::ast::*;
+use kernel::commands::{symbol, kfmt, ipc};
+use kernel::commands::temporal::Temporal;
+use kernel::tables::{Table, Dict};
 use kernel::streams::interpreter::*;
+use kernel::streams::verb::simd::{self, Kernel, Arg};
 use kernel::reactors::task::{Termination, Context, Poll, Task};
 use kernel::reactors::job::Job;
 use kernel::reactors::cps::CpsTask;
 use kernel::reactors::scheduler::Scheduler;
 use kernel::handle::{self, into_raw, UnsafeShared, use_, from_raw};
-use kernel::intercore::bus::Memory;
-use kernel::intercore::message::Message;
+use kernel::intercore::bus::{Memory, Channel, TypeId};
+use kernel::intercore::message::{Message, AckEval};
 use kernel::intercore::server::intercore;
 use kernel::queues::publisher::{Publisher, Subscriber};
+use std::net::{self, SocketAddr};
+use std::io::{Read, Write};
+use std::time::Duration;
 
 fn av<'a>(x: Value) -> AST<'a> {
     AST::Atom(Atom::Value(x))
@@ -22,12 +29,12 @@ fn av<'a>(x: Value) -> AST<'a> {
 pub fn k_ariph() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
-    let code = h.borrow_mut().parse(&"1+2".to_string());
+    let code = h.borrow_mut().parse(&"1+2".to_string()).unwrap();
 
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::Verb(Verb::Plus, &av(Value::Number(1)), &av(Value::Number(2))))]));
 
-    let code = h.borrow_mut().parse(&"1+2*4".to_string());
+    let code = h.borrow_mut().parse(&"1+2*4".to_string()).unwrap();
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::Verb(Verb::Plus,
                                                       &av(Value::Number(1)),
@@ -40,10 +47,10 @@ pub fn k_ariph() {
 pub fn k_list() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
-    let code = h.borrow_mut().parse(&"(1;\"2\";3;4.1111)".to_string());
+    let code = h.borrow_mut().parse(&"(1;\"2\";3;4.1111)".to_string()).unwrap();
 
     let v: Vec<AST> =
-        vec![av(Value::Number(1)), av(Value::SequenceInt(0)), av(Value::Number(3)), av(Value::Float(4.1111))];
+        vec![av(Value::Number(1)), av(Value::Char('2')), av(Value::Number(3)), av(Value::Float(4.1111))];
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::List(&AST::Vector(v)))]));
 }
@@ -52,22 +59,20 @@ pub fn k_list() {
 pub fn k_symbols() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
-    let code = h.borrow_mut().parse(&"`a`b`c;`1`1`1".to_string());
+    let code = h.borrow_mut().parse(&"`a`b`c;`1`1`1".to_string()).unwrap();
+    let e = symbol::intern("").unwrap();
     assert_eq!(code,
                &AST::Vector(
                    vec![
                        // symbols
-                       AST::Atom(Atom::Call(&av(Value::SymbolInt(0)),
-                                              &AST::Atom(Atom::Call(&av(Value::SymbolInt(1)),
-                                                                      &av(Value::SymbolInt(2)))))), 
+                       av(Value::VecSym(vec![symbol::intern("a").unwrap(), symbol::intern("b").unwrap(), symbol::intern("c").unwrap()])),
 
                        // values
-                       AST::Atom(Atom::Call(&av(Value::SymbolInt(3)),
+                       AST::Atom(Atom::Call(&av(Value::SymbolInt(e)),
                                               &AST::Atom(Atom::Call(&av(Value::Number(1)),
-                                                                      &AST::Atom(Atom::Call(&av(Value::SymbolInt(3)),
+                                                                      &AST::Atom(Atom::Call(&av(Value::SymbolInt(e)),
                                                                                               &AST::Atom(Atom::Call(&av(Value::Number(1)),
-                                                                                                                      &AST::Atom(Atom::Call(&av(Value::SymbolInt(3)), &av(Value::Number(1))))))))))))
-                           
+                                                                                                                      &AST::Atom(Atom::Call(&av(Value::SymbolInt(e)), &av(Value::Number(1))))))))))))
                    ]));
 }
 
@@ -75,7 +80,7 @@ pub fn k_symbols() {
 pub fn k_assign() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
-    let code = h.borrow_mut().parse(&"a:b:c:1".to_string());
+    let code = h.borrow_mut().parse(&"a:b:c:1".to_string()).unwrap();
     assert_eq!(code,
                &AST::Vector(
                    vec![AST::Atom(Atom::Assign(&AST::Atom(Atom::NameInt(0)),
@@ -88,7 +93,7 @@ pub fn k_anyargs0() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"[]".to_string());
+    let code = h.borrow_mut().parse(&"[]".to_string()).unwrap();
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::Dict(&AST::Vector(vec![AST::Atom(Atom::Any)])))]));
 }
@@ -98,7 +103,7 @@ pub fn k_anyargs1() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"[;]".to_string());
+    let code = h.borrow_mut().parse(&"[;]".to_string()).unwrap();
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::Dict(&AST::Vector(vec![AST::Atom(Atom::Any),
                                                                         AST::Atom(Atom::Any)])))]));
@@ -109,7 +114,7 @@ pub fn k_anyargs2() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"[;;]".to_string());
+    let code = h.borrow_mut().parse(&"[;;]".to_string()).unwrap();
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::Dict(&AST::Vector(vec![AST::Atom(Atom::Any),
                                                                         AST::Atom(Atom::Any),
@@ -121,7 +126,7 @@ pub fn k_anyargs3() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"[;;3]".to_string());
+    let code = h.borrow_mut().parse(&"[;;3]".to_string()).unwrap();
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::Dict(&AST::Vector(vec![AST::Atom(Atom::Any),
                                                                         AST::Atom(Atom::Any),
@@ -133,7 +138,7 @@ pub fn k_anyargs4() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"[1;;]".to_string());
+    let code = h.borrow_mut().parse(&"[1;;]".to_string()).unwrap();
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::Dict(&AST::Vector(vec![av(Value::Number(1)),
                                                                         AST::Atom(Atom::Any),
@@ -145,7 +150,7 @@ pub fn k_vecconst1() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"(1;2;3)".to_string());
+    let code = h.borrow_mut().parse(&"(1;2;3)".to_string()).unwrap();
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::List(&av(Value::VecInt(vec![1, 2, 3]))))]));
 }
@@ -155,7 +160,7 @@ pub fn k_vecconst2() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"(1.0;2.0;3.0)".to_string());
+    let code = h.borrow_mut().parse(&"(1.0;2.0;3.0)".to_string()).unwrap();
     assert_eq!(code,
                &AST::Vector(vec![AST::Atom(Atom::List(&av(Value::VecFloat(vec![1.0, 2.0, 3.0]))))]));
 }
@@ -165,7 +170,7 @@ pub fn k_plus() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"2+5+3".to_string());
+    let code = h.borrow_mut().parse(&"2+5+3".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "10");
 }
@@ -175,7 +180,7 @@ pub fn k_func() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"{x*2}[(1;2;3)]".to_string());
+    let code = h.borrow_mut().parse(&"{x*2}[(1;2;3)]".to_string()).unwrap();
     assert_eq!(format!("{:?}", code),
                "Vector([Atom(Call(Atom(Lambda(None, Atom(NameInt(0)), Vector([Atom(Verb(Times, Atom(NameInt(0)), \
                 Atom(Value(Number(2)))))]))), Atom(Dict(Vector([Atom(List(Atom(Value(VecInt([1, 2, 3])))))])))))])");
@@ -186,7 +191,7 @@ pub fn k_adverb() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"{x+2}/(1;2;3)".to_string());
+    let code = h.borrow_mut().parse(&"{x+2}/(1;2;3)".to_string()).unwrap();
     assert_eq!(format!("{:?}", code),
                "Vector([Atom(Adverb(Over, Atom(Lambda(None, Atom(NameInt(0)), Vector([Atom(Verb(Plus, \
                 Atom(NameInt(0)), Atom(Value(Number(2)))))]))), Atom(List(Atom(Value(VecInt([1, 2, 3])))))))])");
@@ -198,10 +203,11 @@ pub fn k_reduce() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"+/{x*y}[(1;3;4;5;6);(2;6;2;1;3)]".to_string());
+    let code = h.borrow_mut().parse(&"+/{x*y}[(1;3;4;5;6);(2;6;2;1;3)]".to_string()).unwrap();
     assert_eq!(format!("{:?}", code),
                "Vector([Atom(Adverb(Over, Atom(Verb(Plus, Atom(Value(Nil)), Atom(Value(Nil)))), \
-                Atom(Call(Atom(Lambda(None, Atom(NameInt(0)), Vector([Atom(Verb(Times, Atom(NameInt(0)), \
+                Atom(Call(Atom(Lambda(None, Vector([Atom(NameInt(0)), Atom(NameInt(1))]), \
+                Vector([Atom(Verb(Times, Atom(NameInt(0)), \
                 Atom(NameInt(1))))]))), Atom(Dict(Vector([Atom(List(Atom(Value(VecInt([1, 3, 4, 5, 6]))))), \
                 Atom(List(Atom(Value(VecInt([2, 6, 2, 1, 3])))))])))))))])");
 }
@@ -211,7 +217,7 @@ pub fn k_dict1() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"a:10;[1;2;a;5]".to_string());
+    let code = h.borrow_mut().parse(&"a:10;[1;2;a;5]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#a[1;2;10;5]");
 }
@@ -221,9 +227,9 @@ pub fn k_dict2() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"[1;[\"2\";3];4;5]".to_string());
+    let code = h.borrow_mut().parse(&"[1;[\"2\";3];4;5]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
-               "#a[1;#a[0s;3];4;5]");
+               "#a[1;#a[\"2\";3];4;5]");
 }
 
 #[test]
@@ -231,9 +237,9 @@ pub fn k_dict3() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"[1;[\"2\";[\"3\";3]];4;5]".to_string());
+    let code = h.borrow_mut().parse(&"[1;[\"2\";[\"3\";3]];4;5]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
-               "#a[1;#a[0s;#a[1s;3]];4;5]");
+               "#a[1;#a[\"2\";#a[\"3\";3]];4;5]");
 }
 
 #[test]
@@ -241,7 +247,7 @@ pub fn k_nested_dict() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"a:10;[1;2;[a+a;[4+a;3];2];5]".to_string());
+    let code = h.borrow_mut().parse(&"a:10;[1;2;[a+a;[4+a;3];2];5]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#a[1;2;#a[20;#a[14;3];2];5]");
 }
@@ -251,9 +257,9 @@ pub fn k_list1() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"(1;(\"2\";(\"3\";3));4;5)".to_string());
+    let code = h.borrow_mut().parse(&"(1;(\"2\";(\"3\";3));4;5)".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
-               "#a[1;#a[0s;#a[1s;3]];4;5]");
+               "#a[1;#a[\"2\";#a[\"3\";3]];4;5]");
 }
 
 #[test]
@@ -261,7 +267,7 @@ pub fn k_nested_list() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"a:10;(1;2;(a+a;(4+a;3);2);5)".to_string());
+    let code = h.borrow_mut().parse(&"a:10;(1;2;(a+a;(4+a;3);2);5)".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#a[1;2;#a[20;#a[14;3];2];5]");
 }
@@ -271,9 +277,9 @@ pub fn k_expr1() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"1;[\"2\";1]".to_string());
+    let code = h.borrow_mut().parse(&"1;[\"2\";1]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
-               "#a[0s;1]");
+               "#a[\"2\";1]");
 }
 
 #[test]
@@ -281,12 +287,12 @@ pub fn k_repl1() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"xo:{[x;y]y};xo[1;[2;3]]".to_string());
+    let code = h.borrow_mut().parse(&"xo:{[x;y]y};xo[1;[2;3]]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#i[2;3]");
 
     // test to avoid specializing vectors in function arguments
-    let code = h.borrow_mut().parse(&"xo:{[x;y;z]y};xo[1;2;3]".to_string());
+    let code = h.borrow_mut().parse(&"xo:{[x;y;z]y};xo[1;2;3]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "2");
 }
@@ -296,7 +302,7 @@ pub fn k_repl2() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"xo:{1};z:{[x]xo x};d:{[x]z x};e:{[x]d x};e[3]".to_string());
+    let code = h.borrow_mut().parse(&"xo:{1};z:{[x]xo x};d:{[x]z x};e:{[x]d x};e[3]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "1");
 }
@@ -306,7 +312,7 @@ pub fn k_repl3() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"y:3;addy:{y};f:{[g;y]g y};f[addy;1]".to_string());
+    let code = h.borrow_mut().parse(&"y:3;addy:{y};f:{[g;y]g y};f[addy;1]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "3");
 }
@@ -316,7 +322,7 @@ pub fn k_factorial() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"fac:{$[x=0;1;x*fac[x-1]]};fac 20".to_string());
+    let code = h.borrow_mut().parse(&"fac:{$[x=0;1;x*fac[x-1]]};fac 20".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "2432902008176640000");
 }
@@ -326,7 +332,7 @@ pub fn k_tail_factorial() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"x:5;fac:{[a;b]$[a=1;b;fac[a-1;a*b]]};fac[x-1;x]".to_string());
+    let code = h.borrow_mut().parse(&"x:5;fac:{[a;b]$[a=1;b;fac[a-1;a*b]]};fac[x-1;x]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "120");
 }
@@ -336,7 +342,7 @@ pub fn k_cond1() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"a:{[x;y]x y};a[{x};10]".to_string());
+    let code = h.borrow_mut().parse(&"a:{[x;y]x y};a[{x};10]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "10");
 }
@@ -346,7 +352,7 @@ pub fn k_cond2() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"a:{[x;y]$[x y;20;10]};a[{x};10]".to_string());
+    let code = h.borrow_mut().parse(&"a:{[x;y]$[x y;20;10]};a[{x};10]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "20");
 }
@@ -356,18 +362,39 @@ pub fn k_cond3() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"a:{[x;y]$[x y;20;10]};a[{x};0]".to_string());
+    let code = h.borrow_mut().parse(&"a:{[x;y]$[x y;20;10]};a[{x};0]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "10");
 
 }
 
+#[test]
+pub fn k_cond4() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"$[1;`a;1;`b;`c]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "`a");
+    let code = h.borrow_mut().parse(&"$[0;`a;1;`b;`c]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "`b");
+    let code = h.borrow_mut().parse(&"$[0;`a;0;`b;`c]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "`c");
+    let code = h.borrow_mut().parse(&"{$[x<0;`neg;x=0;`zero;x<10;`small;`big]}'-1 0 5 50".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "`neg`zero`small`big");
+    assert_eq!(format!("{:?}", h.borrow_mut().parse(&"1+$[0;1;1;2]".to_string())),
+               "Err(ParseError { desc: \"rank\", pos: 2 })");
+}
+
 #[test]
 pub fn k_14() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"f:{a:9};a:14;k:{[x] a}; k 3".to_string());
+    let code = h.borrow_mut().parse(&"f:{a:9};a:14;k:{[x] a}; k 3".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "14");
 }
@@ -377,7 +404,7 @@ pub fn k_multiargs() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"xa:9;f:{[x;y;z]x+y*z};f[1;xa+11;3]".to_string());
+    let code = h.borrow_mut().parse(&"xa:9;f:{[x;y;z]x+y*z};f[1;xa+11;3]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "61");
 }
@@ -387,7 +414,7 @@ pub fn k_multiargs2() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"b:2;a:3;fac:{[x;y]x*y};fac[b*a;a+1]".to_string());
+    let code = h.borrow_mut().parse(&"b:2;a:3;fac:{[x;y]x*y};fac[b*a;a+1]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "24");
 }
@@ -396,9 +423,9 @@ pub fn k_tensor0() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"g:1;b:1;[[g;g*b;1;0];[g*b;g;180;0];[0;0;270;0];[0;0;0;1]]".to_string());
+    let code = h.borrow_mut().parse(&"g:1;b:1;[[g;g*b;1;0];[g*b;g;180;0];[0;0;270;0];[0;0;0;1]]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
-               "#a[#a[1;1;1;0];#a[1;1;180;0];#i[0;0;270;0];#i[0;0;270;0];#i[0;0;0;1];#i[0;0;0;1]]");
+               "#a[#a[1;1;1;0];#a[1;1;180;0];#i[0;0;270;0];#i[0;0;0;1]]");
 }
 
 #[test]
@@ -406,9 +433,9 @@ pub fn k_tensor1() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"a:10;[[[a;2;3];[1;[a;4];3]];[1;2]]".to_string());
+    let code = h.borrow_mut().parse(&"a:10;[[[a;2;3];[1;[a;4];3]];[1;2]]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
-               "#a[#a[#a[10;2;3];#a[1;#a[10;4];3]];#i[1;2];#i[1;2]]");
+               "#a[#a[#a[10;2;3];#a[1;#a[10;4];3]];#i[1;2]]");
 }
 
 #[test]
@@ -416,9 +443,9 @@ pub fn k_tensor2() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"a:10;[[[a;2;3];[[a;4];[3;0]]];[1;2]]".to_string());
+    let code = h.borrow_mut().parse(&"a:10;[[[a;2;3];[[a;4];[3;0]]];[1;2]]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
-               "#a[#a[#a[10;2;3];#a[#a[10;4];#i[3;0];#i[3;0]]];#i[1;2];#i[1;2]]");
+               "#a[#a[#a[10;2;3];#a[#a[10;4];#i[3;0]]];#i[1;2]]");
 }
 
 #[test]
@@ -426,9 +453,9 @@ pub fn k_tensor3() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"a:10;[[[[a;2;3];[[a;4];[3;0]]];[1;2]];1]".to_string());
+    let code = h.borrow_mut().parse(&"a:10;[[[[a;2;3];[[a;4];[3;0]]];[1;2]];1]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
-               "#a[#a[#a[#a[10;2;3];#a[#a[10;4];#i[3;0];#i[3;0]]];#i[1;2];#i[1;2]];1]");
+               "#a[#a[#a[#a[10;2;3];#a[#a[10;4];#i[3;0]]];#i[1;2]];1]");
 }
 
 #[test]
@@ -436,8 +463,8 @@ pub fn k_application_order() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code1 = h.borrow_mut().parse(&"a:10;print:{x+1};print[a * 10]".to_string());
-    let code2 = h.borrow_mut().parse(&"a:10;print:{x+1};print a * 10".to_string());
+    let code1 = h.borrow_mut().parse(&"a:10;print:{x+1};print[a * 10]".to_string()).unwrap();
+    let code2 = h.borrow_mut().parse(&"a:10;print:{x+1};print a * 10".to_string()).unwrap();
     assert_eq!(format!("{}",
                        h.borrow_mut().run(code1, Context::Nil, None).unwrap() ==
                        h.borrow_mut().run(code2, Context::Nil, None).unwrap()),
@@ -449,7 +476,7 @@ pub fn k_akkerman() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"f:{[x;y]$[0=x;1+y;$[0=y;f[x-1;1];f[x-1;f[x;y-1]]]]};f[3;4]".to_string());
+    let code = h.borrow_mut().parse(&"f:{[x;y]$[0=x;1+y;$[0=y;f[x-1;1];f[x-1;f[x;y-1]]]]};f[3;4]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "125");
 }
@@ -570,7 +597,7 @@ pub fn k_partial1() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"aa:{[x;y]x+y};bb:aa[;2];bb 3".to_string());
+    let code = h.borrow_mut().parse(&"aa:{[x;y]x+y};bb:aa[;2];bb 3".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "5");
 }
@@ -580,7 +607,7 @@ pub fn k_partial2() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"aa:{[x;y;z]x+y+z};bb:aa[;;];bb[1;2;3]".to_string());
+    let code = h.borrow_mut().parse(&"aa:{[x;y;z]x+y+z};bb:aa[;;];bb[1;2;3]".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "6");
 }
@@ -590,16 +617,16 @@ pub fn k_vecop_va() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"(1;2;3)+1".to_string());
+    let code = h.borrow_mut().parse(&"(1;2;3)+1".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#i[2;3;4]");
-    let code = h.borrow_mut().parse(&"(1;2;3)=1".to_string());
+    let code = h.borrow_mut().parse(&"(1;2;3)=1".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#i[1;0;0]");
-    let code = h.borrow_mut().parse(&"(1;2;3)>1".to_string());
+    let code = h.borrow_mut().parse(&"(1;2;3)>1".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#i[0;1;1]");
-    let code = h.borrow_mut().parse(&"(1;2;3)<>1".to_string());
+    let code = h.borrow_mut().parse(&"(1;2;3)<>1".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#i[0;1;1]");
 }
@@ -609,16 +636,1365 @@ pub fn k_vecop_vv() {
     let mut mem = Memory::new();
     let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
 
-    let code = h.borrow_mut().parse(&"(1;2;3)+(1;2;3)".to_string());
+    let code = h.borrow_mut().parse(&"(1;2;3)+(1;2;3)".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#i[2;4;6]");
-    let code = h.borrow_mut().parse(&"(1;2;3)=(1;2;3)".to_string());
+    let code = h.borrow_mut().parse(&"(1;2;3)=(1;2;3)".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#i[1;1;1]");
-    let code = h.borrow_mut().parse(&"(1;2;3)>(1;1;3)".to_string());
+    let code = h.borrow_mut().parse(&"(1;2;3)>(1;1;3)".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#i[0;1;0]");
-    let code = h.borrow_mut().parse(&"(1;2;3)<>(1;2;3)".to_string());
+    let code = h.borrow_mut().parse(&"(1;2;3)<>(1;2;3)".to_string()).unwrap();
     assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
                "#i[0;0;0]");
 }
+
+#[test]
+pub fn k_monadic() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"!5".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[0;1;2;3;4]");
+    let code = h.borrow_mut().parse(&"x:(3;1;2);#x".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3");
+    let code = h.borrow_mut().parse(&"|(3;1;2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[2;1;3]");
+    let code = h.borrow_mut().parse(&"<(3;1;2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2;0]");
+    let code = h.borrow_mut().parse(&"&(1;0;2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[0;2;2]");
+    let code = h.borrow_mut().parse(&"-(1.5;2.0)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#f[-1.5;-2]");
+    let code = h.borrow_mut().parse(&"- 0N 1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[-9223372036854775808;-1]");
+    let code = h.borrow_mut().parse(&"-(0N;2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[-9223372036854775808;-2]");
+    let code = h.borrow_mut().parse(&"_0n 1.5".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[-9223372036854775808;1]");
+    let code = h.borrow_mut().parse(&"a:3;(.\"a*2\";.(+;1;2);.({x*y};3;4))".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[6;3;12]");
+    let code = h.borrow_mut().parse(&"@[{.\"1+`a\"};0;{x}]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"Dyad plus not supported\"");
+}
+
+#[test]
+pub fn k_monadic_list() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"#(1;(2;3);4)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3");
+    let code = h.borrow_mut().parse(&"*|(1;(2;3))".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[2;3]");
+    let code = h.borrow_mut().parse(&"=(1;2;1;3;2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[#i[0;2];#i[1;4];#i[3]]");
+    let code = h.borrow_mut().parse(&"?(1;2;1;3;2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2;3]");
+}
+
+#[test]
+pub fn k_dyadic_list() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"(-5)#(1;2;3)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[2;3;1;2;3]");
+    let code = h.borrow_mut().parse(&"1_(1;2;3)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[2;3]");
+    let code = h.borrow_mut().parse(&"(1;2),(3;4)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2;3;4]");
+    let code = h.borrow_mut().parse(&"(1;2;3;2)^2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;3]");
+    let code = h.borrow_mut().parse(&"(5;6;7)?6".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1");
+    let code = h.borrow_mut().parse(&"(5;6;7)@(2;0;9)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[7;5;-9223372036854775808]");
+    let code = h.borrow_mut().parse(&"(1;(2;3))~(1;(2;3))".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1");
+    let code = h.borrow_mut().parse(&"9$(1;2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#f[1;2]");
+}
+
+#[test]
+pub fn k_dyadic_arith() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"3!(7;-1)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2]");
+    let code = h.borrow_mut().parse(&"(1;5)&(3;2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2]");
+    let code = h.borrow_mut().parse(&"(1;5)|3".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[3;5]");
+    // int arithmetic wraps at the null and max edges instead of panicking
+    let code = h.borrow_mut().parse(&"0N-1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "9223372036854775807");
+    let code = h.borrow_mut().parse(&"9223372036854775807+1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "-9223372036854775808");
+    let code = h.borrow_mut().parse(&"0W*2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "-2");
+    let code = h.borrow_mut().parse(&"-1!0N".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "0");
+    let code = h.borrow_mut().parse(&"(0N)!-1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "-1");
+    let code = h.borrow_mut().parse(&"0N-1 2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[9223372036854775807;9223372036854775806]");
+}
+
+#[test]
+pub fn k_apply() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"{x}@1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1");
+    let code = h.borrow_mut().parse(&"f:{x+1};f@1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2");
+    let code = h.borrow_mut().parse(&"f@(1;2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[2;3]");
+    let code = h.borrow_mut().parse(&"{x+1}.(1)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2");
+    let code = h.borrow_mut().parse(&"g:{x*y};g . 2 3".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "6");
+    let code = h.borrow_mut().parse(&"{x,y}.(1 2;3)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2;3]");
+    let code = h.borrow_mut().parse(&"@[{{x}+x};1;{x}]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"type\"");
+}
+
+#[test]
+pub fn k_adverbs() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"+\\(1;2;3;4)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;3;6;10]");
+    let code = h.borrow_mut().parse(&"10+/(1;2;3)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "16");
+    let code = h.borrow_mut().parse(&"#'((1;2);(3;4;5))".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[2;3]");
+    let code = h.borrow_mut().parse(&"(1;2)+\\:(10;20)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[#i[11;21];#i[12;22]]");
+    let code = h.borrow_mut().parse(&"-':(1;3;6;10)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2;3;4]");
+    let code = h.borrow_mut().parse(&"*/1+!25".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "7034535277573963776");
+}
+
+#[test]
+pub fn k_adverbs_lambda() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"+/{x*y}[(1;3;4;5;6);(2;6;2;1;3)]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "51");
+    let code = h.borrow_mut().parse(&"{x*2}'(1;2;3)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[2;4;6]");
+    let code = h.borrow_mut().parse(&"{x+y}\\(1;2;3)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;3;6]");
+    let code = h.borrow_mut().parse(&"{x*2}/[3;1]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "8");
+    let code = h.borrow_mut().parse(&"{x*2}\\[3;1]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2;4;8]");
+    let code = h.borrow_mut().parse(&"{_ x%2}/64".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "0");
+    let code = h.borrow_mut().parse(&"({x,y}\\:[1 2;10 20];{x,y}/:[1 2;10 20])".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[#a[#i[1;10;20];#i[2;10;20]];#a[#i[1;2;10];#i[1;2;20]]]");
+    let code = h.borrow_mut().parse(&"@[{{x,y}/:x};1 2;{x}]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"rank\"");
+    let code = h.borrow_mut().parse(&"+/{x+1}'!100000".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "5000050000");
+    let code = h.borrow_mut().parse(&"{x,x}'\"abc\"".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[\"aa\";\"bb\";\"cc\"]");
+}
+
+#[test]
+pub fn k_promotion() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"1+5%2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3.5");
+    let code = h.borrow_mut().parse(&"(1;2;3)*1%2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#f[0.5;1;1.5]");
+    let code = h.borrow_mut().parse(&"7%2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3.5");
+    let code = h.borrow_mut().parse(&"(1;2)<3%2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;0]");
+    let code = h.borrow_mut().parse(&"(1;2)+(1;2)%(2;8)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#f[1.5;2.25]");
+    let code = h.borrow_mut().parse(&"(1;2;3)+(1;2)".to_string()).unwrap();
+    assert_eq!(format!("{:?}", h.borrow_mut().run(code, Context::Nil, None)),
+               "Err(EvalError { desc: \"length\", ast: \"Atom(Value(VecInt([1, 2, 3]))) Atom(Value(VecInt([1, 2])))\" })");
+}
+
+#[test]
+pub fn k_literals() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"1.05".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1.05");
+    let code = h.borrow_mut().parse(&"1e-3".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "0.001");
+    let code = h.borrow_mut().parse(&"-2+3".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1");
+    let code = h.borrow_mut().parse(&"x:5;x-2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3");
+    let code = h.borrow_mut().parse(&"1.5 -2 3e2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#f[1.5;-2;300]");
+    let code = h.borrow_mut().parse(&"1 2 3*-1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[-1;-2;-3]");
+    let code = h.borrow_mut().parse(&"(0N;0W;-0w)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[-9223372036854775808;9223372036854775807;-inf]");
+    // a sign is lexed with its literal, a minus before anything else is the verb
+    let code = h.borrow_mut().parse(&"-(2+3)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "-5");
+    let code = h.borrow_mut().parse(&"- 1 2 3".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[-1;-2;-3]");
+    let code = h.borrow_mut().parse(&"- -1.5 -2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#f[1.5;2]");
+    let code = h.borrow_mut().parse(&"-(1 2)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[-1;-2]");
+    let code = h.borrow_mut().parse(&"x:4;(x-1;x -1;2*-1)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[3;3;-2]");
+    let code = h.borrow_mut().parse(&"\"a-1\",\"-2\"".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"a-1-2\"");
+}
+
+#[test]
+pub fn k_table() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"t:([] a:1 2 3; b:1.5 2.5 3.5);t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "a b  \n-----\n1 1.5\n2 2.5\n3 3.5");
+    let code = h.borrow_mut().parse(&"t`b".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#f[1.5;2.5;3.5]");
+    let code = h.borrow_mut().parse(&"t[1]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "a| 2\nb| 2.5");
+    let code = h.borrow_mut().parse(&"([]a:1 2 3)`a".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2;3]");
+    let code = h.borrow_mut().parse(&"([]a:1 2 3;b:4 5 6)[2]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "a| 3\nb| 6");
+    let code = h.borrow_mut().parse(&"#t,(4;4.5)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "4");
+    let code = h.borrow_mut().parse(&"x:5 6;([] x; y:0)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "x y\n---\n5 0\n6 0");
+    let code = h.borrow_mut().parse(&"([] a:1 2; b:1 2 3)".to_string()).unwrap();
+    assert_eq!(format!("{:?}", h.borrow_mut().run(code, Context::Nil, None)),
+               "Err(EvalError { desc: \"length\", ast: \"VecInt([1, 2])\" })");
+}
+
+#[test]
+pub fn k_table_builtins() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    h.borrow_mut().define_primitives();
+
+    let code = h.borrow_mut().parse(&"t:([] a:1 2; b:3 4);count upsert[t;t]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "4");
+    let code = h.borrow_mut().parse(&"#cols t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2");
+    let code = h.borrow_mut().parse(&"upsert[`a`b!1 2;`b`c!3 4]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "a| 1\nb| 3\nc| 4");
+    let code = h.borrow_mut().parse(&"(@[{upsert[1;2]};0;{x}];@[{upsert[`a`b!1 2;3]};0;{x}])".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[\"upsert: type\";\"upsert: type\"]");
+}
+
+#[test]
+pub fn k_query() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"t:([] s:1 2 1 3; p:1 2 3 4; q:10 20 30 40.0);select p,r:p*q from t where p>1,p<4".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "p r \n----\n2 40\n3 90");
+    let code = h.borrow_mut().parse(&"exec p from t where s=1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;3]");
+    let code = h.borrow_mut().parse(&"update p:p*10 from t where s=1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "s p  q \n-------\n1 10 10\n2 2  20\n1 30 30\n3 4  40");
+    let code = h.borrow_mut().parse(&"delete from t where p>2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "s p q \n------\n1 1 10\n2 2 20");
+    let code = h.borrow_mut().parse(&"delete p from t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "s q \n----\n1 10\n2 20\n1 30\n3 40");
+    let code = h.borrow_mut().parse(&"delete q from t where s=1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "s p q  \n-------\n1 1 NaN\n2 2 20 \n1 3 NaN\n3 4 40 ");
+    let code = h.borrow_mut().parse(&"f:{select from t where p>x};#f 2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2");
+}
+
+#[test]
+pub fn k_query_group() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"t:([] s:1 2 1 3; p:1 2 3 4; q:10 20 30 40.0);select sum p, avg q, max p, min q by s from t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "s| p q  p1 q1\n-| ----------\n1| 4 20 3  10\n2| 2 20 2  20\n3| 4 40 4  40");
+    let code = h.borrow_mut().parse(&"select n:count i, first p, last q by s from t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "s| n p q \n-| ------\n1| 2 1 30\n2| 1 2 20\n3| 1 4 40");
+    let code = h.borrow_mut().parse(&"update tot:sum p by s from t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "s p q  tot\n----------\n1 1 10 4  \n2 2 20 2  \n1 3 30 4  \n3 4 40 4  ");
+    let code = h.borrow_mut().parse(&"select p by s from t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "s| p  \n-| ---\n1| 1 3\n2| ,2 \n3| ,4 ");
+    let code = h.borrow_mut().parse(&"exec sum p by s from t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1| 4\n2| 2\n3| 4");
+    let code = h.borrow_mut().parse(&"d:exec p by s from t where p>1;d 1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[3]");
+    // keys are values, 0n rows share a group
+    let code = h.borrow_mut().parse(&"u:([] f:0n 1 0n 2; p:1 2 3 4);exec sum p by f from u".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "NaN| 4\n1  | 2\n2  | 4");
+    let code = h.borrow_mut().parse(&"u:([] a:`x`y`x; b:1 1 1; p:1 2 3);exec sum p by a,b from u".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "(`x;1)| 4\n(`y;1)| 2");
+}
+
+#[test]
+pub fn k_dict() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"d:1 2 3!10 20 30;d".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1| 10\n2| 20\n3| 30");
+    let code = h.borrow_mut().parse(&"d[3 1]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[30;10]");
+    let code = h.borrow_mut().parse(&"d+3 4!1 1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1| 10\n2| 20\n3| 31\n4| 1");
+    let code = h.borrow_mut().parse(&"d*2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1| 20\n2| 40\n3| 60");
+    let code = h.borrow_mut().parse(&"!d".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[1;2;3]");
+    let code = h.borrow_mut().parse(&"((`a`b!1 2)`a;(`a`b!1 2)[`b])".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[1;2]");
+    let code = h.borrow_mut().parse(&"7!3".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3");
+    let code = h.borrow_mut().parse(&"1 2!1 2 3".to_string()).unwrap();
+    assert_eq!(format!("{:?}", h.borrow_mut().run(code, Context::Nil, None)),
+               "Err(EvalError { desc: \"length\", ast: \"VecInt([1, 2])\" })");
+}
+
+#[test]
+pub fn k_keyed_table() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    h.borrow_mut().define_primitives();
+
+    let code = h.borrow_mut().parse(&"kt:([k:1 2 3] v:10 20 30; w:1.5 2.5 3.5);kt".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "k| v  w  \n-| ------\n1| 10 1.5\n2| 20 2.5\n3| 30 3.5");
+    let code = h.borrow_mut().parse(&"value kt[2]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[20;2.5]");
+    let code = h.borrow_mut().parse(&"#cols kt".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3");
+    let code = h.borrow_mut().parse(&"t:0!kt;key 1!t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "k\n-\n1\n2\n3");
+    let code = h.borrow_mut().parse(&"exec v from kt where w>2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[20;30]");
+}
+
+#[test]
+pub fn k_symbols_strings() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    h.borrow_mut().define_primitives();
+
+    let code = h.borrow_mut().parse(&"s:`zeta`alpha`mid;s".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "`zeta`alpha`mid");
+    let code = h.borrow_mut().parse(&"s@<s".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "`alpha`mid`zeta");
+    let code = h.borrow_mut().parse(&"s=`alpha".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[0;1;0]");
+    let code = h.borrow_mut().parse(&"`zeta>`alpha".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1");
+    let code = h.borrow_mut().parse(&"x:\"ab\",\"cd\";x".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"abcd\"");
+    let code = h.borrow_mut().parse(&"(count x;2#x;x=\"abcd\")".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[4;\"ab\";#i[1;1;1;1]]");
+    let code = h.borrow_mut().parse(&"`$x".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "`abcd");
+    let code = h.borrow_mut().parse(&"y:20000#x;(count {x}'y;y?\"d\";y@1 2;y~y)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[20000;3;\"bc\";1]");
+    let code = h.borrow_mut().parse(&"(#\"h\u{e9}llo\";\"h\u{e9}llo\"@1 4)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[5;\"\u{e9}o\"]");
+    let code = h.borrow_mut().parse(&"(`a`c`b<`b;`b=`a`b`c;<`c`a`b;>`c`a`b)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[#i[1;0;0];#i[0;1;0];#i[1;2;0];#i[0;2;1]]");
+}
+
+#[test]
+pub fn k_temporal() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    h.borrow_mut().define_primitives();
+
+    let code = h.borrow_mut().parse(&"t:2016.10.17D09:31:12.5 2016.10.17D09:37;t+0D00:05".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2016.10.17D09:36:12.500000000 2016.10.17D09:42:00.000000000");
+    let code = h.borrow_mut().parse(&"((t@1)-t@0;2016.10.18-2016.10.17;2016.10.17+1;09:30:00.000+60000)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[0D00:05:47.500000000;1;2016.10.18;09:31:00.000]");
+    let code = h.borrow_mut().parse(&"(t<2016.10.17D09:35;2016.10.17=2016.10.17D00:00)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[#i[1;0];1]");
+    let code = h.borrow_mut().parse(&"(t.date;t.minute;t.hh;t.mm;t.year)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[2016.10.17 2016.10.17;#i[31;37];#i[9;9];#i[10;10];#i[2016;2016]]");
+    let code = h.borrow_mut().parse(&"((t@1).minute;(t+0D01:00).hh;2016.10.17D09:31.mm)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[37;#i[10;10];10]");
+    let code = h.borrow_mut().parse(&"xbar[0D00:05;t]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2016.10.17D09:30:00.000000000 2016.10.17D09:35:00.000000000");
+    let code = h.borrow_mut().parse(&"0D00:05 xbar t".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2016.10.17D09:30:00.000000000 2016.10.17D09:35:00.000000000");
+    let code = h.borrow_mut().parse(&"5 xbar 1 7 12".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[0;5;10]");
+    let code = h.borrow_mut().parse(&"q:([]time:t;v:1 2);select sum v by xbar[0D00:05;time] from q".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "time                         | v\n-----------------------------| -\n2016.10.17D09:30:00.000000000| 1\n2016.10.17D09:35:00.000000000| 2");
+    let code = h.borrow_mut().parse(&"(now[])>2016.10.17".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1");
+    let code = h.borrow_mut().parse(&"(0Np;-0D00:05)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[0Np;-0D00:05:00.000000000]");
+}
+
+#[test]
+pub fn k_builtin_errors() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    h.borrow_mut().define_primitives();
+
+    let code = h.borrow_mut().parse(&"snd[99;1]".to_string()).unwrap();
+    assert_eq!(format!("{:?}", h.borrow_mut().run(code, Context::Nil, None)),
+               "Err(EvalError { desc: \"snd: wrong publisher id 99\", ast: \"Vector([Atom(Value(Number(99))), Atom(Value(Number(1)))])\" })");
+    let code = h.borrow_mut().parse(&"rcv 7".to_string()).unwrap();
+    assert_eq!(format!("{:?}", h.borrow_mut().run(code, Context::Nil, None)),
+               "Err(EvalError { desc: \"rcv: wrong subscriber id 7\", ast: \"Atom(Value(Number(7)))\" })");
+    let code = h.borrow_mut().parse(&"pub[0]".to_string()).unwrap();
+    assert_eq!(format!("{:?}", h.borrow_mut().run(code, Context::Nil, None)),
+               "Err(EvalError { desc: \"pub: rank\", ast: \"Vector([Atom(Value(Number(0)))])\" })");
+    let code = h.borrow_mut().parse(&"key 1".to_string()).unwrap();
+    assert_eq!(format!("{:?}", h.borrow_mut().run(code, Context::Nil, None)),
+               "Err(EvalError { desc: \"key: not a dictionary\", ast: \"Atom(Value(Number(1)))\" })");
+    let code = h.borrow_mut().parse(&"1+2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3");
+}
+
+#[test]
+pub fn k_signal_trap() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    h.borrow_mut().define_primitives();
+
+    let code = h.borrow_mut().parse(&"'\"bad\"".to_string()).unwrap();
+    assert_eq!(format!("{:?}", h.borrow_mut().run(code, Context::Nil, None)),
+               "Err(EvalError { desc: \"bad\", ast: \"Atom(Value(Sequence(\\\"bad\\\")))\" })");
+    let code = h.borrow_mut().parse(&"@[{'\"boom\"};1;{\"caught: \",x}]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"caught: boom\"");
+    let code = h.borrow_mut().parse(&"(@[{x+1};1;0];.[{x+y};(1;2);0];.[{x+y};(1;`a);-1])".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[2;3;-1]");
+    let code = h.borrow_mut().parse(&"f:{'\"deep\"};g:{f x};@[g;1;{x}]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"deep\"");
+    let code = h.borrow_mut().parse(&"@[{@[{'\"inner\"};x;{'\"rethrow \",x}]};1;{x}]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"rethrow inner\"");
+    let code = h.borrow_mut().parse(&"@[snd;99;{x}]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"snd: rank\"");
+    let code = h.borrow_mut().parse(&"a:@[{'\"e\"};0;42];a+1".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "43");
+}
+
+#[test]
+pub fn k_error_positions() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    assert_eq!(format!("{:?}", h.borrow_mut().parse(&"99999999999999999999".to_string())),
+               "Err(ParseError { desc: \"integer overflow\", pos: 0 })");
+    assert_eq!(format!("{:?}", h.borrow_mut().parse(&"1+0x7fffffffffffffff1".to_string())),
+               "Err(ParseError { desc: \"integer overflow\", pos: 2 })");
+    assert_eq!(format!("{:?}", h.borrow_mut().parse(&"1+(2".to_string())),
+               "Err(ParseError { desc: \"unexpected end, expected \\\")\\\"\", pos: 4 })");
+    assert_eq!(format!("{:?}", h.borrow_mut().parse(&"1 2 3)".to_string())),
+               "Err(ParseError { desc: \"unexpected \\\")\\\"\", pos: 5 })");
+
+    let code = h.borrow_mut().parse(&"1+`a".to_string()).unwrap();
+    assert!(h.borrow_mut().run(code, Context::Nil, None).is_err());
+    assert_eq!(h.borrow().fault, Some(1));
+    let code = h.borrow_mut().parse(&"\"a\"*1+2".to_string()).unwrap();
+    assert!(h.borrow_mut().run(code, Context::Nil, None).is_err());
+    assert_eq!(h.borrow().fault, Some(3));
+    let code = h.borrow_mut().parse(&"x:5; x+foo".to_string()).unwrap();
+    assert!(h.borrow_mut().run(code, Context::Nil, None).is_err());
+    assert_eq!(h.borrow().fault, Some(6));
+    let code = h.borrow_mut().parse(&"@[{x+`a};1;0]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()), "0");
+    assert_eq!(h.borrow().fault, None);
+}
+
+#[test]
+pub fn k_kfmt() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+
+    let code = h.borrow_mut().parse(&"($1 2 3;$1.5 2;$1 2f;$`a`b;$,1;$!0;$0N -1 0W;$0n -0w)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[\"1 2 3\";\"1.5 2\";\"1 2f\";\"`a`b\";\",1\";\"!0\";\"0N -1 0W\";\"0n -0w\"]");
+    let code = h.borrow_mut().parse(&"$(1;\"a\\\"b\";`c;(2 3;,\"d\"))".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "\"(1;\\\"a\\\\\\\"b\\\";`c;(2 3;,\\\"d\\\"))\"");
+    let code = h.borrow_mut().parse(&"($0#`;$,`a;(0#`)~0#`a;,`a)".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[\"0#`\";\",`a\";1;`a]");
+
+    let roundtrip = |e: &str| {
+        let code = h.borrow_mut().parse(&e.to_string()).unwrap();
+        let x = format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap());
+        let code = h.borrow_mut().parse(&e.to_string()).unwrap();
+        let s = kfmt::fmt(h.borrow_mut().run(code, Context::Nil, None).unwrap());
+        let code = h.borrow_mut().parse(&s).unwrap();
+        let y = h.borrow_mut().run(code, Context::Nil, None).unwrap();
+        assert_eq!((format!("{}", y), kfmt::fmt(y)), (x, s), "{}", e);
+    };
+
+    // printed values parse back to the same value
+    roundtrip("1 2 3");
+    roundtrip("-1 2");
+    roundtrip("1.5 2");
+    roundtrip("1 2f");
+    roundtrip("2f");
+    roundtrip("0n 1");
+    roundtrip("`a`b");
+    roundtrip(",`a");
+    roundtrip("\"str\"");
+    roundtrip(",\"s\"");
+    roundtrip("\"a\"");
+    roundtrip("(1;\"ab\";`c)");
+    roundtrip("(1 2;3 4)");
+    roundtrip(",1 2");
+    roundtrip("`a`b!1 2");
+    roundtrip("(,1)!,2");
+    roundtrip("([]a:1 2;b:`x`y)");
+    roundtrip("([k:`a`b]v:1 2)");
+    roundtrip("2016.01.01 2016.01.02");
+    roundtrip("0D00:05:00");
+    roundtrip("`$\"a b\"");
+    roundtrip("1e300 2.5e-7");
+    roundtrip("`$(\"a b\";\"c\")");
+    roundtrip("`$,\"a b\"");
+    roundtrip("(`$\"a b\";`c)");
+    roundtrip("0#`");
+}
+
+#[test]
+pub fn k_gc() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    let code = h.borrow_mut().parse(&"f:{[a;b]a+b*2}; x:0; t:([]a:1 2); add:{[a]{[b]a+b}}[10]".to_string()).unwrap();
+    h.borrow_mut().run(code, Context::Nil, None).unwrap();
+    h.borrow_mut().gc(&[]);
+
+    // every line leaves garbage, the workspace stays the same size
+    let mut size = (0, 0);
+    for i in 0..200 {
+        let code = h.borrow_mut().parse(&"x:f[x;1]; y:{x*x}'[!10]; f[1;2]".to_string()).unwrap();
+        assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()), "5");
+        h.borrow_mut().gc(&[]);
+        let n = (unsafe { (*h.borrow().arena.asts.get()).len() }, h.borrow().env.len().1);
+        if i < 10 {
+            size = (size.0.max(n.0), size.1.max(n.1));
+        } else {
+            assert!(n.0 <= size.0 && n.1 <= size.1);
+        }
+    }
+    let code = h.borrow_mut().parse(&"x".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()), "400");
+    let code = h.borrow_mut().parse(&"y".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#a[#i[0;1;4;9;16;25;36;49;64;81]]");
+    let code = h.borrow_mut().parse(&"t`a".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()), "#i[1;2]");
+    let code = h.borrow_mut().parse(&"add[5]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()), "15");
+
+    // roots survive a collection
+    let code = h.borrow_mut().parse(&"f[x;3]".to_string()).unwrap();
+    h.borrow_mut().gc(&[code]);
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()), "406");
+}
+
+#[test]
+pub fn k_scope() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    let code = h.borrow_mut().parse(&"x:5; {x}[1]; x".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "5");
+    let code = h.borrow_mut().parse(&"f:{x}; f[1]; y:5; f[2]; y".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "5");
+    let code = h.borrow_mut().parse(&"c:0; {c:1}[0]; c".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "0");
+    let code = h.borrow_mut().parse(&"z:1; g:{[a] b:z; a+b}; g[2]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3");
+}
+
+#[test]
+pub fn k_compiled() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    let code = h.borrow_mut()
+        .parse(&"fac:{$[x=1;1;x*fac[x-1]]}; ack:{[x;y]$[0=x;1+y;$[0=y;ack[x-1;1];ack[x-1;ack[x;y-1]]]]}; n:10"
+            .to_string())
+        .unwrap();
+    h.borrow_mut().run(code, Context::Nil, None).unwrap();
+
+    // compiled calls leave no env nodes behind
+    let nodes = h.borrow().env.len().0;
+    let code = h.borrow_mut().parse(&"fac[20]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2432902008176640000");
+    let code = h.borrow_mut().parse(&"ack[2;3]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "9");
+    let code = h.borrow_mut().parse(&"{[a;b] c:a*b; c%2}[3;5]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "7.5");
+    let code = h.borrow_mut().parse(&"{x+y}[1.5;2]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3.5");
+    let code = h.borrow_mut().parse(&"{x+n}[5]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "15");
+    let code = h.borrow_mut().parse(&"{[a] a:a+1; a*a}[2]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "9");
+    // ints wrap as in the interpreter
+    let code = h.borrow_mut().parse(&"fac[25]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "7034535277573963776");
+    let code = h.borrow_mut().parse(&"{x-y}[0N;1]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "9223372036854775807");
+    assert_eq!(h.borrow().env.len().0, nodes);
+
+    // whatever the machine can't run is interpreted
+    let code = h.borrow_mut().parse(&"{x+1}[1 2]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[2;3]");
+    let code = h.borrow_mut().parse(&"{[a] $[a;`t;`f]}[1]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "`t");
+    let code = h.borrow_mut().parse(&"p:{x+y}[1;];p[2]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "3");
+    // a vector global deep in the recursion keeps the whole call out of the machine
+    let code = h.borrow_mut().parse(&"v:1 2;r:{$[x=0;v;1+r[x-1]]};r[40]".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[41;42]");
+    // b is local to the lambda, so it isn't there yet when b+a runs
+    let code = h.borrow_mut().parse(&"{[a] b+a; b:1}[1]".to_string()).unwrap();
+    match h.borrow_mut().run(code, Context::Nil, None) {
+        Err(e) => assert_eq!(e.message(), "Identifier not found"),
+        x => panic!("{:?}", x),
+    }
+
+    // preemption still yields in the middle of compiled code
+    h.borrow_mut().counter = PREEMPTION - 3;
+    let code = h.borrow_mut().parse(&"fac[20]".to_string()).unwrap();
+    assert_eq!(h.borrow_mut().run(code, Context::Nil, None).unwrap(),
+               &AST::Atom(Atom::Yield(Context::Nil)));
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2432902008176640000");
+}
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn int(&mut self) -> i64 {
+        match self.next() % 8 {
+            0 => i64::max_value() - (self.next() % 4) as i64,
+            1 => i64::min_value() + (self.next() % 4) as i64,
+            _ => (self.next() % 21) as i64 - 10,
+        }
+    }
+
+    fn float(&mut self) -> f64 {
+        match self.next() % 16 {
+            0 => ::std::f64::NAN,
+            1 => -0.0,
+            2 => ::std::f64::INFINITY,
+            _ => (self.next() % 2001) as f64 / 100.0 - 10.0,
+        }
+    }
+}
+
+#[test]
+pub fn k_simd() {
+    let verbs = [Verb::Plus, Verb::Times, Verb::Eq, Verb::NEq, Verb::Gt, Verb::Ge, Verb::Lt, Verb::Le];
+    let bits = |x: &[f64]| x.iter().map(|f| f.to_bits()).collect::<Vec<u64>>();
+    let mut g = Rng(0x2545F4914F6CDD1D);
+    for n in 0..67 {
+        let a = (0..n).map(|_| g.int()).collect::<Vec<i64>>();
+        let b = (0..n).map(|_| g.int()).collect::<Vec<i64>>();
+        let c = (0..n).map(|_| g.float()).collect::<Vec<f64>>();
+        let d = (0..n).map(|_| g.float()).collect::<Vec<f64>>();
+        let (i, f) = (g.int(), g.float());
+        for v in verbs.iter() {
+            let run = |k| {
+                (simd::ints(k, *v, Arg::Vec(&a), Arg::Vec(&b)),
+                 simd::ints(k, *v, Arg::Atom(i), Arg::Vec(&b)),
+                 simd::ints(k, *v, Arg::Vec(&a), Arg::Atom(i)),
+                 simd::floats(k, *v, Arg::Vec(&c), Arg::Vec(&d)),
+                 simd::floats(k, *v, Arg::Atom(f), Arg::Vec(&d)),
+                 simd::floats(k, *v, Arg::Vec(&c), Arg::Atom(f)))
+            };
+            let (s, x) = (run(Kernel::Scalar), run(simd::detect()));
+            assert_eq!((&s.0, &s.1, &s.2), (&x.0, &x.1, &x.2));
+            for (p, q) in vec![(&s.3, &x.3), (&s.4, &x.4), (&s.5, &x.5)] {
+                match (p, q) {
+                    (&Some(Value::VecFloat(ref p)), &Some(Value::VecFloat(ref q))) => assert_eq!(bits(p), bits(q)),
+                    _ => assert_eq!(p, q),
+                }
+            }
+        }
+        for v in [Verb::Plus, Verb::Min, Verb::Max].iter() {
+            assert_eq!(simd::fold_int(Kernel::Scalar, *v, &a), simd::fold_int(simd::detect(), *v, &a));
+            assert_eq!(simd::fold_float(Kernel::Scalar, *v, &c).map(|x| x.to_bits()),
+                       simd::fold_float(simd::detect(), *v, &c).map(|x| x.to_bits()));
+        }
+    }
+    assert_eq!(simd::ints(Kernel::Avx2, Verb::Plus, Arg::Vec(&[1, 2]), Arg::Vec(&[1, 2, 3])), None);
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    let code = h.borrow_mut().parse(&"1 2 3 4 5+10".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[11;12;13;14;15]");
+    let code = h.borrow_mut().parse(&"2*1 2 3 4 5".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[2;4;6;8;10]");
+    let code = h.borrow_mut().parse(&"1 2 3 4 5>3".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[0;0;0;1;1]");
+    let code = h.borrow_mut().parse(&"1.5 2.5 3.5 4.5 5.5=2.5".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "#i[0;1;0;0;0]");
+    let code = h.borrow_mut().parse(&"+/1 2 3 4 5 6 7".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "28");
+    let code = h.borrow_mut().parse(&"&/5 3 8 1 9 2".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1");
+    let code = h.borrow_mut().parse(&"|/1.5 3.5 2.5 0.5 9.5".to_string()).unwrap();
+    assert_eq!(format!("{}", h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "9.5");
+}
+
+fn star(n: usize) -> Vec<Channel> {
+    let mut channels: Vec<Channel> = Vec::new();
+    for i in 0..n {
+        let mut channel = Channel::new(i, 12);
+        for c in &mut channels {
+            c.subscribers.push(channel.publisher.subscribe());
+            channel.subscribers.push(c.publisher.subscribe());
+        }
+        channel.subscribers.push(channel.publisher.subscribe());
+        channels.push(channel);
+    }
+    channels
+}
+
+fn peach(cores: usize, code: &str) -> Result<String, String> {
+    // run code on core 0 of a star, turning the bus by hand until the shell is done
+    let mut scheds = star(cores).into_iter().map(Scheduler::with_channel2).collect::<Vec<Scheduler>>();
+    let all = into_raw(&mut scheds);
+    let s = into_raw(&mut from_raw(all)[0]);
+    let shell = from_raw(s).spawn(Job::Cps(CpsTask::new(from_raw(s).mem())),
+                                  Termination::Corecursive,
+                                  Some(code));
+    let t = into_raw(from_raw(s).tasks.get_mut(shell.0).expect("no shell"));
+    from_raw(t).0.exec(Some(code));
+    match from_raw(t).0.poll(Context::Nil, from_raw(s)) {
+        Poll::Yield(Context::Intercore(&Message::Peach(_))) => (),
+        x => return Err(format!("{:?}", x)),
+    }
+    for _ in 0..8 {
+        for sc in from_raw(all).iter_mut() {
+            sc.poll_bus();
+            sc.poll_tasks();
+        }
+    }
+    // every chunk ran as a task and gave its slot back
+    assert!(from_raw(all).iter().all(|sc| sc.chunks.is_empty()));
+    from_raw(t).0.exec(Some("r"));
+    match from_raw(t).0.poll(Context::Nil, from_raw(s)) {
+        Poll::End(Context::Node(x)) => Ok(format!("{}", x)),
+        x => Err(format!("{:?}", x)),
+    }
+}
+
+fn shell(cores: usize, lines: &[&str]) -> Result<String, String> {
+    // each line on core 0, every core turning its bus and tasks after it, then r
+    let mut scheds = star(cores).into_iter().map(Scheduler::with_channel2).collect::<Vec<Scheduler>>();
+    let all = into_raw(&mut scheds);
+    let s = into_raw(&mut from_raw(all)[0]);
+    let shell = from_raw(s).spawn(Job::Cps(CpsTask::new(from_raw(s).mem())), Termination::Corecursive, None);
+    let t = into_raw(from_raw(s).tasks.get_mut(shell.0).expect("no shell"));
+    for l in lines.iter() {
+        from_raw(t).0.exec(Some(l));
+        if let Poll::Err(e) = from_raw(t).0.poll(Context::Nil, from_raw(s)) {
+            return Err(format!("{:?}", e));
+        }
+        for _ in 0..8 {
+            for sc in from_raw(all).iter_mut() {
+                sc.poll_bus();
+                sc.poll_tasks();
+            }
+        }
+    }
+    from_raw(t).0.exec(Some("r"));
+    match from_raw(t).0.poll(Context::Nil, from_raw(s)) {
+        Poll::End(Context::Node(x)) => Ok(format!("{}", x)),
+        x => Err(format!("{:?}", x)),
+    }
+}
+
+#[test]
+pub fn k_peach() {
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    let source = |e: &str| {
+        let code = h.borrow_mut().parse(&e.to_string()).unwrap();
+        let f = match code {
+            &AST::Vector(ref v) => &v[0],
+            x => x,
+        };
+        kfmt::code(f, &h.borrow().arena)
+    };
+    assert_eq!(source("{x*2}"), Some("{[x] x*2}".to_string()));
+    assert_eq!(source("{[a;b] c:a+b; (c-1)*b}"), Some("{[a;b] c:a+b;(c-1)*b}".to_string()));
+    assert_eq!(source("{$[x>1;+/x;f[x;`a]]}"), Some("{[x] $[x>1;+/x;f[x;`a]]}".to_string()));
+    assert_eq!(source("{x,'(-1;y)}"), Some("{[x;y] x,'(-1;y)}".to_string()));
+    assert_eq!(peach(3, "r:peach[{x*x};1+!10]"), Ok("#i[1;4;9;16;25;36;49;64;81;100]".to_string()));
+    assert_eq!(peach(4, "r:peach[{x+1};1 2]"), Ok("#i[2;3]".to_string()));
+    assert_eq!(peach(1, "r:peach[{[a] a,a};`a`b`c]"), Ok("#a[`a`a;`b`b;`c`c]".to_string()));
+    assert_eq!(peach(3, "r:peach[{x*2.5};1.0 2.0 3.0 4.0 5.0]"), Ok("#f[2.5;5;7.5;10;12.5]".to_string()));
+    assert_eq!(peach(3, "r:@[{peach[{x+`a};x]};1 2 3;{\"caught: \",x}]"),
+               Ok("\"caught: Dyad plus not supported\"".to_string()));
+    assert!(peach(3, "r:peach[{x+`a};1 2 3]").is_err());
+    assert!(peach(3, "r:peach[1;1 2 3]").is_err());
+    assert_eq!(shell(3, &["f:{a:x;peach[{b:x;b*2};a]};r:f 1 2"]), Ok("#i[2;4]".to_string()));
+    assert_eq!(shell(3, &["r:@[{a:2;peach[{x*a};x]};1 2;{x}]"]),
+               Ok("\"peach: lambda captures local a\"".to_string()));
+}
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len() / 2).map(|i| u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap()).collect()
+}
+
+#[test]
+pub fn k_ipc() {
+    // what q's -8! gives for each value
+    let sym = |s: &str| symbol::intern(s).unwrap();
+    let both = |b: &str, v: Value| {
+        let b = hex(b);
+        assert_eq!(ipc::decode(&b).unwrap(), (ipc::ASYNC, v.clone()));
+        assert_eq!(ipc::encode(&v, ipc::ASYNC, false).unwrap(), b);
+    };
+    both("0100000011000000f90100000000000000", Value::Number(1));
+    both("0100000011000000f90000000000000080", Value::Number(i64::min_value()));
+    both("0100000011000000f70000000000000440", Value::Float(2.5));
+    both("010000000f000000f568656c6c6f00", Value::SymbolInt(sym("hello")));
+    both("010000000a000000f661", Value::Char('a'));
+    both("01000000100000000a00020000006869", Value::Sequence("hi".to_string()));
+    both("01000000120000000b000200000061006200", Value::VecSym(vec![sym("a"), sym("b")]));
+    both("010000001e00000007000200000001000000000000000200000000000000", Value::VecInt(vec![1, 2]));
+    both("010000000d000000f201000000", Value::Temporal(Temporal::Date, 1));
+    both("0100000011000000f40100000000000000", Value::Temporal(Temporal::Timestamp, 1));
+    both("010000000d000000ed10270000", Value::Temporal(Temporal::Time, 10000));
+    both("010000001a000000000002000000f90100000000000000f56100",
+         Value::List(vec![Value::Number(1), Value::SymbolInt(sym("a"))]));
+    both("0100000029000000630b00020000006100620007000200000001000000000000000200000000000000",
+         Value::Dict(Box::new(Dict::new(Value::VecSym(vec![sym("a"), sym("b")]), Value::VecInt(vec![1, 2])).unwrap())));
+    both("010000002f0000006200630b0001000000610000000100000007000200000001000000000000000200000000000000",
+         Value::Table(Table::new(vec!["a".to_string()], vec![Value::VecInt(vec![1, 2])]).unwrap()));
+    both("010000000a0000006500", Value::Nil);
+    // narrower q types widen on the way in, big-endian headers read too
+    assert_eq!(ipc::decode(&hex("010000000d000000fa01000000")).unwrap().1, Value::Number(1));
+    assert_eq!(ipc::decode(&hex("010000000a000000ff01")).unwrap().1, Value::Number(1));
+    assert_eq!(ipc::decode(&hex("010000000b000000fb0080")).unwrap().1, Value::Number(i64::min_value()));
+    assert_eq!(ipc::decode(&hex("0000000000000011f90000000000000001")).unwrap().1, Value::Number(1));
+    assert_eq!(ipc::decode(&hex("01000000160000000600020000000100000000000080")).unwrap().1,
+               Value::VecInt(vec![1, i64::min_value()]));
+    assert!(ipc::decode(&hex("010000000e000000807479706500")).is_err());
+    assert!(ipc::decode(&hex("0100000011000000f901")).is_err());
+
+    // big messages compress like q does and come back whole
+    let v = Value::VecInt((0..2000).map(|i| i % 7).collect());
+    let c = ipc::encode(&v, ipc::SYNC, true).unwrap();
+    let plain = ipc::encode(&v, ipc::SYNC, false).unwrap();
+    assert_eq!(c[2], 1);
+    assert!(c.len() < plain.len() / 4);
+    assert_eq!(ipc::decode(&c).unwrap(), (ipc::SYNC, v));
+    let mut g = Rng(88172645463325252);
+    let v = Value::VecFloat((0..400).map(|_| g.float()).collect());
+    let c = ipc::encode(&v, ipc::SYNC, true).unwrap();
+    let back = ipc::decode(&c).unwrap().1;
+    assert_eq!(ipc::encode(&back, ipc::SYNC, false).unwrap(), ipc::encode(&v, ipc::SYNC, false).unwrap());
+
+    let mut mem = Memory::new();
+    let h = handle::new(Interpreter::new(unsafe { UnsafeShared::new(&mut mem as *mut Memory) }).unwrap());
+    let code = h.borrow_mut().parse(&"-8!1".to_string()).unwrap();
+    assert_eq!(kfmt::fmt(h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "1 0 0 0 17 0 0 0 249 1 0 0 0 0 0 0 0");
+    let code = h.borrow_mut().parse(&"-9!-8!(1;`a;\"hi\";2.5)".to_string()).unwrap();
+    assert_eq!(kfmt::fmt(h.borrow_mut().run(code, Context::Nil, None).unwrap()), "(1;`a;\"hi\";2.5)");
+    let code = h.borrow_mut().parse(&"-9!-8!(`a`b!1 2)".to_string()).unwrap();
+    assert_eq!(kfmt::fmt(h.borrow_mut().run(code, Context::Nil, None).unwrap()), "`a`b!1 2");
+    let code = h.borrow_mut().parse(&"(-9!-8!([]a:1 2;b:`x`y))~([]a:1 2;b:`x`y)".to_string()).unwrap();
+    assert_eq!(kfmt::fmt(h.borrow_mut().run(code, Context::Nil, None).unwrap()), "1");
+    let code = h.borrow_mut().parse(&"-9!-8!2000.01.02D10:00:00.000000001".to_string()).unwrap();
+    assert_eq!(kfmt::fmt(h.borrow_mut().run(code, Context::Nil, None).unwrap()),
+               "2000.01.02D10:00:00.000000001");
+    let fails = |e: &str| {
+        let code = h.borrow_mut().parse(&e.to_string()).unwrap();
+        match h.borrow_mut().run(code, Context::Nil, None) {
+            Err(Error::EvalError { desc, .. }) => desc,
+            x => panic!("{:?}", x),
+        }
+    };
+    // lengths come off the wire: nothing is allocated for bytes that aren't there
+    assert_eq!(fails("-9!1 0 0 0 14 0 0 0 7 0 255 255 255 127"), "ipc: truncated");
+    assert_eq!(fails("-9!1 0 0 0 22 0 0 0 7 0 2 0 0 0 1 0 0 0 0 0 0 0"), "ipc: truncated");
+    assert_eq!(fails("-9!1 0 0 0 14 0 0 0 0 0 255 255 255 127"), "ipc: truncated");
+    assert_eq!(fails("-9!1 0 0 0 12 0 0 0"), "ipc: truncated");
+    assert_eq!(fails("-9!1 0 1 0 20 0 0 0 255 255 255 127 0 0 0 0 0 0 0 0"), "ipc: length");
+    assert_eq!(Message::from_u8(&ipc::encode(&Value::Sequence("1+2".to_string()), ipc::SYNC, false).unwrap()),
+               Message::Exec(0, "1+2".to_string()));
+    assert_eq!(Message::from_u8(&[1, 2, 3]), Message::Nop);
+}
+
+fn hopen(addr: &SocketAddr) -> net::TcpStream {
+    let c = net::TcpStream::connect(addr).unwrap();
+    c.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    c
+}
+
+fn roundtrip(scheds: &mut Vec<Scheduler>, c: &mut net::TcpStream, out: &[u8], want: usize) -> Vec<u8> {
+    // write, then turn the cores until want bytes (0 is a whole frame) are back
+    c.write_all(out).unwrap();
+    let mut got = Vec::new();
+    for _ in 0..50 {
+        for (i, s) in scheds.iter_mut().enumerate() {
+            if i == 0 {
+                s.io.poll();
+                s.poll_ipc();
+            }
+            s.poll_bus();
+            s.poll_tasks();
+        }
+        let mut b = [0u8; 4096];
+        if let Ok(n) = c.read(&mut b) {
+            got.extend_from_slice(&b[..n]);
+        }
+        let n = match (want, ipc::header(&got)) {
+            (0, Ok((_, _, n))) => n,
+            (0, Err(_)) => usize::max_value(),
+            (n, _) => n,
+        };
+        if got.len() >= n {
+            break;
+        }
+    }
+    got
+}
+
+fn ask(scheds: &mut Vec<Scheduler>, c: &mut net::TcpStream, v: Value) -> Result<Value, String> {
+    let b = roundtrip(scheds, c, &ipc::encode(&v, ipc::SYNC, false).unwrap(), 0);
+    ipc::decode(&b).map(|(m, v)| v).map_err(|e| e.message())
+}
+
+#[test]
+pub fn k_listen() {
+    let mut scheds = star(3).into_iter().map(Scheduler::with_channel2).collect::<Vec<Scheduler>>();
+    let addr = scheds[0].listen(&"127.0.0.1:0".parse().unwrap());
+    let s = |x: &str| Value::Sequence(x.to_string());
+    let mut c = hopen(&addr);
+    assert_eq!(roundtrip(&mut scheds, &mut c, b"user:pw\x03\x00", 1), vec![3]);
+    assert_eq!(ask(&mut scheds, &mut c, s("1+2")), Ok(Value::Number(3)));
+    assert_eq!(ask(&mut scheds, &mut c, s("fac:{$[x=1;1;x*fac[x-1]]};fac[10]")), Ok(Value::Number(3628800)));
+    assert_eq!(ask(&mut scheds, &mut c, s("a:`x`y!1 2.5;a`y")), Ok(Value::Float(2.5)));
+    assert_eq!(ask(&mut scheds, &mut c, s("r:peach[{x*x};1 2 3]")), Ok(Value::VecInt(vec![1, 4, 9])));
+    assert_eq!(ask(&mut scheds, &mut c, s("r")), Ok(Value::VecInt(vec![1, 4, 9])));
+    let fac = Value::SymbolInt(symbol::intern("fac").unwrap());
+    assert_eq!(ask(&mut scheds, &mut c, Value::List(vec![fac, Value::Number(5)])),
+               Ok(Value::Number(120)));
+    match ask(&mut scheds, &mut c, s("1+`a")) {
+        Err(e) => assert!(e.contains("Dyad plus not supported"), e),
+        x => panic!("{:?}", x),
+    }
+    assert!(ask(&mut scheds, &mut c, Value::Number(1)).is_err());
+    // async: evaluated, never answered, so the next frame back is the sync one
+    c.write_all(&ipc::encode(&s("b:5"), ipc::ASYNC, false).unwrap()).unwrap();
+    assert_eq!(ask(&mut scheds, &mut c, s("b")), Ok(Value::Number(5)));
+    // a second q has its own task, an old client without a capability byte gets 0
+    let mut d = hopen(&addr);
+    assert_eq!(roundtrip(&mut scheds, &mut d, b"anon\x00", 1), vec![0]);
+    assert_eq!(ask(&mut scheds, &mut d, s("b:7;b*2")), Ok(Value::Number(14)));
+    assert_eq!(ask(&mut scheds, &mut c, s("b")), Ok(Value::Number(5)));
+    assert_eq!(scheds[0].remotes.len(), 2);
+    // a query that runs into preemption is resumed and answered, the next one starts afresh
+    let t = scheds[0].remotes[0].task;
+    match scheds[0].tasks[t].0 {
+        Job::Cps(ref mut x) => x.interpreter.counter = PREEMPTION * (x.interpreter.counter / PREEMPTION + 1) - 3,
+    }
+    assert_eq!(ask(&mut scheds, &mut c, s("+/{x*2}'!10")), Ok(Value::Number(90)));
+    assert!(scheds[0].remotes[0].pending.is_none());
+    assert_eq!(ask(&mut scheds, &mut c, s("b+1")), Ok(Value::Number(6)));
+    // no handshake within bounds, or a frame that can't be, drops the peer
+    let closed = |c: &mut net::TcpStream| match c.read(&mut [0u8; 1]) {
+        Ok(0) => true,
+        Err(ref e) => e.kind() != std::io::ErrorKind::WouldBlock && e.kind() != std::io::ErrorKind::TimedOut,
+        Ok(_) => false,
+    };
+    let mut e = hopen(&addr);
+    assert_eq!(roundtrip(&mut scheds, &mut e, &[b'u'; 2048], 1), vec![]);
+    assert!(closed(&mut e));
+    let mut f = hopen(&addr);
+    assert_eq!(roundtrip(&mut scheds, &mut f, b"user:pw\x03\x00", 1), vec![3]);
+    assert_eq!(roundtrip(&mut scheds, &mut f, &[1, 1, 0, 0, 0, 0, 0, 64], 1), vec![]);
+    assert!(closed(&mut f));
+    assert_eq!(ask(&mut scheds, &mut c, s("b")), Ok(Value::Number(5)));
+}
+
+#[test]
+pub fn k_spawn() {
+    assert_eq!(shell(3, &["r:spawn[1;\"+/!10\"]"]), Ok("#i[1;0]".to_string()));
+    assert_eq!(shell(3, &["h:spawn[1;\"+/!10\"]", "r:wait h"]), Ok("45".to_string()));
+    assert_eq!(shell(3, &["h:spawn[2;\"a:1+2;a*2\"]", "r:(status h;wait h)"]), Ok("#a[`done;6]".to_string()));
+    assert_eq!(shell(1, &["h:spawn[0;\"2*3\"]", "r:(h;wait h)"]), Ok("#a[#i[0;1];6]".to_string()));
+    assert_eq!(shell(3, &["h:spawn[1;\"1+`a\"]", "r:(status h;@[{wait x};h;{\"caught: \",x}])"]),
+               Ok("#a[`failed;\"caught: Dyad plus not supported\"]".to_string()));
+    assert_eq!(shell(3, &["h:spawn[2;\"1+\"]", "r:status h"]), Ok("`failed".to_string()));
+    assert_eq!(shell(3, &["h:spawn[1;\"wait spawn[2;\\\"3*4\\\"]\"]", "r:wait h"]), Ok("12".to_string()));
+    assert_eq!(shell(2, &["r:@[{wait x};1 7;{x}]"]), Ok("\"wait: no task 7\"".to_string()));
+    assert_eq!(shell(2, &["r:@[{wait x};`a;{x}]"]), Ok("\"wait: not a task\"".to_string()));
+    // a collected task gives its slot to the next spawn
+    assert_eq!(shell(2, &["h:spawn[1;\"1\"]", "a:wait h", "g:spawn[1;\"2\"]", "r:(a;h~g;wait g;@[{wait x};g;{x}])"]),
+               Ok("#a[1;1;2;\"wait: no task 0\"]".to_string()));
+}
+
+#[test]
+pub fn k_at() {
+    assert_eq!(shell(3, &["r:at[2;\"+/!1000\"]"]), Ok("499500".to_string()));
+    assert_eq!(shell(3, &["r:1+at[1;\"2*3\"]"]), Ok("7".to_string()));
+    assert_eq!(shell(2, &["r:at[1;\"7\"]"]), Ok("7".to_string()));
+    assert_eq!(shell(1, &["d:at[0;\"`a`b!1 2\"];r:d`b"]), Ok("2".to_string()));
+    assert_eq!(shell(2, &["r:(at[1;\"1 2\"];at[1;\"`x\"])"]), Ok("#a[#i[1;2];`x]".to_string()));
+    assert_eq!(shell(3, &["r:@[{at[2;x]};\"1+`a\";{\"caught: \",x}]"]),
+               Ok("\"caught: Dyad plus not supported\"".to_string()));
+    assert_eq!(shell(2, &["r:at[1;\"1+at[0;\\\"2\\\"]\"]"]), Ok("3".to_string()));
+    assert_eq!(shell(2, &["r:@[{at[x;\"1\"]};`a;{x}]"]), Ok("\"at: type\"".to_string()));
+    assert!(shell(2, &["r:at[1;\"1+\"]"]).is_err());
+
+    // a reply only resumes the call it answers
+    let mut scheds = star(2).into_iter().map(Scheduler::with_channel2).collect::<Vec<Scheduler>>();
+    let s = into_raw(&mut scheds[0]);
+    let shell = from_raw(s).spawn(Job::Cps(CpsTask::new(from_raw(s).mem())), Termination::Corecursive, None);
+    let t = into_raw(from_raw(s).tasks.get_mut(shell.0).expect("no shell"));
+    from_raw(t).0.exec(Some("10*at[1;\"5\"]"));
+    match from_raw(t).0.poll(Context::Nil, from_raw(s)) {
+        Poll::Yield(Context::Intercore(&Message::Eval(ref e))) => assert_eq!((e.to, e.id, &e.txt[..]), (1, 1, "5")),
+        x => panic!("{:?}", x),
+    }
+    let ack = |id| {
+        Message::AckEval(AckEval {
+            from: 1,
+            to: 0,
+            task_id: shell.0,
+            id: id,
+            result: Ok("5".to_string()),
+        })
+    };
+    let (stale, fresh) = (ack(7), ack(1));
+    for c in vec![Context::Cont(shell.0, &stale), Context::Nil] {
+        match from_raw(t).0.poll(c, from_raw(s)) {
+            Poll::Yield(Context::Nil) => (),
+            x => panic!("{:?}", x),
+        }
+    }
+    match from_raw(t).0.poll(Context::Cont(shell.0, &fresh), from_raw(s)) {
+        Poll::End(Context::Node(x)) => assert_eq!(format!("{}", x), "50"),
+        x => panic!("{:?}", x),
+    }
+
+    // over there it is a task of its own: preempted, resumed, answered, then released
+    let mut scheds = star(2).into_iter().map(Scheduler::with_channel2).collect::<Vec<Scheduler>>();
+    let all = into_raw(&mut scheds);
+    let s = into_raw(&mut from_raw(all)[0]);
+    let shell = from_raw(s).spawn(Job::Cps(CpsTask::new(from_raw(s).mem())), Termination::Corecursive, None);
+    let t = into_raw(from_raw(s).tasks.get_mut(shell.0).expect("no shell"));
+    from_raw(t).0.exec(Some("r:at[1;\"+/{x*2}'!10\"]"));
+    from_raw(t).0.poll(Context::Nil, from_raw(s));
+    from_raw(all)[1].poll_bus();
+    let k = from_raw(all)[1].tasks.len() - 1;
+    match from_raw(all)[1].tasks[k].0 {
+        Job::Cps(ref mut x) => x.interpreter.counter = PREEMPTION * (x.interpreter.counter / PREEMPTION + 1) - 3,
+    }
+    from_raw(all)[1].poll_tasks();
+    assert!(from_raw(all)[1].calls.contains_key(&k));
+    for _ in 0..4 {
+        for sc in from_raw(all).iter_mut() {
+            sc.poll_bus();
+            sc.poll_tasks();
+        }
+    }
+    assert_eq!(from_raw(all)[1].free, vec![k]);
+    from_raw(t).0.exec(Some("r"));
+    match from_raw(t).0.poll(Context::Nil, from_raw(s)) {
+        Poll::End(Context::Node(x)) => assert_eq!(format!("{}", x), "90"),
+        x => panic!("{:?}", x),
+    }
+}
+
+#[test]
+pub fn k_queues() {
+    assert_eq!(TypeId::parse("j"), Some(TypeId::Int));
+    assert_eq!(TypeId::parse("F"), Some(TypeId::Vector(Box::new(TypeId::Float))));
+    assert_eq!(TypeId::parse("jfs"),
+               Some(TypeId::Record(vec![TypeId::Int, TypeId::Float, TypeId::Symbol])));
+    assert_eq!(TypeId::parse("jq"), None);
+    assert!(TypeId::parse("X").unwrap().fits(&Value::VecInt(vec![0, 255])));
+    assert!(!TypeId::parse("X").unwrap().fits(&Value::VecInt(vec![256])));
+    assert!(TypeId::parse("jj").unwrap().fits(&Value::VecInt(vec![1, 2])));
+    assert_eq!(shell(1, &["p:pub[0;8;\"F\"];s:sub[0;p];snd[p;1.5 2.5];r:rcv s"]),
+               Ok("#f[1.5;2.5]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"s\"];s:sub[0;p];snd[p;`a];snd[p;`b];r:(rcv s;rcv s)"]),
+               Ok("#a[`a;`b]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"jfs\"];s:sub[0;p];snd[p;(1;2.5;`x)];r:rcv s"]),
+               Ok("#a[1;2.5;`x]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"C\"];s:sub[0;p];snd[p;\"hello\"];r:rcv s"]),
+               Ok("\"hello\"".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"*\"];s:sub[0;p];snd[p;`a`b!1 2];d:rcv s;r:d`b"]), Ok("2".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"j\"];s:sub[0;p];r:(rcv[s;0];@[{snd[p;x]};1.5;{x}])"]),
+               Ok("#a[Nil;\"snd: 1.5 doesn't fit publisher 0\"]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"jfs\"];s:sub[0;p];r:@[{snd[p;x]};(1;2;`x);{x}]"]),
+               Ok("\"snd: (1;2;`x) doesn't fit publisher 0\"".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8];s:sub[0;p];snd[p;7];r:rcv s"]), Ok("7".to_string()));
+    assert_eq!(shell(1, &["r:@[{pub[0;8;x]};\"q\";{x}]"]), Ok("\"pub: unknown queue type\"".to_string()));
+}
+
+#[test]
+pub fn k_park() {
+    assert_eq!(shell(1, &["p:pub[0;8];s:sub[0;p];r:(rcv[s;0];rcv[s;0])"]), Ok("#a[Nil;Nil]".to_string()));
+    assert_eq!(shell(1, &["r:@[{rcv[x;-1]};0;{x}]"]), Ok("\"rcv: type\"".to_string()));
+    let mut scheds = star(1).into_iter().map(Scheduler::with_channel2).collect::<Vec<Scheduler>>();
+    let s = into_raw(&mut scheds[0]);
+    let shell = from_raw(s).spawn(Job::Cps(CpsTask::new(from_raw(s).mem())), Termination::Corecursive, None);
+    let t = into_raw(from_raw(s).tasks.get_mut(shell.0).expect("no shell"));
+    let turn = |n: usize| for _ in 0..n {
+        from_raw(s).poll_bus();
+        from_raw(s).poll_tasks();
+    };
+    let put = |v: i64| {
+        let p = &from_raw(s).queues.publishers()[0];
+        *p.next().expect("queue is full") = Value::Number(v);
+        p.commit();
+    };
+    let r = |e: &'static str| {
+        from_raw(t).0.exec(Some(e));
+        match from_raw(t).0.poll(Context::Nil, from_raw(s)) {
+            Poll::End(Context::Node(x)) => format!("{}", x),
+            x => format!("{:?}", x),
+        }
+    };
+
+    // rcv parks the shell until the item is there
+    from_raw(t).0.exec(Some("p:pub[0;8];s:sub[0;p];r:1+rcv s"));
+    from_raw(t).0.poll(Context::Nil, from_raw(s));
+    turn(8);
+    assert_eq!(from_raw(s).parked.len(), 1);
+    turn(8);
+    assert_eq!(from_raw(s).parked.len(), 1);
+    put(41);
+    turn(1);
+    assert_eq!(from_raw(s).parked.len(), 0);
+    assert_eq!(r("r"), "42");
+
+    // an item already there is taken right away
+    put(7);
+    assert_eq!(r("rcv[s;10]"), "7");
+
+    // the deadline resumes with nil
+    from_raw(t).0.exec(Some("r:(rcv[s;30];2)"));
+    from_raw(t).0.poll(Context::Nil, from_raw(s));
+    turn(8);
+    assert_eq!(from_raw(s).parked.len(), 1);
+    std::thread::sleep(Duration::from_millis(40));
+    turn(1);
+    assert_eq!(from_raw(s).parked.len(), 0);
+    assert_eq!(r("r"), "#a[Nil;2]");
+}
+
+#[test]
+pub fn k_batch() {
+    assert_eq!(shell(1, &["p:pub[0;8];s:sub[0;p];snd[p;1 2 3 4];r:rcvall s"]), Ok("#i[1;2;3;4]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8];s:sub[0;p];r:rcvall s"]), Ok("#i[]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"f\"];s:sub[0;p];snd[p;1.5 2.5];snd[p;3.5];r:rcvall s"]),
+               Ok("#f[1.5;2.5;3.5]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"J\"];s:sub[0;p];snd[p;1 2];snd[p;3 4];r:rcvall s"]),
+               Ok("#a[#i[1;2];#i[3;4]]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"*\"];s:sub[0;p];snd[p;(1;`a)];r:rcvall s"]),
+               Ok("#a[#a[1;`a]]".to_string()));
+    // six, then five more on an eight slot ring: the batch wraps
+    assert_eq!(shell(1, &["p:pub[0;8];s:sub[0;p];snd[p;!6];a:rcvall s;snd[p;10+!5];r:(a;rcvall s;rcvall s)"]),
+               Ok("#a[#i[0;1;2;3;4;5];#i[10;11;12;13;14];#i[]]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;2];s:sub[0;p];r:(@[{snd[p;x]};1 2 3;{x}];rcvall s)"]),
+               Ok("#a[\"snd: queue full\";#i[]]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;2];s:sub[0;p];snd[p;1 2];r:(@[{snd[p;x]};3;{x}];rcvall s)"]),
+               Ok("#a[\"snd: queue full\";#i[1;2]]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"f\"];s:sub[0;p];r:rcvall s"]), Ok("#f[]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"s\"];s:sub[0;p];r:count rcvall s"]), Ok("0".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8;\"jf\"];s:sub[0;p];r:rcvall s"]), Ok("#a[]".to_string()));
+    assert_eq!(shell(1, &["p:pub[0;8];r:@[{snd[p;x]};1.5 2;{x}]"]),
+               Ok("\"snd: 1.5 2 doesn't fit publisher 0\"".to_string()));
+    assert_eq!(shell(1, &["r:@[{rcvall x};`a;{x}]"]), Ok("\"rcvall: type\"".to_string()));
+}